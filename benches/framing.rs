@@ -0,0 +1,71 @@
+//! Reading a stream of v1 frames one call at a time versus through
+//! `FramedRead`. Run with `cargo bench --bench framing`.
+
+use bytes::BytesMut;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use dice_rpc::transport::framing::FrameCodec;
+use futures::StreamExt;
+use tokio_util::codec::{Encoder, FramedRead};
+
+const FRAMES: usize = 1_000;
+
+/// `FRAMES` frames of `size` bytes each, back to back
+fn stream(size: usize) -> Vec<u8> {
+    let payload = vec![b'x'; size];
+    let mut buf = BytesMut::new();
+    let mut codec = FrameCodec::new();
+    for _ in 0..FRAMES {
+        codec.encode(payload.as_slice(), &mut buf).unwrap();
+    }
+    buf.to_vec()
+}
+
+fn read_frames(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("read_frames");
+
+    for size in [64, 1024, 16 * 1024] {
+        let data = stream(size);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("read_frame", size), &data, |b, data| {
+            b.to_async(&rt).iter(|| async {
+                let mut reader = data.as_slice();
+                for _ in 0..FRAMES {
+                    FrameCodec::read_frame(&mut reader).await.unwrap();
+                }
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("read_frame_buf", size),
+            &data,
+            |b, data| {
+                b.to_async(&rt).iter(|| async {
+                    let mut reader = data.as_slice();
+                    let mut buf = BytesMut::new();
+                    for _ in 0..FRAMES {
+                        FrameCodec::read_frame_buf(&mut reader, &mut buf)
+                            .await
+                            .unwrap();
+                    }
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("FramedRead", size), &data, |b, data| {
+            b.to_async(&rt).iter(|| async {
+                let mut frames = FramedRead::new(data.as_slice(), FrameCodec::new());
+                while let Some(frame) = frames.next().await {
+                    frame.unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read_frames);
+criterion_main!(benches);
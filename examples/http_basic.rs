@@ -1,7 +1,7 @@
-/// Basic HTTP RPC server example
-/// 
-/// Run with:
-/// cargo run --example http_basic --features http
+//! Basic HTTP RPC server example
+//!
+//! Run with:
+//! cargo run --example http_basic --features http
 
 use dice_rpc::*;
 use std::sync::Arc;
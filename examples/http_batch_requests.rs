@@ -1,7 +1,7 @@
-/// HTTP server demonstrating batch request support
-/// 
-/// Run with:
-/// cargo run --example http_batch_requests --features http
+//! HTTP server demonstrating batch request support
+//!
+//! Run with:
+//! cargo run --example http_batch_requests --features http
 
 use dice_rpc::*;
 use std::sync::Arc;
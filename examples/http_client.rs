@@ -1,7 +1,7 @@
-/// HTTP client example
-/// 
-/// Run with:
-/// cargo run --example http_client --features http
+//! HTTP client example
+//!
+//! Run with:
+//! cargo run --example http_client --features http
 
 use serde_json::json;
 
@@ -1,7 +1,7 @@
-/// Full-featured HTTP server with all features
-/// 
-/// Run with:
-/// cargo run --example http_full_featured --features http
+//! Full-featured HTTP server with all features
+//!
+//! Run with:
+//! cargo run --example http_full_featured --features http
 
 use dice_rpc::*;
 use std::sync::Arc;
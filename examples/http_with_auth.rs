@@ -1,7 +1,7 @@
-/// Example: HTTP RPC Server with Authentication
-/// 
-/// Run with:
-/// cargo run --example http_server --features http
+//! Example: HTTP RPC Server with Authentication
+//!
+//! Run with:
+//! cargo run --example http_server --features http
 
 use dice_rpc::*;
 use std::sync::Arc;
@@ -1,7 +1,7 @@
-/// Example: Simple HTTP RPC Server (No Auth)
-/// 
-/// Run with:
-/// cargo run --example http_simple --feature http
+//! Example: Simple HTTP RPC Server (No Auth)
+//!
+//! Run with:
+//! cargo run --example http_simple --feature http
 
 use dice_rpc::*;
 use std::sync::Arc;
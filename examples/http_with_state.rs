@@ -1,7 +1,7 @@
-/// HTTP server with persistent state
-/// 
-/// Run with:
-/// cargo run --example http_with_state --features http
+//! HTTP server with persistent state
+//!
+//! Run with:
+//! cargo run --example http_with_state --features http
 
 use dice_rpc::*;
 use std::sync::Arc;
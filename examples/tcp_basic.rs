@@ -1,7 +1,7 @@
-/// Basic TCP server example
-/// 
-/// Run with:
-/// cargo run --example tcp_basic
+//! Basic TCP server example
+//!
+//! Run with:
+//! cargo run --example tcp_basic
 
 use dice_rpc::*;
 use std::sync::Arc;
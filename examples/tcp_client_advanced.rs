@@ -1,7 +1,7 @@
-/// Advanced TCP client with framing support
-/// 
-/// Run with:
-/// cargo run --example tcp_client_advanced --features tcp
+//! Advanced TCP client with framing support
+//!
+//! Run with:
+//! cargo run --example tcp_client_advanced --features tcp
 
 use dice_rpc::transport::FrameCodec;
 use serde_json::json;
@@ -1,7 +1,7 @@
-/// TCP server with length-prefixed framing
-/// 
-/// Run with:
-/// cargo run --example tcp_framed --features tcp
+//! TCP server with length-prefixed framing
+//!
+//! Run with:
+//! cargo run --example tcp_framed --features tcp
 
 use dice_rpc::*;
 use std::sync::Arc;
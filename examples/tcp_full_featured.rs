@@ -1,7 +1,7 @@
-/// Full-featured TCP server with all bells and whistles
-/// 
-/// Run with:
-/// cargo run --example tcp_full_featured --features tcp
+//! Full-featured TCP server with all bells and whistles
+//!
+//! Run with:
+//! cargo run --example tcp_full_featured --features tcp
 
 use dice_rpc::*;
 use std::sync::Arc;
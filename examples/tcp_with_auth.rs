@@ -1,7 +1,7 @@
-/// TCP server with authentication
-/// 
-/// Run with:
-/// cargo run --example tcp_with_auth --features tcp
+//! TCP server with authentication
+//!
+//! Run with:
+//! cargo run --example tcp_with_auth --features tcp
 
 use dice_rpc::*;
 use std::sync::Arc;
@@ -1,7 +1,7 @@
-/// TCP server with persistent state
-/// 
-/// Run with:
-/// cargo run --example tcp_with_state --features tcp
+//! TCP server with persistent state
+//!
+//! Run with:
+//! cargo run --example tcp_with_state --features tcp
 
 use dice_rpc::*;
 use std::sync::Arc;
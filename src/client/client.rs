@@ -47,3 +47,17 @@ pub async fn run_client(args: ClientArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Like `run_client`, against a framed server on the Unix socket at `path`
+#[cfg(all(feature = "tcp", unix))]
+pub async fn run_unix_client(path: &std::path::Path, args: ClientArgs) -> anyhow::Result<()> {
+    let req = json!({
+        "jsonrpc": "2.0",
+        "method": args.method,
+        "params": serde_json::from_str::<serde_json::Value>(&args.params)?,
+        "id": 1
+    });
+    if let Some(resp) = crate::transport::unix::call(path, &req).await? {
+        println!("Response: {}", resp);
+    }
+    Ok(())
+}
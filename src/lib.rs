@@ -1,3 +1,7 @@
+// Parse/validation helpers return the ready-to-send error `RpcResponse`
+// as their `Err`; it is only built on the failure path.
+#![allow(clippy::result_large_err)]
+
 // Core RPC functionality
 pub mod rpc;
 
@@ -29,8 +33,10 @@ pub fn no_http_warning() {
     println!("HTTP support disabled");
 }
 
-pub use rpc::{RpcServer, RpcRequest, RpcResponse, RpcErrorObj};
-pub use state::{StateStore, Transaction, TransactionStatus, Account};
+pub use rpc::{
+    RequestContext, RpcError, RpcErrorObj, RpcRequest, RpcResponse, RpcServer, TransportKind,
+};
+pub use state::{Account, StateEvent, StateStore, Transaction, TransactionStatus};
 pub use util::{BatchRequest, BatchResponse};
-pub use middleware::{AuthMiddleware, AuthStrategy, AuthenticatedServer};
+pub use middleware::{AuthMiddleware, AuthStrategy, AuthenticatedServer, Principal};
 pub use server::metrics::Metrics;
\ No newline at end of file
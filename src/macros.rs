@@ -1,16 +1,18 @@
 /// Macros for ergonomic RPC method registration
 /// 
 /// Usage:
-/// ```rust
+/// ```no_run
+/// # use dice_rpc::{OptionExt, rpc_handler};
+/// # use serde_json::json;
 /// rpc_handler!(my_method, params => {
 ///     let value = params["key"].as_str().ok_or_invalid_params()?;
 ///     Ok(json!({"result": value}))
 /// });
 /// 
-/// server.register_handler("my_method", my_method).await;
+/// # async fn example(server: dice_rpc::RpcServer) {
+/// server.register("my_method", my_method).await;
+/// # }
 /// ```
-/// 
-
 #[macro_export]
 macro_rules! rpc_handler {
     ($name:ident, $params:ident => $body:block) => {
@@ -18,7 +20,7 @@ macro_rules! rpc_handler {
             $params: serde_json::Value,
         ) -> std::pin::Pin<
             Box<
-                dyn std::future::Future<Output = Result<serde_json::Value, $crate::rpc::RpcErrorObj>>
+                dyn std::future::Future<Output = Result<serde_json::Value, $crate::rpc::RpcError>>
                     + Send,
             >,
         > {
@@ -30,12 +32,18 @@ macro_rules! rpc_handler {
 /// Macro to register multiple handlers at once
 /// 
 /// Usage:
-/// ```rust
+/// ```no_run
+/// # use dice_rpc::{register_handlers, rpc_handler};
+/// # rpc_handler!(ping_handler, _params => { Ok(serde_json::json!("pong")) });
+/// # rpc_handler!(get_balance_handler, _params => { Ok(serde_json::json!(0)) });
+/// # rpc_handler!(send_tx_handler, _params => { Ok(serde_json::json!(null)) });
+/// # async fn example(server: dice_rpc::RpcServer) {
 /// register_handlers!(server, {
 ///     "ping" => ping_handler,
 ///     "get_balance" => get_balance_handler,
 ///     "send_tx" => send_tx_handler,
 /// });
+/// # }
 /// ```
 #[macro_export]
 macro_rules! register_handlers {
@@ -48,28 +56,20 @@ macro_rules! register_handlers {
     };
 }
 
-/// Helper trait to convert Option to RpcErrorObj
+/// Helper trait to convert Option to RpcError
 #[allow(dead_code)]
 pub trait OptionExt<T> {
-    fn ok_or_invalid_params(self) -> Result<T, crate::rpc::RpcErrorObj>;
-    fn ok_or_rpc_error(self, code: i64, msg: impl Into<String>) -> Result<T, crate::rpc::RpcErrorObj>;
+    fn ok_or_invalid_params(self) -> Result<T, crate::rpc::RpcError>;
+    fn ok_or_rpc_error(self, code: i64, msg: impl Into<String>) -> Result<T, crate::rpc::RpcError>;
 }
 
 impl<T> OptionExt<T> for Option<T> {
-    fn ok_or_invalid_params(self) -> Result<T, crate::rpc::RpcErrorObj> {
-        self.ok_or(crate::rpc::RpcErrorObj {
-            code: crate::rpc::INVALID_PARAMS,
-            message: "Invalid parameters".to_string(),
-            data: None,
-        })
+    fn ok_or_invalid_params(self) -> Result<T, crate::rpc::RpcError> {
+        self.ok_or_else(|| crate::rpc::RpcError::invalid_params("Invalid parameters"))
     }
 
-    fn ok_or_rpc_error(self, code: i64, msg: impl Into<String>) -> Result<T, crate::rpc::RpcErrorObj> {
-        self.ok_or(crate::rpc::RpcErrorObj {
-            code,
-            message: msg.into(),
-            data: None,
-        })
+    fn ok_or_rpc_error(self, code: i64, msg: impl Into<String>) -> Result<T, crate::rpc::RpcError> {
+        self.ok_or_else(|| crate::rpc::RpcError::new(code, msg))
     }
 }
 
@@ -1,13 +1,7 @@
-mod client;
-mod macros;
-mod middleware;
-mod rpc;
-mod server;
-mod state;
-mod transport;
-mod util;
+use dice_rpc::{client, server, transport};
 
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -37,6 +31,17 @@ enum Mode {
         /// Enable authentication
         #[arg(long)]
         auth: bool,
+
+        /// Let clients authenticate once per connection with `rpc.auth`;
+        /// sessions expire after this many seconds (requires --auth)
+        #[arg(long, requires = "auth")]
+        session_ttl: Option<u64>,
+
+        #[command(flatten)]
+        limits: ConnectionArgs,
+
+        #[command(flatten)]
+        common: ServerArgs,
     },
 
     /// Run the HTTP RPC server
@@ -48,21 +53,303 @@ enum Mode {
         /// Enable authentication
         #[arg(long)]
         auth: bool,
+
+        /// Read the API key from this header (e.g. `authorization` for
+        /// `Bearer <key>`, or `x-api-key`) instead of from params
+        #[arg(long, requires = "auth")]
+        auth_header: Option<String>,
+
+        #[command(flatten)]
+        common: ServerArgs,
+    },
+
+    /// Run the framed RPC server on a Unix domain socket
+    #[cfg(all(feature = "tcp", unix))]
+    UnixServer {
+        /// Socket file; a stale one left by a dead server is replaced
+        #[arg(short, long, default_value = "dice_rpc.sock")]
+        path: PathBuf,
+
+        /// Permission bits for the socket file, in octal
+        #[arg(long, default_value = "660")]
+        mode: String,
+
+        /// Authenticate callers by the uid of the connecting process
+        #[arg(long)]
+        peer_auth: bool,
+
+        /// Grant scopes to a uid as UID=SCOPE[,SCOPE...]; repeat for several
+        #[arg(long = "uid-role", requires = "peer_auth")]
+        uid_roles: Vec<String>,
+
+        /// Refuse uids that have no --uid-role
+        #[arg(long, requires = "peer_auth")]
+        known_uids_only: bool,
+
+        /// Enforce the per-method scope policy in this JSON file
+        /// (requires --peer-auth)
+        #[arg(long, requires = "peer_auth")]
+        policy: Option<PathBuf>,
+    },
+
+    /// Serve requests on stdin and stdout, as a child process of a tool
+    /// (the way language servers run); logs go to stderr
+    StdioServer {
+        /// Message framing: `length` (4-byte prefix, as on framed TCP) or
+        /// `lsp` (`Content-Length:` headers)
+        #[arg(long, default_value = "length")]
+        framing: transport::StdioFraming,
+    },
+
+    /// Manage the API keys in a key store file. Safe to run while a server
+    /// uses the file; send the server SIGHUP to apply the change.
+    Keys {
+        /// Key store file, created if missing
+        #[arg(long, default_value = "keys.json")]
+        store: PathBuf,
+
+        #[command(subcommand)]
+        action: KeysAction,
     },
 
     /// Run a one-shot client request
     Client {
         #[command(flatten)]
         client: client::ClientArgs,
+
+        /// Call a framed server on this Unix socket instead of `--addr`
+        #[cfg(all(feature = "tcp", unix))]
+        #[arg(long)]
+        unix: Option<PathBuf>,
+    },
+}
+
+/// Connection limits for the framed TCP server
+#[cfg(feature = "tcp")]
+#[derive(clap::Args, Debug)]
+struct ConnectionArgs {
+    /// Seconds a frame may take to arrive once started (default 30)
+    #[arg(long)]
+    read_timeout: Option<u64>,
+
+    /// Wait as long as it takes for a started frame to arrive
+    #[arg(long, conflicts_with = "read_timeout")]
+    no_read_timeout: bool,
+
+    /// Close connections that send nothing for this many seconds
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    /// Close connections that stop reading responses for this many seconds
+    #[arg(long)]
+    write_timeout: Option<u64>,
+
+    /// Refuse connections beyond this many open at once
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Refuse connections beyond this many open at once from one IP
+    #[arg(long)]
+    max_connections_per_ip: Option<usize>,
+}
+
+#[cfg(feature = "tcp")]
+impl ConnectionArgs {
+    fn apply(
+        &self,
+        mut config: transport::tcp::TcpServerConfig,
+    ) -> transport::tcp::TcpServerConfig {
+        if let Some(secs) = self.read_timeout {
+            config = config.with_read_timeout(Duration::from_secs(secs));
+        }
+        if self.no_read_timeout {
+            config = config.without_read_timeout();
+        }
+        if let Some(secs) = self.idle_timeout {
+            config = config.with_idle_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.write_timeout {
+            config = config.with_write_timeout(Duration::from_secs(secs));
+        }
+        if let Some(limit) = self.max_connections {
+            config = config.with_max_connections(limit);
+        }
+        if let Some(limit) = self.max_connections_per_ip {
+            config = config.with_max_connections_per_ip(limit);
+        }
+        config
+    }
+}
+
+/// Options shared by the framed TCP and HTTP servers
+#[derive(clap::Args, Debug)]
+struct ServerArgs {
+    /// Enforce the per-method scope policy in this JSON file
+    /// (requires --auth)
+    #[arg(long, requires = "auth")]
+    policy: Option<PathBuf>,
+
+    /// Check API keys against the hashed key store in this file and
+    /// serve the `admin.keys.*` methods (requires --auth)
+    #[arg(long, requires = "auth")]
+    key_store: Option<PathBuf>,
+
+    /// With --auth and no key store, accept the publicly known demo keys
+    /// dev-key-123 (read) and prod-key-456 (read, write); never use this
+    /// outside local testing
+    #[arg(long, requires = "auth", conflicts_with_all = ["key_store", "client_ca"])]
+    insecure_demo_keys: bool,
+
+    /// Refuse peers and restrict methods by the CIDR lists in this JSON
+    /// file; send SIGHUP to reload it
+    #[arg(long)]
+    ip_rules: Option<PathBuf>,
+
+    /// Append a hash-chained record of every state-mutating call to this
+    /// JSON-lines file and serve `admin.audit.query`
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Serve TLS with the PEM certificate chain in this file; send SIGHUP
+    /// to reload it
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// With --auth, authenticate callers by TLS client certificates issued
+    /// by the CAs in this PEM file instead of by API keys
+    #[arg(long, requires_all = ["auth", "tls_cert"], conflicts_with = "key_store")]
+    client_ca: Option<PathBuf>,
+
+    /// Certificate name that becomes the principal: cn, uri (e.g. a SPIFFE
+    /// id) or dns
+    #[arg(long, default_value = "cn", requires = "client_ca")]
+    client_identity: String,
+
+    /// Grant scopes to certificate principals as PATTERN=SCOPE[,SCOPE...],
+    /// where `*` in the pattern matches anything; repeat for several
+    #[arg(long = "client-role", requires = "client_ca")]
+    client_roles: Vec<String>,
+
+    /// Allow each peer IP this many requests per second; failed logins
+    /// count too
+    #[arg(long)]
+    rate_limit: Option<u32>,
+
+    /// Allow each authenticated key this many requests per second
+    #[arg(long, requires = "auth")]
+    key_rate_limit: Option<u32>,
+
+    /// Requests allowed back to back under --rate-limit and
+    /// --key-rate-limit (default: the per-second rate)
+    #[arg(long)]
+    burst: Option<u32>,
+}
+
+impl ServerArgs {
+    fn rate_limiter(
+        &self,
+        metrics: &Arc<server::metrics::Metrics>,
+    ) -> Option<Arc<dice_rpc::middleware::RateLimiter>> {
+        use dice_rpc::middleware::{RateLimit, RateLimiter};
+
+        if self.rate_limit.is_none() && self.key_rate_limit.is_none() {
+            return None;
+        }
+        let limit = |n: u32| match self.burst {
+            Some(burst) => RateLimit::per_second(n).with_burst(burst),
+            None => RateLimit::per_second(n),
+        };
+        let mut limiter = RateLimiter::new().with_metrics(metrics.clone());
+        if let Some(n) = self.rate_limit {
+            limiter = limiter.per_ip(limit(n));
+        }
+        if let Some(n) = self.key_rate_limit {
+            limiter = limiter.per_key(limit(n));
+        }
+        Some(Arc::new(limiter))
+    }
+
+    fn tls(&self) -> anyhow::Result<Option<transport::TlsConfig>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(transport::TlsConfig::from_pem_files(cert, key)?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn client_certs(&self) -> anyhow::Result<Option<dice_rpc::middleware::ClientCertConfig>> {
+        use dice_rpc::middleware::{CertIdentity, ClientCertConfig};
+
+        let Some(path) = &self.client_ca else {
+            return Ok(None);
+        };
+        let identity = match self.client_identity.as_str() {
+            "cn" => CertIdentity::CommonName,
+            "uri" => CertIdentity::Uri,
+            "dns" => CertIdentity::Dns,
+            other => anyhow::bail!(
+                "unknown --client-identity {:?} (expected cn, uri or dns)",
+                other
+            ),
+        };
+        let mut certs = ClientCertConfig::from_ca_file(path)?.with_identity(identity);
+        for role in &self.client_roles {
+            let (pattern, scopes) = role
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("--client-role {:?} is not PATTERN=SCOPES", role))?;
+            certs = certs.with_roles(pattern, scopes.split(',').map(str::trim));
+        }
+        Ok(Some(certs))
+    }
+}
+
+/// `keys` subcommands
+#[derive(Subcommand, Debug)]
+enum KeysAction {
+    /// Generate a new key and print it once
+    Issue {
+        /// Who the key belongs to; requests run as this principal
+        label: String,
+
+        /// Scope (or role) granted to the key; repeat for several
+        #[arg(long = "scope")]
+        scopes: Vec<String>,
+
+        /// Lifetime in seconds (default: never expires)
+        #[arg(long)]
+        ttl: Option<u64>,
     },
+
+    /// Replace a key; the old one keeps working during the grace period
+    Rotate {
+        id: String,
+
+        /// Seconds the old key stays valid
+        #[arg(long, default_value_t = server::admin::DEFAULT_ROTATION_GRACE.as_secs())]
+        grace: u64,
+    },
+
+    /// Revoke a key immediately
+    Revoke { id: String },
+
+    /// List stored keys (metadata only)
+    List,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize logging
-    server::metrics::init_logging();
-
     let opts = Opts::parse();
+
+    // Initialize logging, keeping stdout free when it carries the protocol
+    if matches!(opts.cmd, Mode::StdioServer { .. }) {
+        server::metrics::init_stderr_logging();
+    } else {
+        server::metrics::init_logging();
+    }
+
     match opts.cmd {
         Mode::Server { addr } => {
             // Basic TCP server (no metrics, no auth)
@@ -71,28 +358,295 @@ async fn main() -> anyhow::Result<()> {
         }
 
         #[cfg(feature = "tcp")]
-        Mode::TcpServer { addr, auth } => {
-            run_tcp_server(&addr, auth).await?;
+        Mode::TcpServer {
+            addr,
+            auth,
+            session_ttl,
+            limits,
+            common,
+        } => {
+            let session_ttl = session_ttl.map(Duration::from_secs);
+            run_tcp_server(&addr, auth, session_ttl, limits, common).await?;
         }
 
         #[cfg(feature = "http")]
-        Mode::HttpServer { addr, auth } => {
-            run_http_server(&addr, auth).await?;
+        Mode::HttpServer {
+            addr,
+            auth,
+            auth_header,
+            common,
+        } => {
+            run_http_server(&addr, auth, auth_header, common).await?;
         }
 
-        Mode::Client { client } => {
+        #[cfg(all(feature = "tcp", unix))]
+        Mode::UnixServer {
+            path,
+            mode,
+            peer_auth,
+            uid_roles,
+            known_uids_only,
+            policy,
+        } => {
+            let mode = u32::from_str_radix(&mode, 8)
+                .map_err(|_| anyhow::anyhow!("--mode {:?} is not octal", mode))?;
+            let peer_auth = if peer_auth {
+                Some(peer_cred_config(&uid_roles, known_uids_only)?)
+            } else {
+                None
+            };
+            run_unix_server(path, mode, peer_auth, policy).await?;
+        }
+
+        Mode::StdioServer { framing } => {
+            run_stdio_server(framing).await?;
+        }
+
+        Mode::Keys { store, action } => {
+            run_keys(store, action).await?;
+        }
+
+        #[cfg(all(feature = "tcp", unix))]
+        Mode::Client {
+            client,
+            unix: Some(path),
+        } => {
+            client::run_unix_client(&path, client).await?;
+        }
+
+        Mode::Client { client, .. } => {
             client::run_client(client).await?;
         }
     }
     Ok(())
 }
 
+/// Back `auth` with the key store given by `--key-store` and serve
+/// `admin.keys.*`, or with the demo keys under `--insecure-demo-keys`
+async fn setup_keys(
+    auth: dice_rpc::middleware::AuthMiddleware,
+    server: &dice_rpc::rpc::RpcServer,
+    common: &ServerArgs,
+) -> anyhow::Result<Arc<dice_rpc::middleware::AuthMiddleware>> {
+    use dice_rpc::middleware::KeyStore;
+
+    if common.insecure_demo_keys {
+        auth.add_key_with_scopes("dev-key-123", "dev", ["read"])
+            .await;
+        auth.add_key_with_scopes("prod-key-456", "prod", ["read", "write"])
+            .await;
+        tracing::warn!(
+            "Accepting the insecure demo keys dev-key-123 (read) and prod-key-456 (read, write); \
+             anyone can use them"
+        );
+        return Ok(Arc::new(auth));
+    }
+    let Some(path) = &common.key_store else {
+        anyhow::bail!("--auth needs --key-store, --client-ca or --insecure-demo-keys");
+    };
+
+    let keys = Arc::new(KeyStore::open(path)?);
+    let count = keys.list().await.len();
+    println!(
+        "Authentication enabled. {} key(s) in {}",
+        count,
+        path.display()
+    );
+    if count == 0 {
+        println!(
+            "Issue one with: keys --store {} issue <label> --scope admin",
+            path.display()
+        );
+    }
+    server::admin::register_key_admin_handlers(server, keys.clone()).await;
+
+    // Save last_used times now and then; changes are saved as they happen
+    let flushed = keys.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            if let Err(e) = flushed.flush().await {
+                tracing::warn!("Failed to save key usage: {}", e);
+            }
+        }
+    });
+    Ok(Arc::new(auth.with_key_store(keys)))
+}
+
+/// The store behind `auth` when it is backed by a file
+fn stored_keys(
+    auth: &dice_rpc::middleware::AuthMiddleware,
+) -> Option<Arc<dice_rpc::middleware::KeyStore>> {
+    let keys = auth.key_store();
+    keys.path().is_some().then(|| keys.clone())
+}
+
+/// Authenticate by client certificate when `--client-ca` is given, and
+/// otherwise with `api_keys` backed by the key store (or demo keys)
+async fn setup_auth(
+    api_keys: dice_rpc::middleware::AuthMiddleware,
+    server: &dice_rpc::rpc::RpcServer,
+    common: &ServerArgs,
+) -> anyhow::Result<Arc<dice_rpc::middleware::AuthMiddleware>> {
+    use dice_rpc::middleware::{AuthMiddleware, AuthStrategy};
+
+    let Some(certs) = common.client_certs()? else {
+        return setup_keys(api_keys, server, common).await;
+    };
+    if let Some(path) = &common.client_ca {
+        println!(
+            "Client certificate authentication enabled. Trusted CAs in {}",
+            path.display()
+        );
+    }
+    Ok(Arc::new(AuthMiddleware::new(AuthStrategy::ClientCert(
+        certs,
+    ))))
+}
+
+/// Audit the server's mutating methods to the log at `path` and serve
+/// `admin.audit.query`
+async fn setup_audit(server: &dice_rpc::rpc::RpcServer, path: &PathBuf) -> anyhow::Result<()> {
+    let audit = Arc::new(dice_rpc::middleware::AuditLog::open(path)?);
+    server.add_middleware(audit.clone()).await;
+    server::admin::register_audit_admin_handlers(server, audit).await;
+    Ok(())
+}
+
+/// Reload the IP rules, TLS certificates and key store whenever the
+/// process gets SIGHUP; files that fail to load leave the previous ones in
+/// force
+fn reload_on_hangup(
+    filter: Option<Arc<dice_rpc::middleware::IpFilter>>,
+    tls: Option<transport::TlsConfig>,
+    keys: Option<Arc<dice_rpc::middleware::KeyStore>>,
+) -> anyhow::Result<()> {
+    if filter.is_none() && tls.is_none() && keys.is_none() {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if let Some(filter) = &filter {
+                    match filter.reload() {
+                        Ok(()) => tracing::info!("Reloaded IP rules"),
+                        Err(e) => tracing::warn!("Keeping previous IP rules: {}", e),
+                    }
+                }
+                if let Some(tls) = &tls {
+                    match tls.reload() {
+                        Ok(()) => tracing::info!("Reloaded TLS certificates"),
+                        Err(e) => tracing::warn!("Keeping previous TLS certificates: {}", e),
+                    }
+                }
+                if let Some(keys) = &keys {
+                    match keys.reload().await {
+                        Ok(()) => tracing::info!("Reloaded key store"),
+                        Err(e) => tracing::warn!("Keeping previous keys: {}", e),
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Log a summary of `metrics` every 30 seconds
+fn report_metrics(metrics: Arc<server::metrics::Metrics>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            let snapshot = metrics.snapshot().await;
+            tracing::info!("Metrics Report");
+            tracing::info!("Total Requests: {}", snapshot.total_requests);
+            tracing::info!("Successful: {}", snapshot.total_success);
+            tracing::info!("Errors: {}", snapshot.total_errors);
+            tracing::info!("Avg Duration: {}μs", snapshot.avg_duration_us);
+            tracing::info!("Method Counts: {:?}", snapshot.method_counts);
+        }
+    });
+}
+
+/// Parse `--uid-role UID=SCOPES` entries
+#[cfg(all(feature = "tcp", unix))]
+fn peer_cred_config(
+    uid_roles: &[String],
+    known_uids_only: bool,
+) -> anyhow::Result<dice_rpc::middleware::PeerCredConfig> {
+    let mut creds = dice_rpc::middleware::PeerCredConfig::new();
+    for role in uid_roles {
+        let (uid, scopes) = role
+            .split_once('=')
+            .and_then(|(uid, scopes)| Some((uid.trim().parse::<u32>().ok()?, scopes)))
+            .ok_or_else(|| anyhow::anyhow!("--uid-role {:?} is not UID=SCOPES", role))?;
+        creds = creds.with_roles(uid, scopes.split(',').map(str::trim));
+    }
+    if known_uids_only {
+        creds = creds.known_uids_only();
+    }
+    Ok(creds)
+}
+
+/// Run a `keys` subcommand against the store file
+async fn run_keys(store: PathBuf, action: KeysAction) -> anyhow::Result<()> {
+    use dice_rpc::middleware::KeyStore;
+
+    let keys = KeyStore::open(&store)?;
+    match action {
+        KeysAction::Issue { label, scopes, ttl } => {
+            let issued = keys
+                .issue(label, scopes, ttl.map(Duration::from_secs))
+                .await?;
+            println!("Issued {} for {}", issued.info.id, issued.info.principal);
+            println!("Key (shown once): {}", issued.key);
+        }
+        KeysAction::Rotate { id, grace } => {
+            let issued = keys
+                .rotate(&id, Duration::from_secs(grace))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no active key with id {}", id))?;
+            println!(
+                "Rotated {} -> {}; the old key works for {}s",
+                id, issued.info.id, grace
+            );
+            println!("Key (shown once): {}", issued.key);
+        }
+        KeysAction::Revoke { id } => {
+            if !keys.revoke(&id).await? {
+                anyhow::bail!("no key with id {}", id);
+            }
+            println!("Revoked {}", id);
+        }
+        KeysAction::List => {
+            println!("{}", serde_json::to_string_pretty(&keys.list().await)?);
+            return Ok(());
+        }
+    }
+    println!(
+        "Servers using {} apply this on SIGHUP or their next key change",
+        store.display()
+    );
+    Ok(())
+}
+
 #[cfg(feature = "tcp")]
-async fn run_tcp_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
-    use crate::middleware::{AuthMiddleware, AuthStrategy};
-    use crate::rpc::RpcServer;
-    use crate::state::StateStore;
-    use crate::transport::tcp::TcpServerConfig;
+async fn run_tcp_server(
+    addr: &str,
+    enable_auth: bool,
+    session_ttl: Option<Duration>,
+    limits: ConnectionArgs,
+    common: ServerArgs,
+) -> anyhow::Result<()> {
+    use dice_rpc::middleware::{AccessPolicy, AuthMiddleware, AuthStrategy, IpFilter};
+    use dice_rpc::rpc::RpcServer;
+    use dice_rpc::state::StateStore;
+    use dice_rpc::transport::tcp::TcpServerConfig;
 
     // Create components
     let server = Arc::new(RpcServer::new());
@@ -106,33 +660,48 @@ async fn run_tcp_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
 
     // Register stateful handlers
     server::handlers::register_stateful_handlers(&server, state.clone()).await;
+    if let Some(path) = &common.audit_log {
+        setup_audit(&server, path).await?;
+    }
 
     // Spawn metrics reporter
-    let metrics_clone = metrics.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(30)).await;
-            let snapshot = metrics_clone.snapshot().await;
-            tracing::info!("Metrics Report");
-            tracing::info!("Total Requests: {}", snapshot.total_requests);
-            tracing::info!("Successful: {}", snapshot.total_success);
-            tracing::info!("Errors: {}", snapshot.total_errors);
-            tracing::info!("Avg Duration: {}μs", snapshot.avg_duration_us);
-            tracing::info!("Method Counts: {:?}", snapshot.method_counts);
-        }
-    });
+    report_metrics(metrics.clone());
 
     // Configure TCP server
-    let mut config = TcpServerConfig::new(addr, server).with_metrics(metrics);
+    let limiter = common.rate_limiter(&metrics);
+    let mut config = limits.apply(TcpServerConfig::new(addr, server.clone()).with_metrics(metrics));
+    if let Some(limiter) = limiter {
+        config = config.with_rate_limit(limiter);
+    }
 
     // Optionally enable authentication
+    let mut keys = None;
     if enable_auth {
-        let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
-        auth.add_key("dev-key-123").await;
-        auth.add_key("prod-key-456").await;
-        println!("Authentication enabled. Valid keys: dev-key-123, prod-key-456");
+        let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams);
+        let auth = setup_auth(auth, &server, &common).await?;
+        keys = stored_keys(&auth);
         config = config.with_auth(auth);
+        if let Some(ttl) = session_ttl {
+            config = config.with_sessions(ttl);
+        }
+    }
+    if let Some(path) = &common.policy {
+        config = config.with_middleware(AccessPolicy::from_file(path)?);
+    }
+    let ip_filter = common
+        .ip_rules
+        .as_ref()
+        .map(IpFilter::open)
+        .transpose()?
+        .map(Arc::new);
+    if let Some(filter) = &ip_filter {
+        config = config.with_ip_filter(filter.clone());
     }
+    let tls = common.tls()?;
+    if let Some(tls) = &tls {
+        config = config.with_tls(tls.clone());
+    }
+    reload_on_hangup(ip_filter, tls, keys)?;
 
     server::metrics::log_startup(addr, "TCP (Framed)");
     println!();
@@ -143,6 +712,27 @@ async fn run_tcp_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
     if enable_auth {
         println!("Authentication");
     }
+    if let Some(ttl) = session_ttl {
+        println!("Connection sessions (rpc.auth, {}s)", ttl.as_secs());
+    }
+    if let Some(path) = &common.policy {
+        println!("Access policy ({})", path.display());
+    }
+    if let Some(path) = &common.ip_rules {
+        println!("IP rules ({}, reload with SIGHUP)", path.display());
+    }
+    if let Some(n) = common.rate_limit {
+        println!("Rate limit ({}/s per IP)", n);
+    }
+    if let Some(n) = common.key_rate_limit {
+        println!("Rate limit ({}/s per key)", n);
+    }
+    if let Some(path) = &common.audit_log {
+        println!("Audit log ({})", path.display());
+    }
+    if let Some(path) = &common.tls_cert {
+        println!("TLS ({}, reload with SIGHUP)", path.display());
+    }
     println!();
 
     // Run server
@@ -152,12 +742,85 @@ async fn run_tcp_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(all(feature = "tcp", unix))]
+async fn run_unix_server(
+    path: PathBuf,
+    mode: u32,
+    peer_auth: Option<dice_rpc::middleware::PeerCredConfig>,
+    policy: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    use dice_rpc::middleware::{AccessPolicy, AuthMiddleware, AuthStrategy};
+    use dice_rpc::rpc::RpcServer;
+    use dice_rpc::state::StateStore;
+    use dice_rpc::transport::UnixServerConfig;
+
+    let server = Arc::new(RpcServer::new());
+    let state = Arc::new(StateStore::new());
+    state.set_balance("0xAlice", 100000).await;
+    state.set_balance("0xBob", 50000).await;
+    state.set_balance("0xCharlie", 75000).await;
+    server::handlers::register_stateful_handlers(&server, state.clone()).await;
+
+    let metrics = Arc::new(server::metrics::Metrics::new());
+    report_metrics(metrics.clone());
+
+    let mut config = UnixServerConfig::new(&path, server)
+        .with_mode(mode)
+        .with_metrics(metrics);
+    if let Some(creds) = peer_auth {
+        config = config.with_auth(Arc::new(AuthMiddleware::new(AuthStrategy::PeerCred(creds))));
+    }
+    if let Some(path) = &policy {
+        config = config.with_middleware(AccessPolicy::from_file(path)?);
+    }
+
+    let auth = if config.auth.is_some() {
+        ", peer uid auth"
+    } else {
+        ""
+    };
+    println!(
+        "Starting framed Unix socket server on {} (mode {:o}{})",
+        path.display(),
+        mode,
+        auth
+    );
+    println!(
+        "Call it with: client --unix {} --method ping",
+        path.display()
+    );
+    transport::run_unix(config).await?;
+
+    server::metrics::log_shutdown();
+    Ok(())
+}
+
+async fn run_stdio_server(framing: transport::StdioFraming) -> anyhow::Result<()> {
+    use dice_rpc::rpc::RpcServer;
+    use dice_rpc::state::StateStore;
+
+    let server = Arc::new(RpcServer::new());
+    let state = Arc::new(StateStore::new());
+    state.set_balance("0xAlice", 100000).await;
+    state.set_balance("0xBob", 50000).await;
+    state.set_balance("0xCharlie", 75000).await;
+    server::handlers::register_stateful_handlers(&server, state).await;
+
+    let config = transport::StdioServerConfig::new(server).with_framing(framing);
+    transport::run_stdio(config).await
+}
+
 #[cfg(feature = "http")]
-async fn run_http_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
-    use crate::middleware::{AuthMiddleware, AuthStrategy};
-    use crate::rpc::RpcServer;
-    use crate::state::StateStore;
-    use crate::transport::HttpTransport;
+async fn run_http_server(
+    addr: &str,
+    enable_auth: bool,
+    auth_header: Option<String>,
+    common: ServerArgs,
+) -> anyhow::Result<()> {
+    use dice_rpc::middleware::{AccessPolicy, AuthMiddleware, AuthStrategy, IpFilter};
+    use dice_rpc::rpc::RpcServer;
+    use dice_rpc::state::StateStore;
+    use dice_rpc::transport::HttpTransport;
 
     // Create components
     let server = Arc::new(RpcServer::new());
@@ -171,33 +834,48 @@ async fn run_http_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
 
     // Register stateful handlers
     server::handlers::register_stateful_handlers(&server, state.clone()).await;
+    if let Some(path) = &common.audit_log {
+        setup_audit(&server, path).await?;
+    }
 
     // Spawn metrics reporter
-    let metrics_clone = metrics.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(30)).await;
-            let snapshot = metrics_clone.snapshot().await;
-            tracing::info!("Metrics Report");
-            tracing::info!("Total Requests: {}", snapshot.total_requests);
-            tracing::info!("Successful: {}", snapshot.total_success);
-            tracing::info!("Errors: {}", snapshot.total_errors);
-            tracing::info!("Avg Duration: {}μs", snapshot.avg_duration_us);
-            tracing::info!("Method Counts: {:?}", snapshot.method_counts);
-        }
-    });
+    report_metrics(metrics.clone());
 
     // Create HTTP transport with metrics
-    let mut http = HttpTransport::new(server).with_metrics(metrics);
+    let limiter = common.rate_limiter(&metrics);
+    let mut http = HttpTransport::new(server.clone()).with_metrics(metrics);
+    if let Some(limiter) = limiter {
+        http = http.with_rate_limit(limiter);
+    }
 
     // Optionally enable authentication
+    let mut keys = None;
     if enable_auth {
-        let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
-        auth.add_key("dev-key-123").await;
-        auth.add_key("prod-key-456").await;
-        println!("Authentication enabled. Valid keys: dev-key-123, prod-key-456");
+        let auth = match &auth_header {
+            Some(name) => AuthMiddleware::new(AuthStrategy::ApiKeyInHeader).with_key_header(name),
+            None => AuthMiddleware::new(AuthStrategy::ApiKeyInParams),
+        };
+        let auth = setup_auth(auth, &server, &common).await?;
+        keys = stored_keys(&auth);
         http = http.with_auth(auth);
     }
+    if let Some(path) = &common.policy {
+        http = http.with_middleware(AccessPolicy::from_file(path)?);
+    }
+    let ip_filter = common
+        .ip_rules
+        .as_ref()
+        .map(IpFilter::open)
+        .transpose()?
+        .map(Arc::new);
+    if let Some(filter) = &ip_filter {
+        http = http.with_ip_filter(filter.clone());
+    }
+    let tls = common.tls()?;
+    if let Some(tls) = &tls {
+        http = http.with_tls(tls.clone());
+    }
+    reload_on_hangup(ip_filter, tls, keys)?;
 
     server::metrics::log_startup(addr, "HTTP");
     println!();
@@ -209,18 +887,63 @@ async fn run_http_server(addr: &str, enable_auth: bool) -> anyhow::Result<()> {
     if enable_auth {
         println!("Authentication");
     }
+    if let Some(path) = &common.policy {
+        println!("Access policy ({})", path.display());
+    }
+    if let Some(path) = &common.ip_rules {
+        println!("IP rules ({}, reload with SIGHUP)", path.display());
+    }
+    if let Some(n) = common.rate_limit {
+        println!("Rate limit ({}/s per IP)", n);
+    }
+    if let Some(n) = common.key_rate_limit {
+        println!("Rate limit ({}/s per key)", n);
+    }
+    if let Some(path) = &common.audit_log {
+        println!("Audit log ({})", path.display());
+    }
+    if let Some(path) = &common.tls_cert {
+        println!("TLS ({}, reload with SIGHUP)", path.display());
+    }
     println!();
+    let scheme = if common.tls_cert.is_some() {
+        "https"
+    } else {
+        "http"
+    };
     println!("Endpoints:");
-    println!("POST http://{}/", addr);
-    println!("POST http://{}/rpc", addr);
-    println!("GET  http://{}/metrics", addr);
-    println!("GET  http://{}/health", addr);
+    println!("POST {}://{}/", scheme, addr);
+    println!("POST {}://{}/rpc", scheme, addr);
+    println!("GET  {}://{}/metrics", scheme, addr);
+    println!("GET  {}://{}/health", scheme, addr);
+    println!(
+        "GET  {}://{}/ws (WebSocket)",
+        if scheme == "https" { "wss" } else { "ws" },
+        addr
+    );
     println!();
     println!("Example request:");
-    println!(r#"curl -X POST http://{}/rpc \"#, addr);
+    println!(r#"curl -X POST {}://{}/rpc \"#, scheme, addr);
     println!(r#"  -H "Content-Type: application/json" \"#);
-    if enable_auth {
-        println!(r#"  -d '{{"jsonrpc":"2.0","method":"ping","params":{{"api_key":"dev-key-123"}},"id":1}}'"#);
+    let key = if common.insecure_demo_keys {
+        "dev-key-123"
+    } else {
+        "<api-key>"
+    };
+    match auth_header
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("authorization") => println!(r#"  -H "Authorization: Bearer {}" \"#, key),
+        Some(name) => println!(r#"  -H "{}: {}" \"#, name, key),
+        None => {}
+    }
+    if enable_auth && auth_header.is_none() {
+        println!(
+            r#"  -d '{{"jsonrpc":"2.0","method":"ping","params":{{"api_key":"{}"}},"id":1}}'"#,
+            key
+        );
     } else {
         println!(r#"  -d '{{"jsonrpc":"2.0","method":"ping","params":{{}},"id":1}}'"#);
     }
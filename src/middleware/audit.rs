@@ -0,0 +1,416 @@
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcError, RpcRequest};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// `prev_hash` of the first record in a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Param names whose values never reach the log, at any depth
+const DEFAULT_REDACTED: [&str; 6] = [
+    "api_key",
+    "token",
+    "password",
+    "secret",
+    "signature",
+    "private_key",
+];
+
+/// Most records one query returns
+const MAX_QUERY_LIMIT: usize = 1000;
+
+/// How a mutating call ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Ok,
+    Error { code: i64, message: String },
+}
+
+/// One line of the audit log.
+///
+/// `hash` is the hex SHA-256 of the record's other fields, `prev_hash`
+/// included, so editing, removing or reordering records breaks the chain
+/// from that point on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    /// Unix seconds
+    pub timestamp: u64,
+    pub principal: Option<String>,
+    /// Key store id of the API key used, if any
+    pub key_id: Option<String>,
+    pub peer: Option<String>,
+    pub transport: String,
+    pub method: String,
+    /// Params with credentials replaced by `"[REDACTED]"`
+    pub params: Value,
+    pub outcome: AuditOutcome,
+    /// Transaction created or touched by the call
+    pub txid: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> String {
+        let mut body = serde_json::to_value(self).unwrap_or_default();
+        if let Some(map) = body.as_object_mut() {
+            map.remove("hash");
+        }
+        let bytes = serde_json::to_vec(&body).unwrap_or_default();
+        hex(digest::digest(&digest::SHA256, &bytes).as_ref())
+    }
+}
+
+/// Filters for `AuditLog::query` and `admin.audit.query`; all are optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Earliest timestamp, inclusive (Unix seconds)
+    pub since: Option<u64>,
+    /// Latest timestamp, inclusive (Unix seconds)
+    pub until: Option<u64>,
+    /// Matches the principal id or the API key id
+    pub principal: Option<String>,
+    pub method: Option<String>,
+    /// Only records after this sequence number, for paging
+    pub after_seq: Option<u64>,
+    /// At most this many records, oldest first (default 100, max 1000)
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.since.is_none_or(|t| record.timestamp >= t)
+            && self.until.is_none_or(|t| record.timestamp <= t)
+            && self.after_seq.is_none_or(|s| record.seq > s)
+            && self.method.as_ref().is_none_or(|m| &record.method == m)
+            && self.principal.as_ref().is_none_or(|p| {
+                record.principal.as_ref() == Some(p) || record.key_id.as_ref() == Some(p)
+            })
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(100).min(MAX_QUERY_LIMIT)
+    }
+}
+
+/// The tail of the chain, plus the records themselves when there is no file
+struct Chain {
+    next_seq: u64,
+    last_hash: String,
+    /// Shared with the blocking task that writes each record
+    file: Option<Arc<File>>,
+    records: Vec<AuditRecord>,
+}
+
+/// Append-only, hash-chained record of state-mutating calls.
+///
+/// As a middleware layer it records every call to a method the server
+/// marked with `RpcServer::mark_mutating`: who made it, from where, the
+/// redacted params, the outcome and the txid involved. Add it to the server
+/// itself so it runs after transport auth and sees the principal:
+///
+/// ```no_run
+/// # use dice_rpc::middleware::AuditLog;
+/// # use dice_rpc::server::admin::register_audit_admin_handlers;
+/// # use std::sync::Arc;
+/// # async fn example(server: dice_rpc::RpcServer) -> anyhow::Result<()> {
+/// let audit = Arc::new(AuditLog::open("audit.jsonl")?);
+/// server.mark_mutating(["set_balance", "transfer"]).await;
+/// server.add_middleware(audit.clone()).await;
+/// register_audit_admin_handlers(&server, audit).await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// With a file, each record is one JSON line appended to it and synced to
+/// disk before the call returns (see `with_sync`). File I/O runs on the
+/// blocking thread pool, and queries read through their own handle, so
+/// they never wait for appends. `open` checks the existing chain and
+/// refuses a log that was tampered with; `AuditLog::verify_file` does the
+/// same check offline.
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    redact: Vec<String>,
+    sync: bool,
+    chain: Mutex<Chain>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuditLog {
+    /// A log kept in memory only
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            redact: DEFAULT_REDACTED.iter().map(|s| s.to_string()).collect(),
+            sync: true,
+            chain: Mutex::new(Chain {
+                next_seq: 1,
+                last_hash: GENESIS_HASH.to_string(),
+                file: None,
+                records: Vec::new(),
+            }),
+        }
+    }
+
+    /// Append to the JSON-lines log at `path`, creating it if missing
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let (next_seq, last_hash) = match File::open(&path) {
+            Ok(_) => match Self::verify_file(&path)? {
+                Some(tail) => (tail.seq + 1, tail.hash),
+                None => (1, GENESIS_HASH.to_string()),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (1, GENESIS_HASH.to_string()),
+            Err(e) => return Err(anyhow::anyhow!("cannot read {}: {}", path.display(), e)),
+        };
+
+        let mut options = std::fs::OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("cannot open {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path: Some(path),
+            chain: Mutex::new(Chain {
+                next_seq,
+                last_hash,
+                file: Some(Arc::new(file)),
+                records: Vec::new(),
+            }),
+            ..Self::new()
+        })
+    }
+
+    /// Also redact params named `names`
+    pub fn with_redacted<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.redact.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether each append waits for `sync_data`, so an acknowledged call
+    /// is on disk even after a crash (the default). Turning it off trades
+    /// that guarantee for throughput.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Check every record's hash and link in the log at `path`, returning
+    /// the last record (`None` for an empty log)
+    pub fn verify_file(path: impl AsRef<Path>) -> anyhow::Result<Option<AuditRecord>> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {}", path.display(), e))?;
+        let mut last: Option<AuditRecord> = None;
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: AuditRecord = serde_json::from_str(&line).map_err(|e| {
+                anyhow::anyhow!("{} line {}: malformed record: {}", path.display(), n + 1, e)
+            })?;
+            let (prev_seq, prev_hash) = last
+                .as_ref()
+                .map_or((0, GENESIS_HASH), |r| (r.seq, r.hash.as_str()));
+            if record.seq != prev_seq + 1
+                || record.prev_hash != prev_hash
+                || record.hash != record.compute_hash()
+            {
+                anyhow::bail!(
+                    "{} line {}: audit chain broken at seq {}",
+                    path.display(),
+                    n + 1,
+                    record.seq
+                );
+            }
+            last = Some(record);
+        }
+        Ok(last)
+    }
+
+    /// Record the outcome of a call to `method`
+    pub async fn append(
+        &self,
+        method: &str,
+        params: &Value,
+        ctx: &RequestContext,
+        outcome: &Result<Value, RpcError>,
+    ) -> anyhow::Result<AuditRecord> {
+        let txid = outcome
+            .as_ref()
+            .ok()
+            .and_then(|result| result.get("txid"))
+            .or_else(|| params.get("txid"))
+            .or_else(|| {
+                let err = outcome.as_ref().err()?;
+                err.data.as_ref()?.get("txid")
+            })
+            .and_then(|v| v.as_str())
+            .map(String::from);
+        let outcome = match outcome {
+            Ok(_) => AuditOutcome::Ok,
+            Err(err) => AuditOutcome::Error {
+                code: err.code,
+                message: err.message.clone(),
+            },
+        };
+
+        let mut chain = self.chain.lock().await;
+        let mut record = AuditRecord {
+            seq: chain.next_seq,
+            timestamp: unix_now(),
+            principal: ctx.principal.as_ref().map(|p| p.id.clone()),
+            key_id: ctx.principal.as_ref().and_then(|p| p.key_id.clone()),
+            peer: ctx.peer_addr.map(|addr| addr.to_string()),
+            transport: format!("{:?}", ctx.transport),
+            method: method.to_string(),
+            params: self.redacted(params),
+            outcome,
+            txid,
+            prev_hash: chain.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+
+        // The lock is held across the write so lines land in chain order
+        match &chain.file {
+            Some(file) => {
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                let file = file.clone();
+                let sync = self.sync;
+                tokio::task::spawn_blocking(move || {
+                    (&*file).write_all(&line)?;
+                    if sync {
+                        file.sync_data()?;
+                    }
+                    std::io::Result::Ok(())
+                })
+                .await??;
+            }
+            None => chain.records.push(record.clone()),
+        }
+        chain.next_seq += 1;
+        chain.last_hash = record.hash.clone();
+        Ok(record)
+    }
+
+    /// Records matching `query`, oldest first
+    pub async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let Some(path) = self.path.clone() else {
+            let chain = self.chain.lock().await;
+            return Ok(chain
+                .records
+                .iter()
+                .filter(|r| query.matches(r))
+                .take(query.limit())
+                .cloned()
+                .collect());
+        };
+
+        let query = query.clone();
+        tokio::task::spawn_blocking(move || Self::scan(&path, &query)).await?
+    }
+
+    /// Read matching records from the file at `path` through a handle of
+    /// its own, while appends carry on
+    fn scan(path: &Path, query: &AuditQuery) -> anyhow::Result<Vec<AuditRecord>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut found = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            // A line without its newline is a record still being written
+            if reader.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            let record: AuditRecord = serde_json::from_slice(&line)?;
+            if query.matches(&record) {
+                found.push(record);
+                if found.len() == query.limit() {
+                    break;
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    fn redacted(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| {
+                        let v = if self.redact.iter().any(|r| r.eq_ignore_ascii_case(k)) {
+                            Value::String("[REDACTED]".to_string())
+                        } else {
+                            self.redacted(v)
+                        };
+                        (k.clone(), v)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.redacted(v)).collect()),
+            other => other.clone(),
+        }
+    }
+}
+
+impl RpcMiddleware for AuditLog {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            if !next.is_mutating(&req.method).await {
+                return next.run(req, ctx).await;
+            }
+            let method = req.method.clone();
+            let params = req.params.clone();
+            let caller = ctx.clone();
+
+            let outcome = next.run(req, ctx).await;
+            if let Err(e) = self.append(&method, &params, &caller, &outcome).await {
+                tracing::error!("Failed to write audit record for {}: {}", method, e);
+            }
+            outcome
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -1,8 +1,14 @@
-use crate::rpc::{RpcErrorObj, RpcRequest, RpcResponse};
+use crate::middleware::clientcert::ClientCertConfig;
+use crate::middleware::jwt::JwtConfig;
+use crate::middleware::keystore::KeyStore;
+use crate::middleware::peercred::PeerCredConfig;
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::middleware::signing::{HmacConfig, SIGNATURE_HEADERS, SignedRequest};
+use crate::rpc::{RequestContext, RpcError, RpcRequest, RpcResponse};
+use rustls::server::danger::ClientCertVerifier;
 use serde_json::Value;
-use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::SystemTime;
 
 /// Authentication error codes
 pub const AUTH_ERROR: i64 = -32001;
@@ -16,14 +22,98 @@ pub enum AuthStrategy {
     None,
     /// API key in params: { "api_key": "..." }
     ApiKeyInParams,
-    /// API key in custom header (for HTTP transport)
+    /// API key in a request header (HTTP transport), by default
+    /// `Authorization: Bearer <key>`; see `AuthMiddleware::with_key_header`
     ApiKeyInHeader,
+    /// Signed JWT: `Authorization: Bearer <token>` on HTTP,
+    /// `{ "token": "..." }` in params on header-less transports
+    Jwt(JwtConfig),
+    /// HMAC-SHA256 signature over the raw request with a timestamp and
+    /// single-use nonce: `X-Signature-*` headers on HTTP, a
+    /// `SignedEnvelope` frame on framed TCP. The transport verifies it
+    /// before the request enters the middleware stack.
+    HmacSigned(HmacConfig),
+    /// Mutual TLS: the client presents a certificate issued by a trusted
+    /// CA during the handshake, and its subject (or SAN) names the
+    /// principal. Needs a TLS transport; see `ClientCertConfig`.
+    ClientCert(ClientCertConfig),
+    /// The uid of the local process on the other end of a Unix socket,
+    /// as the kernel reports it. Needs the Unix transport; see
+    /// `PeerCredConfig`.
+    PeerCred(PeerCredConfig),
 }
 
+/// The authenticated caller of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    /// Stable identifier of the caller; never the raw secret
+    pub id: String,
+    /// How the caller authenticated, e.g. `"api_key"` or `"jwt"`
+    pub scheme: String,
+    /// Scopes granted to the caller
+    pub scopes: Vec<String>,
+    /// When the credential stops being valid, if it expires
+    pub expires_at: Option<SystemTime>,
+    /// Raw token claims, for `"jwt"` principals
+    pub claims: Option<Value>,
+    /// Id of the credential used, when it has one apart from `id`
+    /// (a key store entry)
+    pub key_id: Option<String>,
+}
+
+impl Principal {
+    pub fn new(id: impl Into<String>, scheme: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            scheme: scheme.into(),
+            scopes: Vec::new(),
+            expires_at: None,
+            claims: None,
+            key_id: None,
+        }
+    }
+
+    pub fn with_scopes<S: Into<String>>(mut self, scopes: impl IntoIterator<Item = S>) -> Self {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_expiry(mut self, expires_at: SystemTime) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Derive a loggable id for a key that was added without an explicit one
+fn masked_key_id(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "key-****".to_string();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 3..].iter().collect();
+    format!("key-{}…{}", head, tail)
+}
+
+/// Header read by `AuthStrategy::ApiKeyInHeader` unless configured otherwise
+pub const DEFAULT_KEY_HEADER: &str = "authorization";
+
 /// Authentication middleware for RPC requests
 pub struct AuthMiddleware {
     strategy: AuthStrategy,
-    valid_keys: Arc<RwLock<HashSet<String>>>,
+    /// Hashed API keys and what they grant
+    keys: Arc<KeyStore>,
+    /// Lowercased header carrying the key for `ApiKeyInHeader`
+    key_header: String,
 }
 
 impl AuthMiddleware {
@@ -32,68 +122,237 @@ impl AuthMiddleware {
     pub fn new(strategy: AuthStrategy) -> Self {
         Self {
             strategy,
-            valid_keys: Arc::new(RwLock::new(HashSet::new())),
+            keys: Arc::new(KeyStore::new()),
+            key_header: DEFAULT_KEY_HEADER.to_string(),
         }
     }
 
+    /// Read header keys from `name` (e.g. `X-API-Key`) instead of
+    /// `Authorization`. An `Authorization` value must use the `Bearer` scheme;
+    /// any other header carries the bare key.
+    pub fn with_key_header(mut self, name: impl Into<String>) -> Self {
+        self.key_header = name.into().to_ascii_lowercase();
+        self
+    }
+
+    /// Check API keys against `keys` (e.g. one loaded with `KeyStore::open`)
+    /// instead of a fresh in-memory store
+    pub fn with_key_store(mut self, keys: Arc<KeyStore>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// The store API keys are checked against
+    pub fn key_store(&self) -> &Arc<KeyStore> {
+        &self.keys
+    }
+
+    /// The header read by `AuthStrategy::ApiKeyInHeader`
+    pub fn key_header(&self) -> &str {
+        &self.key_header
+    }
+
+    /// Whether credentials come from transport headers rather than params
+    pub fn uses_headers(&self) -> bool {
+        matches!(
+            self.strategy,
+            AuthStrategy::ApiKeyInHeader | AuthStrategy::Jwt(_) | AuthStrategy::HmacSigned(_)
+        )
+    }
+
      #[allow(dead_code)]
     /// Add a valid API key
     pub async fn add_key(&self, key: impl Into<String>) {
-        self.valid_keys.write().await.insert(key.into());
+        let key = key.into();
+        let id = masked_key_id(&key);
+        self.add_key_with_scopes(key, id, Vec::<String>::new())
+            .await;
+    }
+
+    #[allow(dead_code)]
+    /// Add a valid API key that authenticates as the principal `id`
+    pub async fn add_key_with_id(&self, key: impl Into<String>, id: impl Into<String>) {
+        self.add_key_with_scopes(key, id, Vec::<String>::new())
+            .await;
+    }
+
+    /// Add a valid API key for principal `id` holding `scopes` (or roles),
+    /// which an `AccessPolicy` checks per method
+    pub async fn add_key_with_scopes<S: Into<String>>(
+        &self,
+        key: impl Into<String>,
+        id: impl Into<String>,
+        scopes: impl IntoIterator<Item = S>,
+    ) {
+        if let Err(err) = self.keys.insert(&key.into(), id, scopes).await {
+            tracing::warn!("Failed to store API key: {}", err);
+        }
     }
      #[allow(dead_code)]
     /// Remove an API key
     pub async fn remove_key(&self, key: &str) {
-        self.valid_keys.write().await.remove(key);
+        if let Err(err) = self.keys.remove(key).await {
+            tracing::warn!("Failed to remove API key: {}", err);
+        }
     }
 
     /// Check if a key is valid
     pub async fn is_valid_key(&self, key: &str) -> bool {
-        self.valid_keys.read().await.contains(key)
+        self.keys.verify(key).await.is_ok()
     }
 
     /// Validate a request based on the authentication strategy
-    pub async fn validate_request(&self, req: &RpcRequest) -> Result<(), RpcErrorObj> {
+    pub async fn validate_request(&self, req: &RpcRequest) -> Result<(), RpcError> {
+        self.authenticate(req).await.map(|_| ())
+    }
+
+    /// Validate a request and return the principal it authenticated as
+    ///
+    /// `Ok(None)` means the request is allowed without an identity. Header
+    /// strategies need the transport headers, see `authenticate_with_context`;
+    /// here they always fail with `AUTH_REQUIRED`.
+    pub async fn authenticate(&self, req: &RpcRequest) -> Result<Option<Principal>, RpcError> {
+        self.authenticate_with_context(req, &RequestContext::default())
+            .await
+    }
+
+    /// Validate a request using both its params and its transport context
+    pub async fn authenticate_with_context(
+        &self,
+        req: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<Option<Principal>, RpcError> {
         match &self.strategy {
-            AuthStrategy::None => Ok(()),
-            AuthStrategy::ApiKeyInParams => self.validate_params_key(req).await,
-            AuthStrategy::ApiKeyInHeader => {
-                // For header-based auth, this would be checked at transport layer
-                Ok(())
+            AuthStrategy::None => Ok(None),
+            AuthStrategy::ApiKeyInParams => self.validate_params_key(req).await.map(Some),
+            AuthStrategy::ApiKeyInHeader => self.authenticate_headers(ctx).await.map(Some),
+            AuthStrategy::Jwt(_) if ctx.headers.is_some() => {
+                self.authenticate_headers(ctx).await.map(Some)
+            }
+            AuthStrategy::Jwt(jwt) => {
+                let token = req
+                    .params
+                    .get("token")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(AUTH_REQUIRED, "Token required in params"))?;
+                jwt.verify(token).map(Some)
+            }
+            // Signatures cover the raw message, so only the transport that
+            // read it can check them; it sets the principal when they pass
+            AuthStrategy::HmacSigned(_) => ctx
+                .principal
+                .clone()
+                .map(Some)
+                .ok_or_else(|| RpcError::new(AUTH_REQUIRED, "Signed request required")),
+            AuthStrategy::ClientCert(certs) => certs.authenticate(ctx).map(Some),
+            AuthStrategy::PeerCred(creds) => creds.authenticate(ctx).map(Some),
+        }
+    }
+
+    /// Validate the credentials of an HTTP request whose raw body is `body`
+    pub async fn authenticate_http(
+        &self,
+        ctx: &RequestContext,
+        body: &[u8],
+    ) -> Result<Principal, RpcError> {
+        match &self.strategy {
+            AuthStrategy::HmacSigned(_) => {
+                self.verify_signed(&SignedRequest::from_headers(ctx, body)?)
+            }
+            _ => self.authenticate_headers(ctx).await,
+        }
+    }
+
+    /// Check a request signature (from headers or a `SignedEnvelope`)
+    pub fn verify_signed(&self, req: &SignedRequest<'_>) -> Result<Principal, RpcError> {
+        match &self.strategy {
+            AuthStrategy::HmacSigned(hmac) => hmac.verify(req),
+            _ => Err(RpcError::new(
+                AUTH_ERROR,
+                "Signed requests are not accepted",
+            )),
+        }
+    }
+
+    /// Whether the transport must verify `SignedEnvelope` frames
+    pub fn expects_signatures(&self) -> bool {
+        matches!(self.strategy, AuthStrategy::HmacSigned(_))
+    }
+
+    /// The verifier a TLS transport must install so clients present
+    /// certificates, for `AuthStrategy::ClientCert`
+    pub fn client_cert_verifier(&self) -> Option<Arc<dyn ClientCertVerifier>> {
+        match &self.strategy {
+            AuthStrategy::ClientCert(certs) => Some(certs.verifier()),
+            _ => None,
+        }
+    }
+
+    /// Validate the credential carried in the configured header
+    pub async fn authenticate_headers(&self, ctx: &RequestContext) -> Result<Principal, RpcError> {
+        if self.expects_signatures() {
+            return Err(RpcError::new(AUTH_REQUIRED, "Signed request required"));
+        }
+        let credential = self.header_credential(ctx)?;
+        match &self.strategy {
+            AuthStrategy::Jwt(jwt) => jwt.verify(credential),
+            _ => self.keys.verify(credential).await,
+        }
+    }
+
+    /// The raw credential from the key header, without its `Bearer` scheme
+    fn header_credential<'c>(&self, ctx: &'c RequestContext) -> Result<&'c str, RpcError> {
+        let value = ctx.header(&self.key_header).ok_or_else(|| {
+            RpcError::new(
+                AUTH_REQUIRED,
+                format!("Credentials required in {} header", self.key_header),
+            )
+        })?;
+
+        let credential = if self.key_header == DEFAULT_KEY_HEADER {
+            match value.split_once(' ') {
+                Some((scheme, key)) if scheme.eq_ignore_ascii_case("bearer") => key.trim(),
+                _ => {
+                    return Err(RpcError::new(
+                        AUTH_REQUIRED,
+                        "Authorization header must use the Bearer scheme",
+                    ));
+                }
+            }
+        } else {
+            value.trim()
+        };
+        Ok(credential)
+    }
+
+    /// Remove the credential header so handlers never see the raw key
+    pub fn strip_credentials(&self, ctx: &mut RequestContext) {
+        if self.uses_headers()
+            && let Some(headers) = ctx.headers.as_mut()
+        {
+            headers.remove(&self.key_header);
+            if self.expects_signatures() {
+                for name in SIGNATURE_HEADERS {
+                    headers.remove(name);
+                }
             }
         }
     }
 
     /// Validate API key from request params
-    async fn validate_params_key(&self, req: &RpcRequest) -> Result<(), RpcErrorObj> {
+    async fn validate_params_key(&self, req: &RpcRequest) -> Result<Principal, RpcError> {
         let api_key = match &req.params {
             Value::Object(map) => {
                 map.get("api_key")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| RpcErrorObj {
-                        code: AUTH_REQUIRED,
-                        message: "API key required in params".to_string(),
-                        data: None,
-                    })?
+                    .ok_or_else(|| RpcError::new(AUTH_REQUIRED, "API key required in params"))?
             }
             _ => {
-                return Err(RpcErrorObj {
-                    code: AUTH_REQUIRED,
-                    message: "API key required in params".to_string(),
-                    data: None,
-                });
+                return Err(RpcError::new(AUTH_REQUIRED, "API key required in params"));
             }
         };
 
-        if self.is_valid_key(api_key).await {
-            Ok(())
-        } else {
-            Err(RpcErrorObj {
-                code: AUTH_ERROR,
-                message: "Invalid API key".to_string(),
-                data: None,
-            })
-        }
+        self.keys.verify(api_key).await
     }
  
 
@@ -104,6 +363,35 @@ impl AuthMiddleware {
     }
 }
 
+/// Rejects unauthenticated requests and records the principal in the context.
+///
+/// A request whose context already carries a principal (e.g. from a
+/// connection session) was authenticated by an outer layer and passes through.
+impl RpcMiddleware for AuthMiddleware {
+    fn handle<'a>(
+        &'a self,
+        mut req: RpcRequest,
+        mut ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            if ctx.principal.is_some() {
+                return next.run(req, ctx).await;
+            }
+            if let Some(principal) = self.authenticate_with_context(&req, &ctx).await? {
+                ctx.principal = Some(principal);
+            }
+            self.strip_credentials(&mut ctx);
+            // Tokens are bearer secrets; keep them away from handlers
+            if matches!(self.strategy, AuthStrategy::Jwt(_))
+                && let Some(params) = req.params.as_object_mut()
+            {
+                params.remove("token");
+            }
+            next.run(req, ctx).await
+        })
+    }
+}
 
 /// Extension trait for RpcServer to add authentication
 #[allow(async_fn_in_trait)]
@@ -113,7 +401,16 @@ pub trait AuthenticatedServer {
         &self,
         req: RpcRequest,
         auth: &AuthMiddleware,
-    ) -> RpcResponse;
+    ) -> Option<RpcResponse>;
+
+    /// Authenticate, record the principal in `ctx`, then dispatch
+    #[allow(dead_code)]
+    async fn handle_authenticated_request_with_context(
+        &self,
+        req: RpcRequest,
+        auth: &AuthMiddleware,
+        ctx: RequestContext,
+    ) -> Option<RpcResponse>;
 }
 
 impl AuthenticatedServer for crate::rpc::RpcServer {
@@ -121,13 +418,31 @@ impl AuthenticatedServer for crate::rpc::RpcServer {
         &self,
         req: RpcRequest,
         auth: &AuthMiddleware,
-    ) -> RpcResponse {
-        // Validate authentication first
-        if let Err(err) = auth.validate_request(&req).await {
-            return RpcResponse::with_error(req.id.clone(), err.code, err.message);
+    ) -> Option<RpcResponse> {
+        self.handle_authenticated_request_with_context(req, auth, RequestContext::default())
+            .await
+    }
+
+    async fn handle_authenticated_request_with_context(
+        &self,
+        req: RpcRequest,
+        auth: &AuthMiddleware,
+        mut ctx: RequestContext,
+    ) -> Option<RpcResponse> {
+        // Validate authentication first; rejected notifications get no reply
+        match auth.authenticate_with_context(&req, &ctx).await {
+            Ok(principal) => {
+                if principal.is_some() {
+                    ctx.principal = principal;
+                }
+            }
+            Err(err) => {
+                return req.id.map(|id| RpcResponse::with_error_obj(id, err.into()));
+            }
         }
+        auth.strip_credentials(&mut ctx);
 
         // Process request if authenticated
-        self.handle_request(req).await
+        self.handle_request_with_context(req, ctx).await
     }
 }
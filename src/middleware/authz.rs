@@ -0,0 +1,169 @@
+use crate::middleware::auth::{AUTH_REQUIRED, Principal};
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcError, RpcRequest};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+
+/// Error code for an authenticated caller that lacks a required scope
+pub const FORBIDDEN: i64 = -32004;
+
+/// Scopes required to call the methods matching `methods`
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    /// Method names or globs (`admin.*`, `set_*`, `*`)
+    pub methods: Vec<String>,
+    /// Every one of these must be held by the caller; empty means any
+    /// authenticated caller
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Maps methods to the scopes (or roles) a principal needs to call them.
+///
+/// Rules are checked in order and the first one whose patterns match the
+/// method applies. Methods no rule matches are allowed unless the policy is
+/// `deny_by_default`. Install it after authentication so `ctx.principal` is
+/// set when it runs:
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AccessPolicy, AuthMiddleware, AuthStrategy};
+/// # use dice_rpc::transport::TcpServerConfig;
+/// # use std::sync::Arc;
+/// # fn main() -> anyhow::Result<()> {
+/// # let (addr, server) = ("127.0.0.1:4000", Arc::new(dice_rpc::RpcServer::new()));
+/// # let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
+/// let policy = AccessPolicy::from_file("policy.json")?;
+/// let config = TcpServerConfig::new(addr, server)
+///     .with_auth(auth)
+///     .with_middleware(policy);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The JSON form is
+/// `{"deny_by_default": false, "rules": [{"methods": ["admin.*"], "scopes": ["admin"]}]}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccessPolicy {
+    #[serde(default)]
+    rules: Vec<PolicyRule>,
+    #[serde(default)]
+    deny_by_default: bool,
+}
+
+impl AccessPolicy {
+    /// An empty policy that allows every method
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `scopes` for every method matching one of `methods`
+    pub fn require<M, S>(
+        mut self,
+        methods: impl IntoIterator<Item = M>,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> Self
+    where
+        M: Into<String>,
+        S: Into<String>,
+    {
+        self.rules.push(PolicyRule {
+            methods: methods.into_iter().map(Into::into).collect(),
+            scopes: scopes.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    /// Reject methods that no rule matches
+    pub fn deny_by_default(mut self) -> Self {
+        self.deny_by_default = true;
+        self
+    }
+
+    /// Parse a policy from its JSON form
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let de = &mut serde_json::Deserializer::from_str(json);
+        serde_path_to_error::deserialize(de)
+            .map_err(|e| anyhow::anyhow!("invalid access policy at {}: {}", e.path(), e.inner()))
+    }
+
+    /// Load a policy from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    /// The first rule that applies to `method`
+    pub fn rule_for(&self, method: &str) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.methods.iter().any(|p| glob_match(p, method)))
+    }
+
+    /// Decide whether `principal` may call `method`
+    pub fn check(&self, method: &str, principal: Option<&Principal>) -> Result<(), RpcError> {
+        let required: &[String] = match self.rule_for(method) {
+            Some(rule) => &rule.scopes,
+            None if self.deny_by_default => {
+                return Err(RpcError::new(FORBIDDEN, "Method not permitted")
+                    .with_data(json!({ "method": method })));
+            }
+            None => return Ok(()),
+        };
+
+        let Some(principal) = principal else {
+            return Err(RpcError::new(AUTH_REQUIRED, "Authentication required")
+                .with_data(json!({ "method": method })));
+        };
+        let missing: Vec<&String> = required
+            .iter()
+            .filter(|s| !principal.has_scope(s))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(
+                RpcError::new(FORBIDDEN, "Insufficient scope").with_data(json!({
+                    "method": method,
+                    "required": required,
+                    "missing": missing,
+                })),
+            )
+        }
+    }
+}
+
+impl RpcMiddleware for AccessPolicy {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            self.check(&req.method, ctx.principal.as_ref())?;
+            next.run(req, ctx).await
+        })
+    }
+}
+
+/// Match `text` against a pattern where `*` stands for any run of characters
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut text) = text.strip_prefix(prefix) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let suffix = parts.pop().unwrap_or_default();
+    for part in parts {
+        match text.find(part) {
+            Some(at) => text = &text[at + part.len()..],
+            None => return false,
+        }
+    }
+    text.len() >= suffix.len() && text.ends_with(suffix)
+}
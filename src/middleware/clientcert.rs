@@ -0,0 +1,245 @@
+use crate::middleware::auth::{AUTH_ERROR, AUTH_REQUIRED, Principal};
+use crate::middleware::authz::glob_match;
+use crate::rpc::{RequestContext, RpcError};
+use crate::transport::tls::{provider, root_store};
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::server::danger::ClientCertVerifier;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The leaf certificate a client presented in the TLS handshake.
+///
+/// Transports put it in the request extensions only after the handshake
+/// verified it against the `ClientCertConfig` CAs.
+#[derive(Debug, Clone)]
+pub struct PeerCertificate(pub CertificateDer<'static>);
+
+/// Which certificate name becomes the principal id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CertIdentity {
+    /// The subject common name
+    #[default]
+    CommonName,
+    /// The first URI subject alternative name, e.g. a SPIFFE id
+    /// (`spiffe://corp.example/billing`)
+    Uri,
+    /// The first DNS subject alternative name
+    Dns,
+}
+
+impl CertIdentity {
+    fn describe(self) -> &'static str {
+        match self {
+            CertIdentity::CommonName => "subject common name",
+            CertIdentity::Uri => "URI subject alternative name",
+            CertIdentity::Dns => "DNS subject alternative name",
+        }
+    }
+}
+
+/// Names read from an X.509 certificate
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CertNames {
+    pub common_name: Option<String>,
+    pub dns: Vec<String>,
+    pub uris: Vec<String>,
+}
+
+impl CertNames {
+    /// Read the subject CN and subject alternative names of a DER
+    /// certificate; `None` if it is not well-formed
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert, _) = der_element(der)?;
+        let (_, tbs, _) = der_element(cert)?;
+        let mut fields = der_children(tbs)?;
+        // An explicit version comes first; serial, signature algorithm,
+        // issuer and validity precede the subject
+        if fields.first().is_some_and(|(tag, _)| *tag == 0xA0) {
+            fields.remove(0);
+        }
+        let (_, subject) = *fields.get(4)?;
+
+        let mut names = CertNames::default();
+        for (_, rdn) in der_children(subject)? {
+            for (_, attribute) in der_children(rdn)? {
+                if let [(0x06, oid), (_, value)] = der_children(attribute)?[..]
+                    && oid == OID_COMMON_NAME
+                {
+                    names.common_name = Some(String::from_utf8(value.to_vec()).ok()?);
+                }
+            }
+        }
+
+        let extensions = fields.iter().find(|(tag, _)| *tag == 0xA3);
+        if let Some((_, extensions)) = extensions {
+            let (_, list, _) = der_element(extensions)?;
+            for (_, extension) in der_children(list)? {
+                let parts = der_children(extension)?;
+                let (Some((0x06, oid)), Some((0x04, value))) = (parts.first(), parts.last()) else {
+                    continue;
+                };
+                if *oid != OID_SUBJECT_ALT_NAME {
+                    continue;
+                }
+                let (_, general_names, _) = der_element(value)?;
+                for (tag, name) in der_children(general_names)? {
+                    let name = String::from_utf8(name.to_vec()).ok()?;
+                    match tag {
+                        0x82 => names.dns.push(name),
+                        0x86 => names.uris.push(name),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Some(names)
+    }
+
+    pub fn get(&self, identity: CertIdentity) -> Option<&str> {
+        match identity {
+            CertIdentity::CommonName => self.common_name.as_deref(),
+            CertIdentity::Uri => self.uris.first().map(String::as_str),
+            CertIdentity::Dns => self.dns.first().map(String::as_str),
+        }
+    }
+}
+
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1D, 0x11];
+
+/// Split one DER element off `input`: (tag, contents, rest)
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, input) = input.split_first()?;
+    let (len, input) = if first < 0x80 {
+        (first as usize, input)
+    } else {
+        let width = (first & 0x7F) as usize;
+        if width == 0 || width > 4 || input.len() < width {
+            return None;
+        }
+        let (bytes, input) = input.split_at(width);
+        let len = bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, input)
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (contents, rest) = input.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Every element inside a constructed DER value, as (tag, contents)
+fn der_children(mut input: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let mut children = Vec::new();
+    while !input.is_empty() {
+        let (tag, contents, rest) = der_element(input)?;
+        children.push((tag, contents));
+        input = rest;
+    }
+    Some(children)
+}
+
+/// Trusted CAs and role mapping for `AuthStrategy::ClientCert`
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AuthMiddleware, AuthStrategy, CertIdentity, ClientCertConfig};
+/// # fn main() -> anyhow::Result<()> {
+/// let certs = ClientCertConfig::from_ca_file("clients-ca.crt")?
+///     .with_identity(CertIdentity::Uri)
+///     .with_roles("spiffe://corp.example/billing", ["write"])
+///     .with_roles("spiffe://corp.example/*", ["read"]);
+/// let auth = AuthMiddleware::new(AuthStrategy::ClientCert(certs));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClientCertConfig {
+    verifier: Arc<dyn ClientCertVerifier>,
+    identity: CertIdentity,
+    /// (principal pattern, scopes); every matching entry contributes
+    roles: Vec<(String, Vec<String>)>,
+}
+
+impl ClientCertConfig {
+    /// Accept client certificates issued by the CAs in a PEM file
+    pub fn from_ca_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {}", path.display(), e))?;
+        Self::from_ca_pem(&pem)
+    }
+
+    /// Accept client certificates issued by the CAs in PEM text
+    pub fn from_ca_pem(pem: &[u8]) -> anyhow::Result<Self> {
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(root_store(pem)?), provider())
+                .build()?;
+        Ok(Self {
+            verifier,
+            identity: CertIdentity::default(),
+            roles: Vec::new(),
+        })
+    }
+
+    /// Take the principal id from `identity` (the common name by default)
+    pub fn with_identity(mut self, identity: CertIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Grant `scopes` to principals matching `pattern`, where `*` matches
+    /// any run of characters. Principals no pattern matches get no scopes.
+    pub fn with_roles<S: Into<String>>(
+        mut self,
+        pattern: impl Into<String>,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.roles
+            .push((pattern.into(), scopes.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// The rustls verifier transports install on their TLS acceptor
+    pub fn verifier(&self) -> Arc<dyn ClientCertVerifier> {
+        self.verifier.clone()
+    }
+
+    /// The principal for a verified client certificate
+    pub fn principal(&self, cert: &PeerCertificate) -> Result<Principal, RpcError> {
+        let names = CertNames::from_der(cert.0.as_ref()).ok_or_else(|| {
+            RpcError::new(AUTH_ERROR, "Invalid client certificate")
+                .with_data(json!({ "reason": "malformed_certificate" }))
+        })?;
+        let id = names.get(self.identity).ok_or_else(|| {
+            RpcError::new(
+                AUTH_ERROR,
+                format!("Client certificate has no {}", self.identity.describe()),
+            )
+            .with_data(json!({ "reason": "no_identity" }))
+        })?;
+
+        let mut scopes: Vec<String> = Vec::new();
+        for (pattern, granted) in &self.roles {
+            if glob_match(pattern, id) {
+                for scope in granted {
+                    if !scopes.contains(scope) {
+                        scopes.push(scope.clone());
+                    }
+                }
+            }
+        }
+        Ok(Principal::new(id, "client_cert").with_scopes(scopes))
+    }
+
+    /// Authenticate a request by the certificate its transport recorded
+    pub fn authenticate(&self, ctx: &RequestContext) -> Result<Principal, RpcError> {
+        let cert = ctx
+            .extensions
+            .get::<PeerCertificate>()
+            .ok_or_else(|| RpcError::new(AUTH_REQUIRED, "Client certificate required"))?;
+        self.principal(cert)
+    }
+}
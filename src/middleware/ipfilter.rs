@@ -0,0 +1,306 @@
+use crate::middleware::authz::glob_match;
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcError, RpcRequest};
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Error code for a call to a method the peer's address may not use
+pub const IP_DENIED: i64 = -32008;
+
+/// An address block such as `10.0.0.0/8` or `::1/128`; a bare address is a
+/// block of one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` falls inside the block; IPv4-mapped IPv6 addresses match
+    /// IPv4 blocks
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid address in {:?}", s))?;
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| anyhow::anyhow!("invalid prefix length in {:?}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Allowed and denied address blocks. Deny entries win; an empty allow list
+/// admits every address not denied.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IpList {
+    #[serde(default)]
+    pub allow: Vec<Cidr>,
+    #[serde(default)]
+    pub deny: Vec<Cidr>,
+}
+
+impl IpList {
+    /// Whether `ip` is admitted by this list
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        !self.deny.iter().any(|c| c.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|c| c.contains(ip)))
+    }
+}
+
+/// Address lists for the methods matching `methods`
+#[derive(Debug, Clone, Deserialize)]
+pub struct MethodIpRule {
+    /// Method names or globs (`admin.*`, `set_*`, `*`)
+    pub methods: Vec<String>,
+    #[serde(flatten)]
+    pub list: IpList,
+}
+
+/// Connection and per-method address lists for one transport.
+///
+/// The top-level `allow`/`deny` lists decide which peers may connect at
+/// all; method rules then restrict groups of methods further. The first
+/// rule whose patterns match a method applies. The JSON form is
+///
+/// ```json
+/// {
+///   "allow": ["10.0.0.0/8", "127.0.0.1"],
+///   "deny": ["10.13.0.0/16"],
+///   "methods": [{"methods": ["set_balance", "admin.*"], "allow": ["127.0.0.0/8"]}]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IpRules {
+    #[serde(flatten)]
+    pub connections: IpList,
+    #[serde(default)]
+    pub methods: Vec<MethodIpRule>,
+}
+
+impl IpRules {
+    /// Rules that admit everyone
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only admit connections from these blocks
+    pub fn allow<S: AsRef<str>>(
+        mut self,
+        blocks: impl IntoIterator<Item = S>,
+    ) -> anyhow::Result<Self> {
+        self.connections.allow.extend(parse_blocks(blocks)?);
+        Ok(self)
+    }
+
+    /// Refuse connections from these blocks
+    pub fn deny<S: AsRef<str>>(
+        mut self,
+        blocks: impl IntoIterator<Item = S>,
+    ) -> anyhow::Result<Self> {
+        self.connections.deny.extend(parse_blocks(blocks)?);
+        Ok(self)
+    }
+
+    /// Only let callers in `blocks` call the methods matching `methods`
+    pub fn restrict<M, S>(
+        mut self,
+        methods: impl IntoIterator<Item = M>,
+        blocks: impl IntoIterator<Item = S>,
+    ) -> anyhow::Result<Self>
+    where
+        M: Into<String>,
+        S: AsRef<str>,
+    {
+        self.methods.push(MethodIpRule {
+            methods: methods.into_iter().map(Into::into).collect(),
+            list: IpList {
+                allow: parse_blocks(blocks)?,
+                deny: Vec::new(),
+            },
+        });
+        Ok(self)
+    }
+
+    /// Parse rules from their JSON form
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let de = &mut serde_json::Deserializer::from_str(json);
+        serde_path_to_error::deserialize(de)
+            .map_err(|e| anyhow::anyhow!("invalid IP rules at {}: {}", e.path(), e.inner()))
+    }
+
+    /// Load rules from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    /// The first method rule that applies to `method`
+    pub fn rule_for(&self, method: &str) -> Option<&MethodIpRule> {
+        self.methods
+            .iter()
+            .find(|rule| rule.methods.iter().any(|p| glob_match(p, method)))
+    }
+}
+
+fn parse_blocks<S: AsRef<str>>(blocks: impl IntoIterator<Item = S>) -> anyhow::Result<Vec<Cidr>> {
+    blocks.into_iter().map(|b| b.as_ref().parse()).collect()
+}
+
+/// Admission control by peer address, with rules that can be swapped while
+/// the server runs.
+///
+/// Give each transport its own filter with `with_ip_filter`. The transport
+/// drops connections from addresses the rules refuse as soon as they are
+/// accepted, logging them and counting them in its metrics. As a middleware
+/// layer the filter rejects calls to restricted methods with `IP_DENIED`.
+/// Requests without a peer address (in-process calls) pass the connection
+/// lists but not a method rule with an allow list.
+///
+/// ```no_run
+/// # use dice_rpc::middleware::IpFilter;
+/// # use dice_rpc::transport::TcpServerConfig;
+/// # use std::sync::Arc;
+/// # fn main() -> anyhow::Result<()> {
+/// # let (addr, server) = ("127.0.0.1:4000", Arc::new(dice_rpc::RpcServer::new()));
+/// let filter = Arc::new(IpFilter::open("ip-rules.json")?);
+/// let config = TcpServerConfig::new(addr, server).with_ip_filter(filter.clone());
+///
+/// // Later, e.g. on SIGHUP
+/// filter.reload()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    rules: RwLock<Arc<IpRules>>,
+    path: Option<PathBuf>,
+}
+
+impl IpFilter {
+    pub fn new(rules: IpRules) -> Self {
+        Self {
+            rules: RwLock::new(Arc::new(rules)),
+            path: None,
+        }
+    }
+
+    /// Load the rules from a JSON file that `reload` will read again
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let rules = IpRules::from_file(&path)?;
+        Ok(Self {
+            rules: RwLock::new(Arc::new(rules)),
+            path: Some(path),
+        })
+    }
+
+    /// The rules currently in force
+    pub fn rules(&self) -> Arc<IpRules> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Put `rules` in force for new connections and requests
+    pub fn replace(&self, rules: IpRules) {
+        *self.rules.write().unwrap() = Arc::new(rules);
+    }
+
+    /// Re-read the file given to `open`; on error the current rules stay
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("IP filter was not loaded from a file"))?;
+        self.replace(IpRules::from_file(path)?);
+        Ok(())
+    }
+
+    /// Whether a connection from `ip` should be accepted
+    pub fn admits(&self, ip: IpAddr) -> bool {
+        self.rules().connections.permits(ip)
+    }
+
+    /// Decide whether a caller at `ip` may call `method`
+    pub fn check(&self, method: &str, ip: Option<IpAddr>) -> Result<(), RpcError> {
+        let rules = self.rules();
+        let permitted = match ip {
+            Some(ip) => {
+                rules.connections.permits(ip)
+                    && rules.rule_for(method).is_none_or(|r| r.list.permits(ip))
+            }
+            None => rules
+                .rule_for(method)
+                .is_none_or(|r| r.list.allow.is_empty()),
+        };
+        if permitted {
+            Ok(())
+        } else {
+            Err(RpcError::new(IP_DENIED, "Address not permitted")
+                .with_data(json!({ "method": method, "reason": "ip_denied" })))
+        }
+    }
+}
+
+impl RpcMiddleware for IpFilter {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            self.check(&req.method, ctx.peer_addr.map(|addr| addr.ip()))?;
+            next.run(req, ctx).await
+        })
+    }
+}
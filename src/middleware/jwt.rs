@@ -0,0 +1,282 @@
+use crate::middleware::auth::{AUTH_ERROR, Principal};
+use crate::rpc::RpcError;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use ring::{hmac, signature};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// DER encoding of the rsaEncryption OID (1.2.840.113549.1.1.1)
+const RSA_ENCRYPTION_OID: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01,
+];
+
+/// Key used to verify token signatures. The algorithm is fixed by the key,
+/// so a token cannot pick a weaker one through its `alg` header.
+#[derive(Clone)]
+pub enum JwtKey {
+    /// HMAC-SHA256 shared secret
+    Hs256(hmac::Key),
+    /// RSA PKCS#1 v1.5 SHA-256 public key (DER `RSAPublicKey`)
+    Rs256(Vec<u8>),
+}
+
+impl JwtKey {
+    fn alg(&self) -> &'static str {
+        match self {
+            JwtKey::Hs256(_) => "HS256",
+            JwtKey::Rs256(_) => "RS256",
+        }
+    }
+
+    fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match self {
+            JwtKey::Hs256(key) => hmac::verify(key, message, sig).is_ok(),
+            JwtKey::Rs256(der) => {
+                signature::UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, der)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+/// Validation settings for `AuthStrategy::Jwt`
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AuthMiddleware, AuthStrategy, JwtConfig};
+/// # fn main() -> anyhow::Result<()> {
+/// let jwt = JwtConfig::rs256_pem(&std::fs::read_to_string("issuer.pub.pem")?)?
+///     .with_issuer("https://auth.example.com")
+///     .with_audience("dice-rpc");
+/// let auth = AuthMiddleware::new(AuthStrategy::Jwt(jwt));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct JwtConfig {
+    key: Arc<JwtKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: Duration,
+}
+
+impl JwtConfig {
+    /// Verify HS256 tokens with a shared secret
+    pub fn hs256(secret: impl AsRef<[u8]>) -> Self {
+        Self::new(JwtKey::Hs256(hmac::Key::new(
+            hmac::HMAC_SHA256,
+            secret.as_ref(),
+        )))
+    }
+
+    /// Verify RS256 tokens with a PEM public key, either SPKI
+    /// (`BEGIN PUBLIC KEY`) or PKCS#1 (`BEGIN RSA PUBLIC KEY`)
+    pub fn rs256_pem(pem: &str) -> anyhow::Result<Self> {
+        let (label, der) = decode_pem(pem)?;
+        let der = match label.as_str() {
+            "PUBLIC KEY" => spki_rsa_public_key(&der)
+                .ok_or_else(|| anyhow::anyhow!("PEM public key is not an RSA key"))?
+                .to_vec(),
+            "RSA PUBLIC KEY" => der,
+            other => anyhow::bail!("unsupported PEM block: {}", other),
+        };
+        Ok(Self::new(JwtKey::Rs256(der)))
+    }
+
+    /// Verify tokens with an already constructed key
+    pub fn new(key: JwtKey) -> Self {
+        Self {
+            key: Arc::new(key),
+            issuer: None,
+            audience: None,
+            leeway: Duration::from_secs(30),
+        }
+    }
+
+    /// Require the `iss` claim to equal `issuer`
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Require the `aud` claim to contain `audience`
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Clock skew tolerated on `exp` and `nbf` (default 30s)
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Verify a compact JWS token and return the principal it identifies
+    pub fn verify(&self, token: &str) -> Result<Principal, RpcError> {
+        let mut parts = token.split('.');
+        let (header_b64, payload_b64, sig_b64) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(h), Some(p), Some(s), None) => (h, p, s),
+                _ => return Err(invalid_token("malformed token")),
+            };
+
+        let header: JwtHeader = decode_segment(header_b64)?;
+        if header.alg != self.key.alg() {
+            return Err(invalid_token(format!("unexpected alg {}", header.alg)));
+        }
+
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig_b64)
+            .map_err(|_| invalid_token("malformed signature"))?;
+        let signed_len = header_b64.len() + 1 + payload_b64.len();
+        if !self.key.verify(&token.as_bytes()[..signed_len], &sig) {
+            return Err(invalid_token("bad signature"));
+        }
+
+        let claims: Value = decode_segment(payload_b64)?;
+        self.check_claims(&claims)?;
+
+        let sub = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid_token("missing sub claim"))?;
+        let mut principal = Principal::new(sub, "jwt").with_scopes(scopes(&claims));
+        if let Some(exp) = claims.get("exp").and_then(Value::as_u64) {
+            principal = principal.with_expiry(UNIX_EPOCH + Duration::from_secs(exp));
+        }
+        principal.claims = Some(claims);
+        Ok(principal)
+    }
+
+    fn check_claims(&self, claims: &Value) -> Result<(), RpcError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let leeway = self.leeway.as_secs();
+
+        let exp = claims
+            .get("exp")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid_token("missing exp claim"))?;
+        if now > exp + leeway {
+            return Err(RpcError::new(AUTH_ERROR, "Token expired")
+                .with_data(json!({ "reason": "expired" })));
+        }
+        if let Some(nbf) = claims.get("nbf").and_then(Value::as_u64)
+            && now + leeway < nbf
+        {
+            return Err(RpcError::new(AUTH_ERROR, "Token not yet valid")
+                .with_data(json!({ "reason": "not_before" })));
+        }
+
+        if let Some(issuer) = &self.issuer
+            && claims.get("iss").and_then(Value::as_str) != Some(issuer)
+        {
+            return Err(invalid_token("issuer mismatch"));
+        }
+        if let Some(audience) = &self.audience {
+            let ok = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !ok {
+                return Err(invalid_token("audience mismatch"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+fn invalid_token(reason: impl Into<String>) -> RpcError {
+    RpcError::new(AUTH_ERROR, "Invalid token").with_data(json!({ "reason": reason.into() }))
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(segment: &str) -> Result<T, RpcError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| invalid_token("malformed base64"))?;
+    serde_json::from_slice(&bytes).map_err(|_| invalid_token("malformed JSON"))
+}
+
+/// Scopes from an OAuth-style `scope` string or a `scopes`/`scp` array
+fn scopes(claims: &Value) -> Vec<String> {
+    if let Some(scope) = claims.get("scope").and_then(Value::as_str) {
+        return scope.split_whitespace().map(str::to_string).collect();
+    }
+    ["scopes", "scp"]
+        .iter()
+        .filter_map(|name| claims.get(*name).and_then(Value::as_array))
+        .flatten()
+        .filter_map(|s| s.as_str().map(str::to_string))
+        .collect()
+}
+
+/// Decode the first PEM block into its label and DER bytes
+pub(crate) fn decode_pem(pem: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let begin = pem
+        .find("-----BEGIN ")
+        .ok_or_else(|| anyhow::anyhow!("no PEM block found"))?;
+    let rest = &pem[begin + "-----BEGIN ".len()..];
+    let label_end = rest
+        .find("-----")
+        .ok_or_else(|| anyhow::anyhow!("malformed PEM header"))?;
+    let label = &rest[..label_end];
+    let body = &rest[label_end + 5..];
+    let end = body
+        .find(&format!("-----END {}-----", label))
+        .ok_or_else(|| anyhow::anyhow!("missing PEM footer for {}", label))?;
+
+    let b64: String = body[..end].split_whitespace().collect();
+    let der = STANDARD.decode(b64)?;
+    Ok((label.to_string(), der))
+}
+
+/// Split one DER TLV off the front of `input`: (tag, contents, rest)
+fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || input.len() < n {
+            return None;
+        }
+        let len = input[..n]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        input = &input[n..];
+        len
+    };
+    if input.len() < len {
+        return None;
+    }
+    Some((tag, &input[..len], &input[len..]))
+}
+
+/// Extract the PKCS#1 `RSAPublicKey` from a DER `SubjectPublicKeyInfo`
+fn spki_rsa_public_key(der: &[u8]) -> Option<&[u8]> {
+    let (0x30, spki, _) = der_next(der)? else {
+        return None;
+    };
+    let (0x30, algorithm, rest) = der_next(spki)? else {
+        return None;
+    };
+    if !algorithm.starts_with(RSA_ENCRYPTION_OID) {
+        return None;
+    }
+    match der_next(rest)? {
+        (0x03, [0x00, key @ ..], _) => Some(key),
+        _ => None,
+    }
+}
@@ -0,0 +1,615 @@
+use crate::middleware::auth::{AUTH_ERROR, Principal};
+use crate::rpc::RpcError;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock};
+
+/// Error code for a key that was valid but has passed its `expires_at`
+/// (including the old key of a rotation once its grace period is over)
+pub const KEY_EXPIRED: i64 = -32005;
+
+/// Prefix of the keys `KeyStore::issue` generates, which continue with the
+/// key id, a `.` and the secret
+const ISSUED_KEY_PREFIX: &str = "dk_";
+
+/// Public metadata of a stored key; never includes the key itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// Stable id used to rotate or revoke the key
+    pub id: String,
+    /// Principal id requests made with the key run as
+    pub principal: String,
+    pub label: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Unix seconds
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    pub last_used: Option<u64>,
+    #[serde(default)]
+    pub revoked: bool,
+    /// Id of the key that replaced this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated_to: Option<String>,
+}
+
+/// A newly generated key; `key` is only ever available here
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub info: KeyInfo,
+}
+
+#[derive(Deserialize)]
+struct KeyRecord {
+    #[serde(flatten)]
+    info: KeyInfo,
+    /// base64 salt and SHA-256(salt || key)
+    salt: String,
+    hash: String,
+    /// Set when the key embeds its id, so `verify` finds the record by id
+    /// instead of hashing against every caller-chosen key
+    #[serde(default)]
+    id_in_key: bool,
+    /// Unix seconds of the latest successful `verify`, 0 if none since
+    /// loading; updated under the read lock and merged into `info` on save
+    #[serde(skip)]
+    used: AtomicU64,
+}
+
+impl KeyRecord {
+    fn new(info: KeyInfo, (salt, hash): (String, String), id_in_key: bool) -> Self {
+        Self {
+            info,
+            salt,
+            hash,
+            id_in_key,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    /// `info` with the in-memory `last_used` applied
+    fn info(&self) -> KeyInfo {
+        let mut info = self.info.clone();
+        let used = self.used.load(Ordering::Relaxed);
+        if used > info.last_used.unwrap_or(0) {
+            info.last_used = Some(used);
+        }
+        info
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        let Ok(salt) = STANDARD.decode(&self.salt) else {
+            return false;
+        };
+        let Ok(hash) = STANDARD.decode(&self.hash) else {
+            return false;
+        };
+        constant_time_eq(salted_hash(&salt, key).as_ref(), &hash)
+    }
+}
+
+/// A record as written back to the file, `last_used` included
+#[derive(Serialize)]
+struct StoredRecord<'a> {
+    #[serde(flatten)]
+    info: KeyInfo,
+    salt: &'a str,
+    hash: &'a str,
+    id_in_key: bool,
+}
+
+#[derive(Default, Deserialize)]
+struct KeyFile {
+    keys: Vec<KeyRecord>,
+}
+
+/// The records, oldest first, indexed by key id
+#[derive(Default)]
+struct Keys {
+    records: Vec<KeyRecord>,
+    by_id: HashMap<String, usize>,
+}
+
+impl Keys {
+    fn new(records: Vec<KeyRecord>) -> Self {
+        let mut keys = Self::default();
+        for record in records {
+            keys.push(record);
+        }
+        keys
+    }
+
+    fn push(&mut self, record: KeyRecord) {
+        self.by_id
+            .insert(record.info.id.clone(), self.records.len());
+        self.records.push(record);
+    }
+
+    fn get(&self, id: &str) -> Option<&KeyRecord> {
+        self.records.get(*self.by_id.get(id)?)
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut KeyRecord> {
+        let index = *self.by_id.get(id)?;
+        self.records.get_mut(index)
+    }
+
+    /// Latest `verify` time of each record used since it was loaded
+    fn used(&self) -> HashMap<String, u64> {
+        self.records
+            .iter()
+            .map(|r| (r.info.id.clone(), r.used.load(Ordering::Relaxed)))
+            .filter(|&(_, used)| used > 0)
+            .collect()
+    }
+
+    /// Carry over `used` times onto freshly loaded records
+    fn keep_used(&mut self, used: &HashMap<String, u64>) {
+        for (id, &time) in used {
+            if let Some(record) = self.get_mut(id) {
+                record.used.fetch_max(time, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The record holding `key`: looked up by the id an issued key embeds,
+    /// otherwise among the caller-chosen keys
+    fn find(&self, key: &str) -> Option<&KeyRecord> {
+        let embedded = key
+            .strip_prefix(ISSUED_KEY_PREFIX)
+            .and_then(|rest| rest.split_once('.'))
+            .and_then(|(id, _)| self.by_id.get(id))
+            .map(|&index| &self.records[index])
+            .filter(|record| record.id_in_key);
+        if let Some(record) = embedded {
+            return record.matches(key).then_some(record);
+        }
+        self.records
+            .iter()
+            .filter(|record| !record.id_in_key)
+            .find(|record| record.matches(key))
+    }
+}
+
+/// API keys stored as salted SHA-256 hashes with their metadata.
+///
+/// A store opened with `KeyStore::open` is written back to its file after
+/// every change (issue, rotate, revoke, remove). Each change first re-reads
+/// the file under a lock on `<path>.lock`, so processes sharing the file
+/// (say, a server and the `keys` command) never undo each other's changes;
+/// `reload` picks up theirs in between. `last_used` is tracked in memory
+/// and saved with the next change or an explicit `flush`.
+///
+/// Issued keys have the form `dk_<id>.<secret>`, so `verify` checks a
+/// single hash under a shared lock. Keys added with `insert` are matched
+/// against each caller-chosen key in turn.
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AuthMiddleware, AuthStrategy, KeyStore};
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// # async fn example() -> anyhow::Result<()> {
+/// let keys = Arc::new(KeyStore::open("keys.json")?);
+/// let issued = keys.issue("billing", ["read"], Some(Duration::from_secs(86400))).await?;
+/// println!("{}", issued.key); // shown once, only the hash is stored
+///
+/// let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams).with_key_store(keys);
+/// # Ok(())
+/// # }
+/// ```
+pub struct KeyStore {
+    path: Option<PathBuf>,
+    keys: RwLock<Keys>,
+    /// Held across a change's file I/O, which leaves `keys` readable
+    writer: Mutex<()>,
+    /// Set when `verify` recorded a use that is not saved yet
+    unsaved_use: AtomicBool,
+    rng: SystemRandom,
+}
+
+impl Default for KeyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyStore {
+    /// An in-memory store
+    pub fn new() -> Self {
+        Self::with_keys(None, Keys::default())
+    }
+
+    /// Load the store persisted at `path`, or start an empty one there
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let keys = load(&path)?;
+        Ok(Self::with_keys(Some(path), keys))
+    }
+
+    fn with_keys(path: Option<PathBuf>, keys: Keys) -> Self {
+        Self {
+            path,
+            keys: RwLock::new(keys),
+            writer: Mutex::new(()),
+            unsaved_use: AtomicBool::new(false),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// The file this store persists to, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Generate and store a new key for principal `label`
+    pub async fn issue<S: Into<String>>(
+        &self,
+        label: impl Into<String>,
+        scopes: impl IntoIterator<Item = S>,
+        ttl: Option<Duration>,
+    ) -> anyhow::Result<IssuedKey> {
+        let label = label.into();
+        let id = self.generate_id()?;
+        let key = self.generate_key(&id)?;
+        let info = KeyInfo {
+            id,
+            principal: label.clone(),
+            label,
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            created_at: unix_now(),
+            expires_at: ttl.map(|ttl| unix_now() + ttl.as_secs()),
+            last_used: None,
+            revoked: false,
+            rotated_to: None,
+        };
+
+        let record = KeyRecord::new(info.clone(), self.hash(&key)?, true);
+        self.change(move |keys| {
+            keys.push(record);
+            Ok(())
+        })
+        .await?;
+        Ok(IssuedKey { key, info })
+    }
+
+    /// Store a caller-chosen key that authenticates as `principal`
+    pub async fn insert<S: Into<String>>(
+        &self,
+        key: &str,
+        principal: impl Into<String>,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> anyhow::Result<KeyInfo> {
+        let principal = principal.into();
+        let info = KeyInfo {
+            id: self.generate_id()?,
+            label: principal.clone(),
+            principal,
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            created_at: unix_now(),
+            expires_at: None,
+            last_used: None,
+            revoked: false,
+            rotated_to: None,
+        };
+
+        let record = KeyRecord::new(info.clone(), self.hash(key)?, false);
+        self.change(move |keys| {
+            keys.push(record);
+            Ok(())
+        })
+        .await?;
+        Ok(info)
+    }
+
+    /// Replace key `id` with a new one for the same principal and scopes.
+    ///
+    /// The old key keeps working for `grace` (never longer than it would
+    /// have anyway) and then fails with `KEY_EXPIRED`. Returns `None` when
+    /// `id` is unknown, revoked or already rotated.
+    pub async fn rotate(&self, id: &str, grace: Duration) -> anyhow::Result<Option<IssuedKey>> {
+        let id = id.to_string();
+        let new_id = self.generate_id()?;
+        let key = self.generate_key(&new_id)?;
+        let hash = self.hash(&key)?;
+
+        self.change(move |keys| {
+            let Some(old) = keys
+                .get_mut(&id)
+                .filter(|r| !r.info.revoked && r.info.rotated_to.is_none())
+            else {
+                return Ok(None);
+            };
+
+            let now = unix_now();
+            let info = KeyInfo {
+                id: new_id,
+                principal: old.info.principal.clone(),
+                label: old.info.label.clone(),
+                scopes: old.info.scopes.clone(),
+                created_at: now,
+                // A key issued with a lifetime gets the same lifetime again
+                expires_at: old
+                    .info
+                    .expires_at
+                    .map(|exp| now + exp.saturating_sub(old.info.created_at)),
+                last_used: None,
+                revoked: false,
+                rotated_to: None,
+            };
+
+            let grace_end = now + grace.as_secs();
+            old.info.expires_at = Some(old.info.expires_at.map_or(grace_end, |e| e.min(grace_end)));
+            old.info.rotated_to = Some(info.id.clone());
+
+            keys.push(KeyRecord::new(info.clone(), hash, true));
+            Ok(Some(IssuedKey { key, info }))
+        })
+        .await
+    }
+
+    /// Revoke key `id` immediately; `false` if no such key exists
+    pub async fn revoke(&self, id: &str) -> anyhow::Result<bool> {
+        let id = id.to_string();
+        self.change(move |keys| {
+            let Some(record) = keys.get_mut(&id) else {
+                return Ok(false);
+            };
+            record.info.revoked = true;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Delete every record holding `key`
+    pub async fn remove(&self, key: &str) -> anyhow::Result<()> {
+        let key = key.to_string();
+        self.change(move |keys| {
+            let records = std::mem::take(&mut keys.records);
+            *keys = Keys::new(records.into_iter().filter(|r| !r.matches(&key)).collect());
+            Ok(())
+        })
+        .await
+    }
+
+    /// Metadata of every stored key, oldest first
+    pub async fn list(&self) -> Vec<KeyInfo> {
+        self.keys
+            .read()
+            .await
+            .records
+            .iter()
+            .map(KeyRecord::info)
+            .collect()
+    }
+
+    /// Check `key` and return the principal it authenticates as
+    ///
+    /// Revoked and unknown keys fail with `AUTH_ERROR`; expired ones with
+    /// `KEY_EXPIRED`, so clients can tell they need a new key.
+    pub async fn verify(&self, key: &str) -> Result<Principal, RpcError> {
+        let keys = self.keys.read().await;
+        let record = keys
+            .find(key)
+            .ok_or_else(|| RpcError::new(AUTH_ERROR, "Invalid API key"))?;
+        check(record)?;
+        let now = unix_now();
+        if record.used.swap(now, Ordering::Relaxed) != now {
+            self.unsaved_use.store(true, Ordering::Relaxed);
+        }
+
+        let mut principal = Principal::new(record.info.principal.clone(), "api_key")
+            .with_scopes(record.info.scopes.clone())
+            .with_key_id(record.info.id.clone());
+        if let Some(expires_at) = record.info.expires_at {
+            principal = principal.with_expiry(UNIX_EPOCH + Duration::from_secs(expires_at));
+        }
+        Ok(principal)
+    }
+
+    /// Check that key `id` still exists and is neither revoked nor expired,
+    /// for callers holding on to a principal `verify` returned earlier
+    pub async fn check_id(&self, id: &str) -> Result<(), RpcError> {
+        let keys = self.keys.read().await;
+        let record = keys
+            .get(id)
+            .ok_or_else(|| RpcError::new(AUTH_ERROR, "API key removed"))?;
+        check(record)
+    }
+
+    /// Write the store, including `last_used` times, to its file; does
+    /// nothing when no key was used since the last write
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        if self.path.is_none() || !self.unsaved_use.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.change(|_| Ok(())).await
+    }
+
+    /// Re-read the file, taking in changes other processes made to it.
+    /// On error the keys in memory stay in force.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let _writer = self.writer.lock().await;
+        let loaded = tokio::task::spawn_blocking(move || load(&path)).await??;
+        self.replace(loaded).await;
+        Ok(())
+    }
+
+    /// Apply `change` to the keys and save them. With a file, the change
+    /// runs off the runtime on the file's current contents, under the lock.
+    async fn change<T, F>(&self, change: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Keys) -> anyhow::Result<T> + Send + 'static,
+    {
+        let _writer = self.writer.lock().await;
+        let Some(path) = self.path.clone() else {
+            return change(&mut *self.keys.write().await);
+        };
+
+        self.unsaved_use.store(false, Ordering::Relaxed);
+        let used = self.keys.read().await.used();
+        let (keys, result) = tokio::task::spawn_blocking(move || {
+            let _lock = lock(&path)?;
+            let mut keys = load(&path)?;
+            keys.keep_used(&used);
+            let result = change(&mut keys)?;
+            save(&path, &keys)?;
+            Ok((keys, result))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|changed| changed)
+        .inspect_err(|_| self.unsaved_use.store(true, Ordering::Relaxed))?;
+        self.replace(keys).await;
+        Ok(result)
+    }
+
+    /// Swap in freshly loaded keys, keeping uses recorded meanwhile
+    async fn replace(&self, mut loaded: Keys) {
+        let mut keys = self.keys.write().await;
+        loaded.keep_used(&keys.used());
+        *keys = loaded;
+    }
+
+    /// A random salt and SHA-256(salt || key), base64
+    fn hash(&self, key: &str) -> anyhow::Result<(String, String)> {
+        let salt = self.random::<16>()?;
+        Ok((
+            STANDARD.encode(salt),
+            STANDARD.encode(salted_hash(&salt, key)),
+        ))
+    }
+
+    fn generate_key(&self, id: &str) -> anyhow::Result<String> {
+        Ok(format!(
+            "{}{}.{}",
+            ISSUED_KEY_PREFIX,
+            id,
+            URL_SAFE_NO_PAD.encode(self.random::<32>()?)
+        ))
+    }
+
+    fn generate_id(&self) -> anyhow::Result<String> {
+        let bytes = self.random::<6>()?;
+        Ok(format!(
+            "key_{}",
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        ))
+    }
+
+    fn random<const N: usize>(&self) -> anyhow::Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.rng
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("system random number generator failed"))?;
+        Ok(bytes)
+    }
+}
+
+/// Revoked keys fail with `AUTH_ERROR`, expired ones with `KEY_EXPIRED`
+fn check(record: &KeyRecord) -> Result<(), RpcError> {
+    if record.info.revoked {
+        return Err(RpcError::new(AUTH_ERROR, "API key revoked")
+            .with_data(json!({ "key_id": record.info.id })));
+    }
+    if let Some(expires_at) = record.info.expires_at
+        && unix_now() >= expires_at
+    {
+        return Err(
+            RpcError::new(KEY_EXPIRED, "API key expired").with_data(json!({
+                "key_id": record.info.id,
+                "expired_at": expires_at,
+                "rotated_to": record.info.rotated_to,
+            })),
+        );
+    }
+    Ok(())
+}
+
+/// The keys stored at `path`; none if there is no file yet
+fn load(path: &Path) -> anyhow::Result<Keys> {
+    let file = match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str::<KeyFile>(&json)
+            .map_err(|e| anyhow::anyhow!("invalid key store {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => KeyFile::default(),
+        Err(e) => anyhow::bail!("cannot read key store {}: {}", path.display(), e),
+    };
+    Ok(Keys::new(file.keys))
+}
+
+fn save(path: &Path, keys: &Keys) -> anyhow::Result<()> {
+    let records: Vec<_> = keys
+        .records
+        .iter()
+        .map(|r| StoredRecord {
+            info: r.info(),
+            salt: &r.salt,
+            hash: &r.hash,
+            id_in_key: r.id_in_key,
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&json!({ "keys": records }))?;
+    // Write then rename so a crash never leaves a truncated store
+    let tmp = path.with_extension("tmp");
+    write_private(&tmp, json.as_bytes())
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| anyhow::anyhow!("cannot write key store {}: {}", path.display(), e))
+}
+
+/// Take the exclusive lock on `<path>.lock`, released when the file drops
+fn lock(path: &Path) -> anyhow::Result<std::fs::File> {
+    let lock_path = path.with_extension("lock");
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .and_then(|file| file.lock().map(|_| file))
+        .map_err(|e| anyhow::anyhow!("cannot lock key store {}: {}", lock_path.display(), e))?;
+    Ok(file)
+}
+
+/// Create or truncate `path` readable by its owner only
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+fn salted_hash(salt: &[u8], key: &str) -> digest::Digest {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(salt);
+    ctx.update(key.as_bytes());
+    ctx.finish()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
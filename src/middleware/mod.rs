@@ -1,3 +1,30 @@
+pub mod audit;
 pub mod auth;
+pub mod authz;
+pub mod clientcert;
+pub mod ipfilter;
+pub mod jwt;
+pub mod keystore;
+pub mod peercred;
+pub mod pipeline;
+pub mod ratelimit;
+pub mod session;
+pub mod signing;
+pub use audit::{AuditLog, AuditOutcome, AuditQuery, AuditRecord};
 #[allow(unused)]
-pub use auth::{AuthMiddleware, AuthStrategy, AuthenticatedServer, AUTH_ERROR, AUTH_REQUIRED};
\ No newline at end of file
+pub use auth::{
+    AUTH_ERROR, AUTH_REQUIRED, AuthMiddleware, AuthStrategy, AuthenticatedServer, Principal,
+};
+pub use authz::{AccessPolicy, FORBIDDEN, PolicyRule};
+pub use clientcert::{CertIdentity, CertNames, ClientCertConfig, PeerCertificate};
+pub use pipeline::{
+    LoggingMiddleware, MetricsMiddleware, MiddlewareFuture, Next, REQUEST_TIMEOUT, RpcMiddleware,
+    TimeoutMiddleware,
+};
+pub use peercred::{PeerCredConfig, PeerCredentials};
+pub use ipfilter::{Cidr, IP_DENIED, IpFilter, IpList, IpRules, MethodIpRule};
+pub use ratelimit::{RATE_LIMITED, RateLimit, RateLimiter};
+pub use session::{SESSION_AUTH_METHOD, SESSION_LOGOUT_METHOD, SessionMiddleware};
+pub use signing::{HmacConfig, SignedEnvelope, SignedRequest, sign_request};
+pub use jwt::{JwtConfig, JwtKey};
+pub use keystore::{IssuedKey, KEY_EXPIRED, KeyInfo, KeyStore};
@@ -0,0 +1,83 @@
+use crate::middleware::auth::{AUTH_ERROR, AUTH_REQUIRED, Principal};
+use crate::rpc::{RequestContext, RpcError};
+use serde_json::json;
+
+/// The process on the other end of a Unix socket, as the kernel reported
+/// it at accept (`SO_PEERCRED`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not reported on every platform
+    pub pid: Option<i32>,
+}
+
+/// Uid-to-scope mapping for `AuthStrategy::PeerCred`
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AuthMiddleware, AuthStrategy, PeerCredConfig};
+/// let creds = PeerCredConfig::new()
+///     .with_roles(0, ["admin"])
+///     .with_roles(1001, ["read", "write"])
+///     .known_uids_only();
+/// let auth = AuthMiddleware::new(AuthStrategy::PeerCred(creds));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PeerCredConfig {
+    /// (uid, scopes); every matching entry contributes
+    roles: Vec<(u32, Vec<String>)>,
+    known_only: bool,
+}
+
+impl PeerCredConfig {
+    /// Accept any local peer, with no scopes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grant `scopes` to processes running as `uid`
+    pub fn with_roles<S: Into<String>>(
+        mut self,
+        uid: u32,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.roles
+            .push((uid, scopes.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Refuse peers whose uid has no `with_roles` entry
+    pub fn known_uids_only(mut self) -> Self {
+        self.known_only = true;
+        self
+    }
+
+    /// The principal for a peer: id `uid:<n>`, scheme `"peer_cred"`
+    pub fn principal(&self, creds: &PeerCredentials) -> Result<Principal, RpcError> {
+        let mut known = false;
+        let mut scopes: Vec<String> = Vec::new();
+        for (uid, granted) in &self.roles {
+            if *uid == creds.uid {
+                known = true;
+                for scope in granted {
+                    if !scopes.contains(scope) {
+                        scopes.push(scope.clone());
+                    }
+                }
+            }
+        }
+        if self.known_only && !known {
+            return Err(RpcError::new(AUTH_ERROR, "Unknown peer uid")
+                .with_data(json!({ "reason": "unknown_uid", "uid": creds.uid })));
+        }
+        Ok(Principal::new(format!("uid:{}", creds.uid), "peer_cred").with_scopes(scopes))
+    }
+
+    /// Authenticate a request by the credentials its transport recorded
+    pub fn authenticate(&self, ctx: &RequestContext) -> Result<Principal, RpcError> {
+        let creds = ctx.extensions.get::<PeerCredentials>().ok_or_else(|| {
+            RpcError::new(AUTH_REQUIRED, "Peer credentials required (Unix socket)")
+        })?;
+        self.principal(creds)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::rpc::{RequestContext, RpcError, RpcRequest, RpcServer};
+use crate::server::metrics::{Metrics, RequestTracer};
+use futures::future::BoxFuture;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Error code for a request that exceeded its `TimeoutMiddleware` budget
+pub const REQUEST_TIMEOUT: i64 = -32003;
+
+/// Outcome of a request as it travels back out through the middleware stack
+pub type MiddlewareFuture<'a> = BoxFuture<'a, Result<Value, RpcError>>;
+
+/// A layer around handler dispatch.
+///
+/// Middleware sees every request, including each entry of a batch and
+/// notifications, regardless of the transport it arrived on. Code before
+/// `next.run(..)` is the "before" hook; returning `Err` there short-circuits
+/// the request. Code after it is the "after" hook and may inspect or replace
+/// the outcome.
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{MiddlewareFuture, Next, RpcMiddleware};
+/// # use dice_rpc::rpc::{RequestContext, RpcError, RpcRequest};
+/// struct Deny;
+///
+/// impl RpcMiddleware for Deny {
+///     fn handle<'a>(&'a self, req: RpcRequest, ctx: RequestContext, next: Next<'a>) -> MiddlewareFuture<'a> {
+///         Box::pin(async move {
+///             if req.method.starts_with("admin.") {
+///                 return Err(RpcError::new(-32010, "Forbidden"));
+///             }
+///             next.run(req, ctx).await
+///         })
+///     }
+/// }
+/// ```
+pub trait RpcMiddleware: Send + Sync + 'static {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a>;
+}
+
+/// A shared layer, so one instance can also be kept for queries or reloads
+impl<T: RpcMiddleware> RpcMiddleware for Arc<T> {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        (**self).handle(req, ctx, next)
+    }
+}
+
+/// The remainder of the middleware stack, ending in the method handler
+pub struct Next<'a> {
+    server: &'a RpcServer,
+    layers: &'a [Arc<dyn RpcMiddleware>],
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(server: &'a RpcServer, layers: &'a [Arc<dyn RpcMiddleware>]) -> Self {
+        Self { server, layers }
+    }
+
+    /// Whether the server marked `method` as state-mutating
+    pub async fn is_mutating(&self, method: &str) -> bool {
+        self.server.is_mutating(method).await
+    }
+
+    /// Pass the request on to the next layer, or to the handler
+    pub fn run(self, req: RpcRequest, ctx: RequestContext) -> MiddlewareFuture<'a> {
+        match self.layers.split_first() {
+            Some((layer, rest)) => layer.handle(req, ctx, Next::new(self.server, rest)),
+            None => Box::pin(self.server.call_handler(req, ctx)),
+        }
+    }
+}
+
+/// Records every request in `Metrics` through a `RequestTracer`
+pub struct MetricsMiddleware {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsMiddleware {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl RpcMiddleware for MetricsMiddleware {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            let tracer = RequestTracer::new(req.method.clone(), self.metrics.clone());
+            let outcome = next.run(req, ctx).await;
+            match &outcome {
+                Ok(_) => tracer.success().await,
+                Err(err) => tracer.error(&err.to_string()).await,
+            }
+            outcome
+        })
+    }
+}
+
+/// Logs each call with its transport, peer and outcome
+pub struct LoggingMiddleware;
+
+impl RpcMiddleware for LoggingMiddleware {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            let method = req.method.clone();
+            let peer = ctx.peer_addr.map(|a| a.to_string()).unwrap_or_default();
+            let transport = ctx.transport;
+            let start = Instant::now();
+
+            let outcome = next.run(req, ctx).await;
+            let elapsed_ms = start.elapsed().as_millis();
+            match &outcome {
+                Ok(_) => tracing::debug!(
+                    "{} from {} via {:?}: ok ({}ms)",
+                    method,
+                    peer,
+                    transport,
+                    elapsed_ms
+                ),
+                Err(err) => tracing::debug!(
+                    "{} from {} via {:?}: {} ({}ms)",
+                    method,
+                    peer,
+                    transport,
+                    err,
+                    elapsed_ms
+                ),
+            }
+            outcome
+        })
+    }
+}
+
+/// Fails requests whose handler does not finish within `timeout`
+pub struct TimeoutMiddleware {
+    timeout: Duration,
+}
+
+impl TimeoutMiddleware {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl RpcMiddleware for TimeoutMiddleware {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            let method = req.method.clone();
+            match tokio::time::timeout(self.timeout, next.run(req, ctx)).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(
+                    RpcError::new(REQUEST_TIMEOUT, "Request timed out").with_data(
+                        json!({ "method": method, "timeout_ms": self.timeout.as_millis() as u64 }),
+                    ),
+                ),
+            }
+        })
+    }
+}
@@ -0,0 +1,366 @@
+use crate::middleware::auth::Principal;
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcError, RpcRequest};
+use crate::server::metrics::Metrics;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Error code for a request rejected by `RateLimiter`
+pub const RATE_LIMITED: i64 = -32006;
+
+/// Idle buckets are swept once the table grows past this many entries
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// A token bucket: `burst` requests at once, refilled at `per_second`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    burst: f64,
+    per_second: f64,
+}
+
+impl RateLimit {
+    /// `n` requests per second, with a burst of `n`
+    pub fn per_second(n: u32) -> Self {
+        Self {
+            burst: n as f64,
+            per_second: n as f64,
+        }
+    }
+
+    /// `n` requests per minute, with a burst of `n`
+    pub fn per_minute(n: u32) -> Self {
+        Self {
+            burst: n as f64,
+            per_second: n as f64 / 60.0,
+        }
+    }
+
+    /// Allow up to `burst` requests back to back
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst as f64;
+        self
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst);
+        self.updated = now;
+    }
+
+    /// Time until the bucket holds a whole token again
+    fn wait(&self, limit: &RateLimit) -> Duration {
+        if limit.per_second <= 0.0 {
+            return Duration::MAX;
+        }
+        Duration::from_secs_f64(((1.0 - self.tokens) / limit.per_second).max(0.0))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Key(String),
+    Ip(IpAddr),
+    /// Method name and the caller (credential or peer IP)
+    Method(String, String),
+}
+
+/// The credential a caller's buckets count against: the key store entry
+/// for stored keys, so keys sharing a label get separate budgets, and the
+/// principal id (JWT `sub`, certificate identity) otherwise
+fn credential(principal: &Principal) -> String {
+    let id = principal.key_id.as_ref().unwrap_or(&principal.id);
+    format!("{}:{}", principal.scheme, id)
+}
+
+/// Which of a limiter's buckets a middleware layer checks
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    All,
+    Peer,
+    Caller,
+}
+
+/// Token-bucket limits per API key, per peer IP and per method.
+///
+/// Per-key limits apply to each credential (a stored key's id, a JWT
+/// subject or a certificate identity), per-IP limits to callers with a
+/// peer address, and per-method limits to each caller of that method
+/// separately. A request must fit within every limit that applies to
+/// it, and each batch entry counts as one request. Over-limit requests fail
+/// with `RATE_LIMITED` and `retry_after_ms` in `error.data`; `HttpTransport`
+/// also answers them with HTTP 429.
+///
+/// The transports split a limiter into two layers: `peer_layer` checks the
+/// per-IP bucket before authentication, so failed logins and bad keys are
+/// throttled too, and `caller_layer` checks the per-key and per-method
+/// buckets once the principal is known. Used directly as a middleware, the
+/// limiter checks every bucket in one place.
+///
+/// Share one limiter between transports so a caller's budget is the same
+/// whichever way it connects:
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{RateLimit, RateLimiter};
+/// # use dice_rpc::transport::{HttpTransport, TcpServerConfig};
+/// # use dice_rpc::{Metrics, RpcServer};
+/// # use std::sync::Arc;
+/// # let (metrics, server) = (Arc::new(Metrics::new()), Arc::new(RpcServer::new()));
+/// # let addr = "127.0.0.1:4000";
+/// let limiter = Arc::new(
+///     RateLimiter::new()
+///         .per_key(RateLimit::per_second(20))
+///         .per_ip(RateLimit::per_second(50))
+///         .per_method("transfer", RateLimit::per_minute(10))
+///         .with_metrics(metrics.clone()),
+/// );
+/// let tcp = TcpServerConfig::new(addr, server.clone()).with_rate_limit(limiter.clone());
+/// let http = HttpTransport::new(server).with_rate_limit(limiter);
+/// ```
+#[derive(Default)]
+pub struct RateLimiter {
+    per_key: Option<RateLimit>,
+    per_ip: Option<RateLimit>,
+    per_method: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<BucketKey, Bucket>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl RateLimiter {
+    /// A limiter with no limits configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit each authenticated credential
+    pub fn per_key(mut self, limit: RateLimit) -> Self {
+        self.per_key = Some(limit);
+        self
+    }
+
+    /// Limit each peer IP address
+    pub fn per_ip(mut self, limit: RateLimit) -> Self {
+        self.per_ip = Some(limit);
+        self
+    }
+
+    /// Limit each caller of `method`
+    pub fn per_method(mut self, method: impl Into<String>, limit: RateLimit) -> Self {
+        self.per_method.insert(method.into(), limit);
+        self
+    }
+
+    /// Count rejected requests in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Take one token for `method` from every bucket that applies, or fail
+    /// without taking any
+    pub fn check(&self, method: &str, ctx: &RequestContext) -> Result<(), RpcError> {
+        self.check_stage(method, ctx, Stage::All, true)
+    }
+
+    /// A layer checking only the per-IP limit, to run before authentication
+    pub fn peer_layer(self: &Arc<Self>) -> Arc<dyn RpcMiddleware> {
+        Arc::new(RateLimitLayer {
+            limiter: self.clone(),
+            stage: Stage::Peer,
+        })
+    }
+
+    /// A layer checking the per-key and per-method limits, to run after
+    /// authentication
+    pub fn caller_layer(self: &Arc<Self>) -> Arc<dyn RpcMiddleware> {
+        Arc::new(RateLimitLayer {
+            limiter: self.clone(),
+            stage: Stage::Caller,
+        })
+    }
+
+    /// Fail if the per-IP bucket is empty, without taking a token. HTTP
+    /// header auth runs outside the layer stack, so it checks this first
+    /// and charges failed attempts with `charge_peer`
+    pub(crate) async fn admit_peer(&self, ctx: &RequestContext) -> Result<(), RpcError> {
+        let result = self.check_stage("", ctx, Stage::Peer, false);
+        if let Err(err) = &result {
+            self.record(err).await;
+        }
+        result
+    }
+
+    /// Take a per-IP token for a request that never reached the layers
+    pub(crate) fn charge_peer(&self, ctx: &RequestContext) {
+        let _ = self.acquire("", ctx, Stage::Peer, true);
+    }
+
+    fn check_stage(
+        &self,
+        method: &str,
+        ctx: &RequestContext,
+        stage: Stage,
+        take: bool,
+    ) -> Result<(), RpcError> {
+        self.acquire(method, ctx, stage, take)
+            .map_err(|(limit, wait)| {
+                RpcError::new(RATE_LIMITED, "Rate limit exceeded").with_data(json!({
+                    "limit": limit,
+                    "method": method,
+                    "retry_after_ms": wait.as_millis().min(u64::MAX as u128) as u64,
+                }))
+            })
+    }
+
+    /// On rejection, the kind of limit hit and how long until it frees up.
+    /// With `take` unset the buckets are only inspected
+    fn acquire(
+        &self,
+        method: &str,
+        ctx: &RequestContext,
+        stage: Stage,
+        take: bool,
+    ) -> Result<(), (&'static str, Duration)> {
+        let peer = stage != Stage::Caller;
+        let caller = stage != Stage::Peer;
+        let principal = ctx.principal.as_ref().map(credential);
+        let ip = ctx.peer_addr.map(|addr| addr.ip());
+
+        let mut applicable: Vec<(BucketKey, &RateLimit, &'static str)> = Vec::new();
+        if let (Some(limit), Some(id)) = (&self.per_key, &principal)
+            && caller
+        {
+            applicable.push((BucketKey::Key(id.clone()), limit, "key"));
+        }
+        if let (Some(limit), Some(ip)) = (&self.per_ip, ip)
+            && peer
+        {
+            applicable.push((BucketKey::Ip(ip), limit, "ip"));
+        }
+        if let Some(limit) = self.per_method.get(method).filter(|_| caller) {
+            let caller = principal
+                .or_else(|| ip.map(|ip| ip.to_string()))
+                .unwrap_or_default();
+            applicable.push((
+                BucketKey::Method(method.to_string(), caller),
+                limit,
+                "method",
+            ));
+        }
+        if applicable.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            self.prune(&mut buckets, now);
+        }
+
+        let mut rejected: Option<(&'static str, Duration)> = None;
+        for (key, limit, kind) in &applicable {
+            let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+                tokens: limit.burst,
+                updated: now,
+            });
+            bucket.refill(limit, now);
+            if bucket.tokens < 1.0 {
+                let wait = bucket.wait(limit);
+                if rejected.is_none_or(|(_, longest)| wait > longest) {
+                    rejected = Some((kind, wait));
+                }
+            }
+        }
+        if let Some(rejected) = rejected {
+            return Err(rejected);
+        }
+        if !take {
+            return Ok(());
+        }
+
+        for (key, _, _) in &applicable {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop buckets that have refilled completely; they are recreated full
+    fn prune(&self, buckets: &mut HashMap<BucketKey, Bucket>, now: Instant) {
+        buckets.retain(|key, bucket| {
+            let limit = match key {
+                BucketKey::Key(_) => self.per_key.as_ref(),
+                BucketKey::Ip(_) => self.per_ip.as_ref(),
+                BucketKey::Method(method, _) => self.per_method.get(method),
+            };
+            match limit {
+                Some(limit) => {
+                    bucket.refill(limit, now);
+                    bucket.tokens < limit.burst
+                }
+                None => false,
+            }
+        });
+    }
+}
+
+impl RateLimiter {
+    async fn limit<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+        stage: Stage,
+    ) -> Result<serde_json::Value, RpcError> {
+        if let Err(err) = self.check_stage(&req.method, &ctx, stage, true) {
+            self.record(&err).await;
+            return Err(err);
+        }
+        next.run(req, ctx).await
+    }
+
+    async fn record(&self, err: &RpcError) {
+        if let Some(metrics) = &self.metrics {
+            let limit = err.data.as_ref().and_then(|d| d["limit"].as_str());
+            metrics.record_rate_limited(limit.unwrap_or_default()).await;
+        }
+    }
+}
+
+impl RpcMiddleware for RateLimiter {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(self.limit(req, ctx, next, Stage::All))
+    }
+}
+
+/// One stage of a shared `RateLimiter`; see `RateLimiter::peer_layer`
+struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+    stage: Stage,
+}
+
+impl RpcMiddleware for RateLimitLayer {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(self.limiter.limit(req, ctx, next, self.stage))
+    }
+}
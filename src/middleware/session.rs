@@ -0,0 +1,138 @@
+use crate::middleware::auth::{AUTH_REQUIRED, AuthMiddleware, Principal};
+use crate::middleware::pipeline::{MiddlewareFuture, Next, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcError, RpcRequest};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Method a client calls once per connection to open a session
+pub const SESSION_AUTH_METHOD: &str = "rpc.auth";
+/// Method that ends the current session
+pub const SESSION_LOGOUT_METHOD: &str = "rpc.logout";
+
+struct Session {
+    principal: Principal,
+    expires_at: Instant,
+}
+
+/// Per-connection authentication state for persistent transports.
+///
+/// A client calls `rpc.auth` with its credentials (e.g. `{"api_key": "..."}`)
+/// and every later request on the same connection runs as that principal
+/// without resending them. The session lasts `ttl`; after that requests
+/// without credentials fail with `AUTH_REQUIRED` until `rpc.auth` is called
+/// again. Calling `rpc.auth` while a session is open re-authenticates.
+/// A session opened with a key from the key store ends as soon as that key
+/// is revoked or removed.
+///
+/// Entries of the batch that carries `rpc.auth` run concurrently with it, so
+/// the session only applies from the next message on.
+pub struct SessionMiddleware {
+    auth: Arc<AuthMiddleware>,
+    ttl: Duration,
+    session: Mutex<Option<Session>>,
+}
+
+impl SessionMiddleware {
+    pub fn new(auth: Arc<AuthMiddleware>, ttl: Duration) -> Self {
+        Self {
+            auth,
+            ttl,
+            session: Mutex::new(None),
+        }
+    }
+
+    /// The principal of the open session, if it has not expired
+    pub fn principal(&self) -> Option<Principal> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|s| s.expires_at > Instant::now())
+            .map(|s| s.principal.clone())
+    }
+
+    async fn open(
+        &self,
+        req: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<serde_json::Value, RpcError> {
+        let principal = self
+            .auth
+            .authenticate_with_context(req, ctx)
+            .await?
+            .ok_or_else(|| RpcError::new(AUTH_REQUIRED, "No credentials to open a session"))?;
+
+        // A session never outlives the credential that opened it
+        let ttl = principal
+            .expires_at
+            .and_then(|at| at.duration_since(SystemTime::now()).ok())
+            .map_or(self.ttl, |left| left.min(self.ttl));
+
+        let reply = json!({
+            "principal": principal.id,
+            "expires_in": ttl.as_secs(),
+        });
+        *self.session.lock().unwrap() = Some(Session {
+            principal,
+            expires_at: Instant::now() + ttl,
+        });
+        Ok(reply)
+    }
+
+    /// Resolve the session for a regular request, dropping it once expired
+    fn current(&self) -> Result<Option<Principal>, RpcError> {
+        let mut session = self.session.lock().unwrap();
+        match session.as_ref() {
+            Some(s) if s.expires_at > Instant::now() => Ok(Some(s.principal.clone())),
+            Some(_) => {
+                *session = None;
+                Err(RpcError::new(AUTH_REQUIRED, "Session expired")
+                    .with_data(json!({ "reauthenticate": SESSION_AUTH_METHOD })))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl RpcMiddleware for SessionMiddleware {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        mut ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            match req.method.as_str() {
+                SESSION_AUTH_METHOD => return self.open(&req, &ctx).await,
+                SESSION_LOGOUT_METHOD => {
+                    let closed = self.session.lock().unwrap().take().is_some();
+                    return Ok(json!({ "closed": closed }));
+                }
+                _ => {}
+            }
+
+            match self.current() {
+                Ok(Some(principal)) => {
+                    if let Some(key_id) = &principal.key_id
+                        && let Err(err) = self.auth.key_store().check_id(key_id).await
+                    {
+                        self.session.lock().unwrap().take();
+                        return Err(err);
+                    }
+                    ctx.principal = Some(principal);
+                }
+                Ok(None) => {}
+                Err(expired) => {
+                    // Credentials sent alongside an expired session still
+                    // count; the auth layer checks them
+                    return match next.run(req, ctx).await {
+                        Err(err) if err.code == AUTH_REQUIRED => Err(expired),
+                        result => result,
+                    };
+                }
+            }
+            next.run(req, ctx).await
+        })
+    }
+}
@@ -0,0 +1,330 @@
+use crate::middleware::auth::{AUTH_ERROR, AUTH_REQUIRED, Principal};
+use crate::middleware::ratelimit::RATE_LIMITED;
+use crate::rpc::{RequestContext, RpcError};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// HTTP header naming the signing key
+pub const SIGNATURE_KEY_HEADER: &str = "x-signature-key";
+/// HTTP header carrying the request time in Unix seconds
+pub const SIGNATURE_TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// HTTP header carrying the single-use nonce
+pub const SIGNATURE_NONCE_HEADER: &str = "x-signature-nonce";
+/// HTTP header carrying the hex HMAC-SHA256 signature
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+/// Every header a signed HTTP request uses
+pub const SIGNATURE_HEADERS: [&str; 4] = [
+    SIGNATURE_KEY_HEADER,
+    SIGNATURE_TIMESTAMP_HEADER,
+    SIGNATURE_NONCE_HEADER,
+    SIGNATURE_HEADER,
+];
+
+/// Sign `body` as sent at `timestamp` with `nonce`; returns lowercase hex.
+///
+/// The signed message is `"{timestamp}\n{nonce}\n"` followed by the exact
+/// body bytes, so any change to the request invalidates the signature.
+pub fn sign_request(secret: &[u8], timestamp: u64, nonce: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, &signing_input(timestamp, nonce, body));
+    tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn signing_input(timestamp: u64, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut input = format!("{}\n{}\n", timestamp, nonce).into_bytes();
+    input.extend_from_slice(body);
+    input
+}
+
+/// A signature and the request it covers, from headers or a TCP envelope
+pub struct SignedRequest<'a> {
+    pub key_id: &'a str,
+    pub timestamp: u64,
+    pub nonce: &'a str,
+    pub signature: &'a str,
+    pub body: &'a [u8],
+}
+
+impl<'a> SignedRequest<'a> {
+    /// Read the signature headers of an HTTP request whose body is `body`
+    pub fn from_headers(ctx: &'a RequestContext, body: &'a [u8]) -> Result<Self, RpcError> {
+        let header = |name: &str| {
+            ctx.header(name).ok_or_else(|| {
+                RpcError::new(AUTH_REQUIRED, "Signed request required")
+                    .with_data(json!({ "missing_header": name }))
+            })
+        };
+        Ok(Self {
+            key_id: header(SIGNATURE_KEY_HEADER)?,
+            timestamp: header(SIGNATURE_TIMESTAMP_HEADER)?
+                .trim()
+                .parse()
+                .map_err(|_| rejected("malformed timestamp"))?,
+            nonce: header(SIGNATURE_NONCE_HEADER)?,
+            signature: header(SIGNATURE_HEADER)?,
+            body,
+        })
+    }
+}
+
+/// Framed-TCP wrapper for a signed message: the JSON-RPC request (or batch)
+/// travels as the exact string that was signed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedEnvelope {
+    pub key_id: String,
+    pub timestamp: u64,
+    pub nonce: String,
+    pub signature: String,
+    pub payload: String,
+}
+
+impl SignedEnvelope {
+    /// Sign `payload` now with a fresh random nonce
+    pub fn sign(key_id: impl Into<String>, secret: &[u8], payload: impl Into<String>) -> Self {
+        let payload = payload.into();
+        let timestamp = unix_now();
+        let mut nonce = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .expect("system random number generator failed");
+        let nonce: String = nonce.iter().map(|b| format!("{:02x}", b)).collect();
+        Self {
+            key_id: key_id.into(),
+            signature: sign_request(secret, timestamp, &nonce, payload.as_bytes()),
+            timestamp,
+            nonce,
+            payload,
+        }
+    }
+
+    /// Recognise an envelope frame; plain JSON-RPC frames yield `None`
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        serde_json::from_slice(frame).ok()
+    }
+
+    pub fn request(&self) -> SignedRequest<'_> {
+        SignedRequest {
+            key_id: &self.key_id,
+            timestamp: self.timestamp,
+            nonce: &self.nonce,
+            signature: &self.signature,
+            body: self.payload.as_bytes(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SigningKey {
+    key: hmac::Key,
+    scopes: Vec<String>,
+}
+
+/// Recently seen nonces, bounded by `capacity`.
+///
+/// Nonces only need remembering while their timestamp is inside the skew
+/// window, so expired ones are evicted. A nonce still inside it is never
+/// dropped, as it could then be replayed: while the cache is full of them,
+/// new requests are refused. Size it above the peak request rate times the
+/// window.
+struct NonceCache {
+    capacity: usize,
+    seen: HashSet<(String, String)>,
+    order: VecDeque<(u64, String, String)>,
+}
+
+/// What `NonceCache::insert` made of a nonce
+enum NonceCheck {
+    Fresh,
+    Replayed,
+    /// Full of nonces inside the window; the first leaves it in this many
+    /// seconds
+    Full(u64),
+}
+
+impl NonceCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Remember `nonce` unless it was already used or there is no room
+    fn insert(
+        &mut self,
+        key_id: &str,
+        nonce: &str,
+        timestamp: u64,
+        oldest_valid: u64,
+    ) -> NonceCheck {
+        let entry = (key_id.to_string(), nonce.to_string());
+        if self.seen.contains(&entry) {
+            return NonceCheck::Replayed;
+        }
+        while let Some((ts, _, _)) = self.order.front()
+            && *ts < oldest_valid
+        {
+            if let Some((_, k, n)) = self.order.pop_front() {
+                self.seen.remove(&(k, n));
+            }
+        }
+        if self.order.len() >= self.capacity {
+            // Timestamps arrive out of order, so expired nonces may sit
+            // behind the first live one
+            let seen = &mut self.seen;
+            self.order.retain(|(ts, k, n)| {
+                let live = *ts >= oldest_valid;
+                if !live {
+                    seen.remove(&(k.clone(), n.clone()));
+                }
+                live
+            });
+        }
+        if self.order.len() >= self.capacity {
+            let first = self.order.iter().map(|(ts, _, _)| *ts).min();
+            return NonceCheck::Full(first.unwrap_or(oldest_valid) + 1 - oldest_valid);
+        }
+        self.order
+            .push_back((timestamp, entry.0.clone(), entry.1.clone()));
+        self.seen.insert(entry);
+        NonceCheck::Fresh
+    }
+}
+
+/// Shared secrets and replay settings for `AuthStrategy::HmacSigned`
+///
+/// ```no_run
+/// # use dice_rpc::middleware::{AuthMiddleware, AuthStrategy, HmacConfig};
+/// # use std::time::Duration;
+/// let hmac = HmacConfig::new()
+///     .with_key("billing-svc", b"shared-secret", ["write"])
+///     .with_max_skew(Duration::from_secs(60));
+/// let auth = AuthMiddleware::new(AuthStrategy::HmacSigned(hmac));
+/// ```
+#[derive(Clone)]
+pub struct HmacConfig {
+    keys: Arc<HashMap<String, SigningKey>>,
+    max_skew: Duration,
+    nonces: Arc<Mutex<NonceCache>>,
+}
+
+impl Default for HmacConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HmacConfig {
+    /// No keys, a 5 minute skew window and room for 100k nonces
+    pub fn new() -> Self {
+        Self {
+            keys: Arc::new(HashMap::new()),
+            max_skew: Duration::from_secs(300),
+            nonces: Arc::new(Mutex::new(NonceCache::new(100_000))),
+        }
+    }
+
+    /// Accept signatures by `secret` under `key_id`; the caller runs as
+    /// principal `key_id` with `scopes`
+    pub fn with_key<S: Into<String>>(
+        mut self,
+        key_id: impl Into<String>,
+        secret: impl AsRef<[u8]>,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let key = SigningKey {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref()),
+            scopes: scopes.into_iter().map(Into::into).collect(),
+        };
+        Arc::make_mut(&mut self.keys).insert(key_id.into(), key);
+        self
+    }
+
+    /// Reject timestamps further than `max_skew` from the server clock
+    pub fn with_max_skew(mut self, max_skew: Duration) -> Self {
+        self.max_skew = max_skew;
+        self
+    }
+
+    /// Remember at most `capacity` nonces. Once that many are inside the
+    /// skew window, requests fail with `RATE_LIMITED` until one leaves it.
+    pub fn with_nonce_capacity(mut self, capacity: usize) -> Self {
+        self.nonces = Arc::new(Mutex::new(NonceCache::new(capacity.max(1))));
+        self
+    }
+
+    /// Verify a signed request and return the principal it was signed as
+    pub fn verify(&self, req: &SignedRequest<'_>) -> Result<Principal, RpcError> {
+        let key = self
+            .keys
+            .get(req.key_id)
+            .ok_or_else(|| rejected("unknown key"))?;
+
+        let now = unix_now();
+        let skew = self.max_skew.as_secs();
+        if req.timestamp.abs_diff(now) > skew {
+            return Err(RpcError::new(AUTH_ERROR, "Stale request timestamp")
+                .with_data(json!({ "reason": "stale_timestamp", "server_time": now })));
+        }
+
+        let signature = decode_hex(req.signature).ok_or_else(|| rejected("malformed signature"))?;
+        let input = signing_input(req.timestamp, req.nonce, req.body);
+        if hmac::verify(&key.key, &input, &signature).is_err() {
+            return Err(rejected("bad signature"));
+        }
+
+        // Only remember nonces of genuine requests, so forgeries cannot
+        // flush the cache
+        let check = self.nonces.lock().unwrap().insert(
+            req.key_id,
+            req.nonce,
+            req.timestamp,
+            now.saturating_sub(skew),
+        );
+        match check {
+            NonceCheck::Fresh => {}
+            NonceCheck::Replayed => {
+                return Err(RpcError::new(AUTH_ERROR, "Replayed request")
+                    .with_data(json!({ "reason": "replayed_nonce" })));
+            }
+            NonceCheck::Full(wait) => {
+                return Err(
+                    RpcError::new(RATE_LIMITED, "Too many signed requests").with_data(json!({
+                        "reason": "nonce_cache_full",
+                        "retry_after_ms": wait * 1000,
+                    })),
+                );
+            }
+        }
+
+        Ok(Principal::new(req.key_id, "hmac").with_scopes(key.scopes.clone()))
+    }
+}
+
+fn rejected(reason: &str) -> RpcError {
+    RpcError::new(AUTH_ERROR, "Invalid signature").with_data(json!({ "reason": reason }))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
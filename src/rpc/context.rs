@@ -0,0 +1,132 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::middleware::auth::Principal;
+
+/// Which transport a request arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    /// In-process call (tests, embedding) with no network peer
+    Local,
+    /// TCP with 4-byte length-prefixed frames
+    TcpFramed,
+    /// Legacy newline-delimited TCP
+    TcpLine,
+    /// HTTP POST via axum
+    Http,
+    /// Messages on an HTTP `/ws` WebSocket connection
+    WebSocket,
+    /// Unix domain socket with 4-byte length-prefixed frames
+    Unix,
+    /// The process's stdin and stdout, as a child of the client
+    Stdio,
+}
+
+/// Request-scoped type map for passing data between middleware and handlers
+///
+/// Values are stored behind an `Arc` so the map stays cheap to clone when a
+/// batch fans out into one context per entry.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value, replacing any previous value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Get a reference to a value of type `T`
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref::<T>())
+    }
+
+    /// Remove a value of type `T`, returning whether one was present
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+/// Per-request information handed to context-aware handlers
+///
+/// Transports fill in what they know (peer address, headers); the auth layer
+/// adds the authenticated principal before the handler runs.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Remote address of the caller, if the transport has one
+    pub peer_addr: Option<SocketAddr>,
+    /// Transport the request arrived on
+    pub transport: TransportKind,
+    /// HTTP headers (lower-cased names), `None` on non-HTTP transports
+    pub headers: Option<HashMap<String, String>>,
+    /// Identity established by `AuthMiddleware`, if any
+    pub principal: Option<Principal>,
+    /// Arbitrary request-scoped data
+    pub extensions: Extensions,
+}
+
+impl RequestContext {
+    pub fn new(transport: TransportKind) -> Self {
+        Self {
+            peer_addr: None,
+            transport,
+            headers: None,
+            principal: None,
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+
+    pub fn with_principal(mut self, principal: Principal) -> Self {
+        self.principal = Some(principal);
+        self
+    }
+
+    /// Look up an HTTP header by (case-insensitive) name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .as_ref()
+            .and_then(|h| h.get(&name.to_ascii_lowercase()))
+            .map(String::as_str)
+    }
+}
+
+impl Default for RequestContext {
+    fn default() -> Self {
+        Self::new(TransportKind::Local)
+    }
+}
@@ -0,0 +1,154 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+use super::rpc::{
+    INTERNAL_ERROR, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, RpcErrorObj,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Error type returned by RPC handlers
+///
+/// Carries the JSON-RPC `code`, `message` and optional `data` that are sent
+/// to the client, plus an optional `source` chain that is only logged on the
+/// server. Converting from `anyhow::Error` maps to `INTERNAL_ERROR` with a
+/// generic message, so internal details never reach the caller.
+///
+/// ```no_run
+/// # use dice_rpc::{OptionExt, RpcError};
+/// # use serde_json::{Value, json};
+/// # struct Db;
+/// # impl Db {
+/// #     async fn lookup(&self, name: &str) -> anyhow::Result<String> { Ok(name.to_string()) }
+/// # }
+/// # static DB: Db = Db;
+/// async fn handler(params: Value) -> Result<Value, RpcError> {
+///     let name = params["name"].as_str().ok_or_invalid_params()?;
+///     let row = DB.lookup(name).await?; // anyhow::Error -> -32603
+///     Ok(json!(row))
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+    source: Option<BoxError>,
+}
+
+impl RpcError {
+    /// Create an error with an arbitrary code
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+            source: None,
+        }
+    }
+
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self::new(PARSE_ERROR, message)
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(INVALID_REQUEST, message)
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(INTERNAL_ERROR, message)
+    }
+
+    /// Application-defined error. The spec reserves -32768..=-32000 for the
+    /// server, so application codes are usually chosen in -32099..=-32000 or
+    /// outside that range entirely.
+    pub fn application(code: i64, message: impl Into<String>) -> Self {
+        Self::new(code, message)
+    }
+
+    /// Attach a typed `data` payload; it is serialized into the response
+    pub fn with_data<T: Serialize>(mut self, data: T) -> Self {
+        self.data = serde_json::to_value(data).ok();
+        self
+    }
+
+    /// Attach the underlying cause; it is logged but never sent to the client
+    pub fn with_source<E>(mut self, source: E) -> Self
+    where
+        E: Into<BoxError>,
+    {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Render the message followed by the full source chain, for logging
+    pub fn chain(&self) -> String {
+        let mut out = self.to_string();
+        let mut next = std::error::Error::source(self);
+        while let Some(err) = next {
+            out.push_str(": ");
+            out.push_str(&err.to_string());
+            next = err.source();
+        }
+        out
+    }
+
+    /// The wire representation of this error
+    pub fn to_error_obj(&self) -> RpcErrorObj {
+        RpcErrorObj {
+            code: self.code,
+            message: self.message.clone(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        RpcError::internal("Internal error").with_source(err)
+    }
+}
+
+impl From<RpcErrorObj> for RpcError {
+    fn from(obj: RpcErrorObj) -> Self {
+        Self {
+            code: obj.code,
+            message: obj.message,
+            data: obj.data,
+            source: None,
+        }
+    }
+}
+
+impl From<RpcError> for RpcErrorObj {
+    fn from(err: RpcError) -> Self {
+        RpcErrorObj {
+            code: err.code,
+            message: err.message,
+            data: err.data,
+        }
+    }
+}
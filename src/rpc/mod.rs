@@ -1,2 +1,9 @@
+#[allow(clippy::module_inception)]
 pub mod rpc;        // request/response
 pub use rpc::*;
+pub mod context;
+pub use context::*;
+pub mod error;
+pub use error::RpcError;
+pub mod pubsub;
+pub use pubsub::{Overflow, Subscriber, SubscriptionLimits, TopicStream};
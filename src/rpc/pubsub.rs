@@ -0,0 +1,250 @@
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+use super::context::RequestContext;
+use super::error::RpcError;
+
+/// Method clients call to open a subscription
+pub const SUBSCRIBE_METHOD: &str = "subscribe";
+/// Method clients call to close one
+pub const UNSUBSCRIBE_METHOD: &str = "unsubscribe";
+/// Method of the notifications the server pushes for a subscription
+pub const SUBSCRIPTION_METHOD: &str = "subscription";
+
+/// Items published on one subscription
+pub type TopicStream = BoxStream<'static, Value>;
+
+type TopicFactory = Arc<dyn Fn(Value) -> Result<TopicStream, RpcError> + Send + Sync>;
+
+/// What happens when a subscriber's buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Skip notifications until there is room; the next one delivered
+    /// carries `"dropped": n` so the client knows to resync
+    DropNewest,
+    /// Close the connection, like a Redis client over its output limit
+    Disconnect,
+}
+
+/// Per-connection bounds on subscriptions
+#[derive(Debug, Clone, Copy)]
+pub struct SubscriptionLimits {
+    /// Notifications queued for a connection before `overflow` applies
+    pub buffer: usize,
+    pub overflow: Overflow,
+    /// Open subscriptions allowed on one connection
+    pub max_per_connection: usize,
+}
+
+impl Default for SubscriptionLimits {
+    fn default() -> Self {
+        Self {
+            buffer: 256,
+            overflow: Overflow::DropNewest,
+            max_per_connection: 64,
+        }
+    }
+}
+
+/// Topics registered on a server, and the limits its transports apply
+#[derive(Default)]
+pub(crate) struct Topics {
+    factories: RwLock<HashMap<String, TopicFactory>>,
+    limits: RwLock<SubscriptionLimits>,
+}
+
+impl Topics {
+    pub(crate) fn insert(&self, name: &str, factory: TopicFactory) {
+        self.factories
+            .write()
+            .unwrap()
+            .insert(name.to_string(), factory);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.factories.read().unwrap().is_empty()
+    }
+
+    pub(crate) fn limits(&self) -> SubscriptionLimits {
+        *self.limits.read().unwrap()
+    }
+
+    pub(crate) fn set_limits(&self, limits: SubscriptionLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// `subscribe` params: `{"topic": ..., "params": ...}` or `[topic, params]`
+    pub(crate) fn subscribe(&self, params: Value, ctx: &RequestContext) -> Result<Value, RpcError> {
+        let subscriber = connection_subscriber(ctx)?;
+        let (topic, topic_params) = match params {
+            Value::Array(mut items) if !items.is_empty() => {
+                let topic_params = if items.len() > 1 {
+                    items.remove(1)
+                } else {
+                    Value::Null
+                };
+                (items.remove(0), topic_params)
+            }
+            Value::Object(mut map) => (
+                map.remove("topic").unwrap_or_default(),
+                map.remove("params").unwrap_or_default(),
+            ),
+            _ => (Value::Null, Value::Null),
+        };
+        let topic = topic
+            .as_str()
+            .ok_or_else(|| RpcError::invalid_params("Missing subscription topic"))?;
+
+        let factory = self.factories.read().unwrap().get(topic).cloned();
+        let Some(factory) = factory else {
+            let mut topics: Vec<String> = self.factories.read().unwrap().keys().cloned().collect();
+            topics.sort();
+            return Err(
+                RpcError::invalid_params(format!("Unknown subscription topic: {}", topic))
+                    .with_data(json!({ "topics": topics })),
+            );
+        };
+        let stream = factory(topic_params)?;
+        subscriber.subscribe(stream).map(Value::String)
+    }
+
+    /// `unsubscribe` params: `{"subscription": id}` or `[id]`
+    pub(crate) fn unsubscribe(params: Value, ctx: &RequestContext) -> Result<Value, RpcError> {
+        let subscriber = connection_subscriber(ctx)?;
+        let id = match &params {
+            Value::Array(items) => items.first(),
+            Value::Object(map) => map.get("subscription"),
+            _ => None,
+        }
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params("Missing subscription id"))?;
+        Ok(Value::Bool(subscriber.unsubscribe(id)))
+    }
+}
+
+fn connection_subscriber(ctx: &RequestContext) -> Result<&Subscriber, RpcError> {
+    ctx.extensions.get::<Subscriber>().ok_or_else(|| {
+        RpcError::invalid_request(
+            "Subscriptions need a persistent connection (framed TCP or WebSocket)",
+        )
+    })
+}
+
+struct SubscriberInner {
+    tx: mpsc::Sender<Value>,
+    limits: SubscriptionLimits,
+    active: Mutex<HashMap<String, AbortHandle>>,
+    overflowed: Arc<Notify>,
+}
+
+impl Drop for SubscriberInner {
+    fn drop(&mut self) {
+        for (_, task) in self.active.lock().unwrap().drain() {
+            task.abort();
+        }
+    }
+}
+
+/// The subscriptions of one connection.
+///
+/// A transport creates one per connection with `RpcServer::subscriber`,
+/// puts it in the request extensions for `subscribe`/`unsubscribe`, and
+/// writes out what arrives on the paired receiver. Every subscription ends
+/// when the last clone is dropped.
+#[derive(Clone)]
+pub struct Subscriber {
+    inner: Arc<SubscriberInner>,
+}
+
+impl Subscriber {
+    /// A subscriber and the receiver of its notifications, which are
+    /// complete JSON-RPC notification objects
+    pub fn new(limits: SubscriptionLimits) -> (Self, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel(limits.buffer.max(1));
+        let inner = SubscriberInner {
+            tx,
+            limits,
+            active: Mutex::new(HashMap::new()),
+            overflowed: Arc::new(Notify::new()),
+        };
+        (
+            Self {
+                inner: Arc::new(inner),
+            },
+            rx,
+        )
+    }
+
+    /// Forward `stream` as notifications under a new subscription id
+    pub fn subscribe(&self, mut stream: TopicStream) -> Result<String, RpcError> {
+        let mut active = self.inner.active.lock().unwrap();
+        if active.len() >= self.inner.limits.max_per_connection {
+            return Err(RpcError::invalid_request("Too many subscriptions")
+                .with_data(json!({ "max_per_connection": self.inner.limits.max_per_connection })));
+        }
+
+        let id = format!("sub_{}", Uuid::new_v4().simple());
+        let tx = self.inner.tx.clone();
+        let overflow = self.inner.limits.overflow;
+        let overflowed = self.inner.overflowed.clone();
+        let subscription = id.clone();
+        let task = tokio::spawn(async move {
+            let mut dropped: u64 = 0;
+            while let Some(result) = stream.next().await {
+                let mut params = json!({ "subscription": subscription, "result": result });
+                if dropped > 0 {
+                    params["dropped"] = json!(dropped);
+                }
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": SUBSCRIPTION_METHOD,
+                    "params": params,
+                });
+                match tx.try_send(notification) {
+                    Ok(()) => dropped = 0,
+                    Err(TrySendError::Full(_)) if overflow == Overflow::DropNewest => dropped += 1,
+                    Err(TrySendError::Full(_)) => {
+                        overflowed.notify_one();
+                        break;
+                    }
+                    Err(TrySendError::Closed(_)) => break,
+                }
+            }
+        });
+        active.insert(id.clone(), task.abort_handle());
+        Ok(id)
+    }
+
+    /// Cancel subscription `id`; `false` if it is not open here
+    pub fn unsubscribe(&self, id: &str) -> bool {
+        match self.inner.active.lock().unwrap().remove(id) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Open subscriptions
+    pub fn len(&self) -> usize {
+        self.inner.active.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Resolves once a subscription overflowed under `Overflow::Disconnect`;
+    /// the transport should then close the connection
+    pub async fn overflowed(&self) {
+        self.inner.overflowed.notified().await
+    }
+}
@@ -1,18 +1,96 @@
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use super::context::RequestContext;
+use super::error::RpcError;
+use super::pubsub::{
+    SUBSCRIBE_METHOD, Subscriber, SubscriptionLimits, TopicStream, Topics, UNSUBSCRIBE_METHOD,
+};
+use crate::middleware::pipeline::{Next, RpcMiddleware};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcRequest {
     pub jsonrpc: String,
     pub method: String,
     #[serde(default)]
     pub params: Value,
-    pub id: Value, // id can be string or number or null
+    /// `None` when the `id` member is absent (a notification),
+    /// `Some(Value::Null)` when the client explicitly sent `"id": null`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Value>, // id can be string or number or null
+}
+
+/// Deserializes a field that is known to be present, so that an explicit
+/// `null` becomes `Some(Value::Null)` rather than collapsing into `None`.
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
+impl RpcRequest {
+    /// A notification is a request without an `id` member.
+    /// The server must not reply to it, not even with an error.
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+
+    /// Validate a decoded JSON value against the JSON-RPC 2.0 request shape.
+    ///
+    /// On failure returns the `INVALID_REQUEST` response to send back. Its `id`
+    /// echoes the request's id when that id is itself well-formed, else `null`.
+    pub fn from_value(value: Value) -> Result<Self, RpcResponse> {
+        let obj = match value.as_object() {
+            Some(obj) => obj,
+            None => return Err(invalid_request(Value::Null, "expected a request object")),
+        };
+
+        let id = match obj.get("id") {
+            None => Value::Null,
+            Some(id @ (Value::Null | Value::String(_) | Value::Number(_))) => id.clone(),
+            Some(_) => {
+                return Err(invalid_request(
+                    Value::Null,
+                    "id must be a string, number or null",
+                ));
+            }
+        };
+
+        if obj.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Err(invalid_request(id, "jsonrpc must be exactly \"2.0\""));
+        }
+        if !obj.get("method").is_some_and(Value::is_string) {
+            return Err(invalid_request(id, "method must be a string"));
+        }
+        if obj
+            .get("params")
+            .is_some_and(|p| !p.is_object() && !p.is_array())
+        {
+            return Err(invalid_request(id, "params must be an object or an array"));
+        }
+
+        serde_json::from_value(value).map_err(|e| invalid_request(id, e.to_string()))
+    }
+}
+
+fn invalid_request(id: Value, detail: impl std::fmt::Display) -> RpcResponse {
+    RpcResponse::with_error(id, INVALID_REQUEST, format!("Invalid Request: {}", detail))
+}
+
+/// Build the `PARSE_ERROR` response for a payload that is not valid JSON
+pub fn parse_error(err: impl std::fmt::Display) -> RpcResponse {
+    RpcResponse::with_error(Value::Null, PARSE_ERROR, format!("Parse error: {}", err))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,12 +106,17 @@ pub struct RpcResponse {
     pub jsonrpc: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<RpcErrorObj>,
     pub id: Value,
 }
 
-pub const METHOD_NOT_FOUND: i64 = -32602;
+/// Standard JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
 pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
 
 /// Helper methods for constructing JSON-RPC 2.0 responses.
 ///
@@ -50,7 +133,9 @@ impl RpcResponse {
     /// * `res` - The result value returned from the RPC call.
     ///
     /// # Example
-    /// ```rust
+    /// ```
+    /// # use dice_rpc::RpcResponse;
+    /// # let request_id = serde_json::json!(1);
     /// let res = RpcResponse::with_result(request_id, serde_json::json!({"balance": 100}));
     /// ```
     pub fn with_result(id: Value, res: Value) -> Self {
@@ -70,10 +155,11 @@ impl RpcResponse {
     /// * `message` - A human-readable error message describing the problem.
     ///
     /// # Example
-    /// ```rust
+    /// ```
+    /// # use dice_rpc::RpcResponse;
+    /// # let request_id = serde_json::json!(1);
     /// let err = RpcResponse::with_error(request_id, -32601, "Method not found");
     /// ```
-
     pub fn with_error(id: Value, code: i64, message: impl Into<String>) -> Self {
         RpcResponse {
             jsonrpc: "2.0".to_string(),
@@ -86,13 +172,76 @@ impl RpcResponse {
             id,
         }
     }
+
+    /// Constructs an error JSON-RPC response from a full `RpcErrorObj`,
+    /// keeping its `data` member intact.
+    pub fn with_error_obj(id: Value, error: RpcErrorObj) -> Self {
+        RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
 }
 
-// A Handler is an async function that takes params and returns Result<Value, RpcErrorObj>.
-pub type Handler = dyn Fn(Value) -> HandlerFuture + Send + Sync + 'static;
+// A Handler is an async function that takes params plus the request context
+// and returns Result<Value, RpcError>.
+pub type Handler = dyn Fn(Value, RequestContext) -> HandlerFuture + Send + Sync + 'static;
 pub type HandlerFuture =
-    std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value, RpcErrorObj>> + Send>>;
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value, RpcError>> + Send>>;
+
+/// A handler over typed params `P` returning a serializable result `R`.
+///
+/// Implemented for every `Fn(P) -> impl Future<Output = Result<R, RpcError>>`,
+/// so plain async fns and closures can be passed to `RpcServer::register_typed`.
+pub trait TypedHandler<P, R>: Send + Sync + 'static {
+    type Future: std::future::Future<Output = Result<R, RpcError>> + Send + 'static;
+
+    fn call(&self, params: P) -> Self::Future;
+}
+
+impl<P, R, F, Fut> TypedHandler<P, R> for F
+where
+    F: Fn(P) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<R, RpcError>> + Send + 'static,
+{
+    type Future = Fut;
+
+    fn call(&self, params: P) -> Self::Future {
+        self(params)
+    }
+}
 
+/// Deserialize raw JSON-RPC params into `P`.
+///
+/// Structs accept both by-name (object) and by-position (array) params.
+/// Omitted params are tried as `null` first (for `()` / `Option<_>`) and then
+/// as an empty object (for structs whose fields all have defaults).
+/// Failures become `INVALID_PARAMS` with the offending path in `data`.
+pub fn decode_params<P: DeserializeOwned>(params: Value) -> Result<P, RpcError> {
+    let result = serde_path_to_error::deserialize::<_, P>(params.clone());
+    let err = match result {
+        Ok(p) => return Ok(p),
+        Err(err) => err,
+    };
+
+    if params.is_null()
+        && let Ok(p) = serde_json::from_value::<P>(Value::Object(Default::default()))
+    {
+        return Ok(p);
+    }
+
+    let path = err.path().to_string();
+    Err(
+        RpcError::invalid_params(format!("Invalid params: {}", err.inner())).with_data(
+            serde_json::json!({
+                "path": path,
+                "error": err.inner().to_string(),
+            }),
+        ),
+    )
+}
 
 /// Represents a lightweight asynchronous JSON-RPC server.
 ///
@@ -104,9 +253,15 @@ pub type HandlerFuture =
 /// # Fields
 /// - `handlers`: A thread-safe map from method names (`String`) to
 ///   their corresponding RPC handlers (`Arc<Handler>`).
-/// ```
+/// - `middleware`: Layers wrapped around every dispatch, outermost first.
+/// - `mutating`: Methods that change state, which middleware such as the
+///   audit log treats specially.
+/// - `topics`: Subscription topics, served over persistent transports.
 pub struct RpcServer {
     handlers: RwLock<HashMap<String, Arc<Handler>>>,
+    middleware: RwLock<Vec<Arc<dyn RpcMiddleware>>>,
+    mutating: RwLock<HashSet<String>>,
+    topics: Arc<Topics>,
 }
 
 /// Implementation of the core functionality for the `RpcServer`.
@@ -125,65 +280,270 @@ pub struct RpcServer {
 /// **`register()`**
 /// - Registers a new RPC method handler.
 /// - Takes a method name (`&str`) and an async function that accepts a `serde_json::Value`
-///   as input parameters and returns a `Result<Value, RpcErrorObj>`.
+///   as input parameters and returns a `Result<Value, RpcError>`.
 /// - The handler is boxed and wrapped in an `Arc` for shared ownership and inserted into the internal map.
 /// - Example:
-///   ```rust
+///   ```no_run
+///   # use serde_json::Value;
+///   # async fn example(server: dice_rpc::RpcServer) {
 ///   server.register("ping", |_params| async move {
 ///       Ok(Value::String("pong".into()))
 ///   }).await;
+///   # }
+///   ```
+///
+/// **`register_with_context()`**
+/// - Like `register()`, but the handler also receives the `RequestContext`.
+/// - Example:
+///   ```no_run
+///   # use serde_json::json;
+///   # async fn example(server: dice_rpc::RpcServer) {
+///   server.register_with_context("whoami", |_params, ctx| async move {
+///       Ok(json!({ "peer": ctx.peer_addr.map(|a| a.to_string()) }))
+///   }).await;
+///   # }
+///   ```
+///
+/// **`register_typed()`**
+/// - Like `register()`, but the handler takes a `P: DeserializeOwned` and returns an `R: Serialize`.
+/// - Example:
+///   ```no_run
+///   # use serde::Deserialize;
+///   # async fn example(server: dice_rpc::RpcServer) {
+///   #[derive(Deserialize)]
+///   struct Echo { msg: String }
+///
+///   server.register_typed("echo", |p: Echo| async move { Ok(p.msg) }).await;
+///   # }
 ///   ```
 ///
 /// **`handle_request()`**
 /// - Processes an incoming `RpcRequest` by matching its `method` against the registered handlers.
 /// - If found, it awaits the handler’s async execution and wraps the output into a `RpcResponse`.
 /// - On success, returns a response with the handler’s result.
-/// - On failure
+/// - On failure, returns a response carrying the handler’s error.
+/// - Notifications (requests without an `id`) are executed but yield `None`.
+///
+/// **`mark_mutating()`**
+/// - Flags methods that change state (`set_balance`, `transfer`, ...), so
+///   middleware can find them with `Next::is_mutating` and e.g. audit them.
+///
+/// **`add_middleware()`**
+/// - Wraps every dispatch, on every transport, in an `RpcMiddleware` layer.
+/// - Layers run in the order they were added; the first one added is outermost.
+///
+/// **`register_topic()`**
+/// - Publishes a stream clients can `subscribe` to over framed TCP or
+///   WebSocket; each item is pushed as a `subscription` notification.
+/// - Example:
+///   ```no_run
+///   # use futures::StreamExt;
+///   # use serde_json::json;
+///   # use std::time::Duration;
+///   # async fn example(server: dice_rpc::RpcServer) {
+///   server.register_topic("ticks", |_params| {
+///       let ticks = tokio::time::interval(Duration::from_secs(1));
+///       Ok(futures::stream::unfold(ticks, |mut ticks| async move {
+///           ticks.tick().await;
+///           Some((json!("tick"), ticks))
+///       })
+///       .boxed())
+///   }).await;
+///   # }
+///   ```
 impl RpcServer {
     pub fn new() -> Self {
         Self {
             handlers: RwLock::new(HashMap::new()),
+            middleware: RwLock::new(Vec::new()),
+            mutating: RwLock::new(HashSet::new()),
+            topics: Arc::new(Topics::default()),
         }
     }
 
+    /// Appends a middleware layer that every request passes through
+    pub async fn add_middleware(&self, layer: impl RpcMiddleware) {
+        self.middleware.write().await.push(Arc::new(layer));
+    }
+
+    /// Marks `methods` as state-mutating
+    pub async fn mark_mutating<S: Into<String>>(&self, methods: impl IntoIterator<Item = S>) {
+        self.mutating
+            .write()
+            .await
+            .extend(methods.into_iter().map(Into::into));
+    }
+
+    /// Whether `method` was marked with `mark_mutating`
+    pub async fn is_mutating(&self, method: &str) -> bool {
+        self.mutating.read().await.contains(method)
+    }
+
     pub async fn register<F, Fut>(&self, method: &str, f: F)
     where
         F: Fn(Value) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = Result<Value, RpcErrorObj>> + Send + 'static,
+        Fut: std::future::Future<Output = Result<Value, RpcError>> + Send + 'static,
+    {
+        self.register_with_context(method, move |params, _ctx| f(params))
+            .await;
+    }
+
+    /// Registers a handler that also receives the per-request `RequestContext`
+    /// (peer address, transport, headers, authenticated principal, extensions).
+    pub async fn register_with_context<F, Fut>(&self, method: &str, f: F)
+    where
+        F: Fn(Value, RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<Value, RpcError>> + Send + 'static,
     {
         //wrap into Arc<Handlers>
         let method_name = method.to_string();
-        let handler_arc: Arc<Handler> = Arc::new(move |params: Value| {
-            let fut = f(params);
+        let handler_arc: Arc<Handler> = Arc::new(move |params: Value, ctx: RequestContext| {
+            let fut = f(params, ctx);
             Box::pin(fut)
         });
 
         self.handlers.write().await.insert(method_name, handler_arc);
     }
 
-    pub async fn handle_request(&self, req: RpcRequest) -> RpcResponse {
-        let id = req.id.clone();
-        let handlers = self.handlers.read().await;
-        if let Some(h) = handlers.get(&req.method) {
-            // call handler
-            match (h)(req.params).await {
-                Ok(res) => RpcResponse::with_result(id, res),
-                Err(err) => RpcResponse::with_error(id, err.code, err.message),
+    /// Registers a handler over typed params and result.
+    ///
+    /// Params are deserialized into `P` before the handler runs; a mismatch is
+    /// answered with `INVALID_PARAMS` carrying the serde error path in `data`.
+    /// The handler's `R` is serialized back into the response `result`.
+    pub async fn register_typed<P, R>(&self, method: &str, f: impl TypedHandler<P, R>)
+    where
+        P: DeserializeOwned + Send + 'static,
+        R: Serialize + Send + 'static,
+    {
+        let f = Arc::new(f);
+        self.register(method, move |params: Value| {
+            let f = f.clone();
+            async move {
+                let params = decode_params::<P>(params)?;
+                let result = f.call(params).await?;
+                serde_json::to_value(result)
+                    .map_err(|e| RpcError::internal("Failed to serialize result").with_source(e))
             }
-        } else {
-            RpcResponse::with_error(
-                id,
-                METHOD_NOT_FOUND,
-                format!("Method not found: {}", req.method),
-            )
+        })
+        .await;
+    }
+
+    /// Registers a subscription topic, and the `subscribe`/`unsubscribe`
+    /// methods with it. `factory` gets the subscription's params and returns
+    /// the stream to push, or an error to refuse the subscription.
+    pub async fn register_topic<F>(&self, name: &str, factory: F)
+    where
+        F: Fn(Value) -> Result<TopicStream, RpcError> + Send + Sync + 'static,
+    {
+        self.topics.insert(name, Arc::new(factory));
+        let topics = self.topics.clone();
+        self.register_with_context(SUBSCRIBE_METHOD, move |params, ctx| {
+            let topics = topics.clone();
+            async move { topics.subscribe(params, &ctx) }
+        })
+        .await;
+        self.register_with_context(UNSUBSCRIBE_METHOD, |params, ctx| async move {
+            Topics::unsubscribe(params, &ctx)
+        })
+        .await;
+    }
+
+    /// Bounds applied to the subscriptions of each connection
+    pub fn set_subscription_limits(&self, limits: SubscriptionLimits) {
+        self.topics.set_limits(limits);
+    }
+
+    /// A subscriber for a new persistent connection, and the receiver of
+    /// its notifications; `None` if no topics are registered
+    pub fn subscriber(&self) -> Option<(Subscriber, tokio::sync::mpsc::Receiver<Value>)> {
+        if self.topics.is_empty() {
+            return None;
+        }
+        Some(Subscriber::new(self.topics.limits()))
+    }
+
+    /// Dispatches a request to its handler.
+    ///
+    /// Returns `None` for notifications: the handler still runs, but the
+    /// outcome (including errors such as an unknown method) is discarded.
+    pub async fn handle_request(&self, req: RpcRequest) -> Option<RpcResponse> {
+        self.handle_request_with_context(req, RequestContext::default())
+            .await
+    }
+
+    /// Dispatches a request to its handler with the given `RequestContext`.
+    pub async fn handle_request_with_context(
+        &self,
+        req: RpcRequest,
+        ctx: RequestContext,
+    ) -> Option<RpcResponse> {
+        self.handle_request_with_middleware(req, ctx, &[]).await
+    }
+
+    /// Dispatches a request through `layers`, then through the server's own
+    /// middleware, and finally to its handler.
+    ///
+    /// Transports pass their configured layers (auth, metrics) here so that
+    /// every transport applies them the same way.
+    pub async fn handle_request_with_middleware(
+        &self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        layers: &[Arc<dyn RpcMiddleware>],
+    ) -> Option<RpcResponse> {
+        let stack: Vec<Arc<dyn RpcMiddleware>> = layers
+            .iter()
+            .cloned()
+            .chain(self.middleware.read().await.iter().cloned())
+            .collect();
+
+        let is_notification = req.is_notification();
+        let id = req.id.clone().unwrap_or(Value::Null);
+        let method = req.method.clone();
+
+        let resp = match Next::new(self, &stack).run(req, ctx).await {
+            Ok(res) => RpcResponse::with_result(id, res),
+            Err(err) => {
+                // the source chain stays server-side
+                if std::error::Error::source(&err).is_some() {
+                    tracing::error!("Handler {} failed: {}", method, err.chain());
+                }
+                RpcResponse::with_error_obj(id, err.into())
+            }
+        };
+
+        if is_notification { None } else { Some(resp) }
+    }
+
+    /// Invokes the handler registered for `req.method`; the innermost step
+    /// of the middleware stack.
+    pub(crate) async fn call_handler(
+        &self,
+        req: RpcRequest,
+        ctx: RequestContext,
+    ) -> Result<Value, RpcError> {
+        let handler = self.handlers.read().await.get(&req.method).cloned();
+        match handler {
+            Some(h) => (h)(req.params, ctx).await,
+            None => Err(RpcError::method_not_found(&req.method)),
         }
     }
 }
 
 
+impl Default for RpcServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // Helper function to parse raw JSON string into RpcRequest
-pub fn parse_rpc_request(raw: &str) -> Result<RpcRequest, serde_json::Error> {
-    serde_json::from_str::<RpcRequest>(raw)
+//
+// Invalid JSON yields a PARSE_ERROR response, a well-formed value that is not
+// a valid request object yields INVALID_REQUEST.
+pub fn parse_rpc_request(raw: &str) -> Result<RpcRequest, RpcResponse> {
+    let value: Value = serde_json::from_str(raw).map_err(parse_error)?;
+    RpcRequest::from_value(value)
 }
 
 /// Registers a set of default RPC handlers for the given `RpcServer`.
@@ -210,7 +570,6 @@ pub fn parse_rpc_request(raw: &str) -> Result<RpcRequest, serde_json::Error> {
 ///   and safe registration of handlers prior to running the server.
 /// - These handlers serve as mock implementations useful for testing RPC integration
 ///   or demonstrating how to define async RPC endpoints.
-
 pub async fn register_default_handlers(server: &RpcServer) {
     // ping -> "pong"
     server.register("ping", |_params| async move {
@@ -226,7 +585,7 @@ pub async fn register_default_handlers(server: &RpcServer) {
             ""
         };
         if address.is_empty() {
-            return Err(RpcErrorObj { code: INVALID_PARAMS, message: "Missing 'address' param".into(), data: None });
+            return Err(RpcError::invalid_params("Missing 'address' param"));
         }
         // fake balance: length-based deterministic value for demo
         let bal = (address.len() * 12345) as u64;
@@ -241,7 +600,7 @@ pub async fn register_default_handlers(server: &RpcServer) {
             ""
         };
         if raw.is_empty() {
-            return Err(RpcErrorObj { code: INVALID_PARAMS, message: "Missing 'raw_tx' param".into(), data: None });
+            return Err(RpcError::invalid_params("Missing 'raw_tx' param"));
         }
         // "send" generates a uuid txid
         let txid = Uuid::new_v4().to_string();
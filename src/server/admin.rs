@@ -0,0 +1,169 @@
+use crate::middleware::{AccessPolicy, AuditLog, AuditQuery, KeyStore};
+use crate::rpc::{RequestContext, RpcError, RpcServer, decode_params};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Scope a principal needs to call any `admin.*` method
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// Grace period for `admin.keys.rotate` when the caller does not pass one
+pub const DEFAULT_ROTATION_GRACE: Duration = Duration::from_secs(3600);
+
+/// Params for `admin.keys.issue`
+#[derive(Debug, Deserialize)]
+pub struct IssueKeyParams {
+    pub label: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Lifetime in seconds; the key never expires when omitted
+    pub ttl_secs: Option<u64>,
+}
+
+/// Params for `admin.keys.rotate`
+#[derive(Debug, Deserialize)]
+pub struct RotateKeyParams {
+    pub id: String,
+    /// How long the old key keeps working, in seconds
+    pub grace_secs: Option<u64>,
+}
+
+/// Params for methods keyed by a key id
+#[derive(Debug, Deserialize)]
+pub struct KeyIdParams {
+    pub id: String,
+}
+
+/// Admin methods are refused unless the caller holds `ADMIN_SCOPE`, whatever
+/// access policy the transport runs
+fn require_admin(method: &str, ctx: &RequestContext) -> Result<(), RpcError> {
+    AccessPolicy::new()
+        .require(["admin.*"], [ADMIN_SCOPE])
+        .check(method, ctx.principal.as_ref())
+}
+
+fn store_error(err: anyhow::Error) -> RpcError {
+    RpcError::internal("Key store update failed").with_source(err)
+}
+
+fn unknown_key(id: &str) -> RpcError {
+    RpcError::invalid_params("Unknown key id").with_data(json!({ "id": id }))
+}
+
+/// Issue a key; the response is the only place the key itself appears
+pub async fn issue_key(keys: Arc<KeyStore>, p: IssueKeyParams) -> Result<Value, RpcError> {
+    let issued = keys
+        .issue(p.label, p.scopes, p.ttl_secs.map(Duration::from_secs))
+        .await
+        .map_err(store_error)?;
+    Ok(json!(issued))
+}
+
+/// Replace a key, keeping the old one valid for the grace period
+pub async fn rotate_key(keys: Arc<KeyStore>, p: RotateKeyParams) -> Result<Value, RpcError> {
+    let grace = p
+        .grace_secs
+        .map_or(DEFAULT_ROTATION_GRACE, Duration::from_secs);
+    let issued = keys
+        .rotate(&p.id, grace)
+        .await
+        .map_err(store_error)?
+        .ok_or_else(|| unknown_key(&p.id))?;
+    Ok(json!({
+        "replaced": p.id,
+        "grace_secs": grace.as_secs(),
+        "new": issued,
+    }))
+}
+
+/// Revoke a key immediately
+pub async fn revoke_key(keys: Arc<KeyStore>, p: KeyIdParams) -> Result<Value, RpcError> {
+    if !keys.revoke(&p.id).await.map_err(store_error)? {
+        return Err(unknown_key(&p.id));
+    }
+    Ok(json!({ "revoked": p.id }))
+}
+
+/// List key metadata (never the keys or their hashes)
+pub async fn list_keys(keys: Arc<KeyStore>) -> Result<Value, RpcError> {
+    let keys = keys.list().await;
+    Ok(json!({ "count": keys.len(), "keys": keys }))
+}
+
+/// Audit records matching the query params
+pub async fn query_audit(log: Arc<AuditLog>, query: AuditQuery) -> Result<Value, RpcError> {
+    let records = log
+        .query(&query)
+        .await
+        .map_err(|e| RpcError::internal("Audit log query failed").with_source(e))?;
+    Ok(json!({ "count": records.len(), "records": records }))
+}
+
+/// Register `admin.audit.query` over `log`; like the key methods it requires
+/// `ADMIN_SCOPE`
+pub async fn register_audit_admin_handlers(server: &RpcServer, log: Arc<AuditLog>) {
+    server
+        .register_with_context("admin.audit.query", move |params, ctx| {
+            let log = log.clone();
+            async move {
+                require_admin("admin.audit.query", &ctx)?;
+                query_audit(log, decode_params(params)?).await
+            }
+        })
+        .await;
+}
+
+/// Register `admin.keys.issue`, `.rotate`, `.revoke` and `.list` over `keys`.
+///
+/// Every method requires a principal with `ADMIN_SCOPE`, so register these
+/// only on servers that authenticate requests.
+pub async fn register_key_admin_handlers(server: &RpcServer, keys: Arc<KeyStore>) {
+    let k = keys.clone();
+    server
+        .register_with_context("admin.keys.issue", move |params, ctx| {
+            let k = k.clone();
+            async move {
+                require_admin("admin.keys.issue", &ctx)?;
+                issue_key(k, decode_params(params)?).await
+            }
+        })
+        .await;
+
+    let k = keys.clone();
+    server
+        .register_with_context("admin.keys.rotate", move |params, ctx| {
+            let k = k.clone();
+            async move {
+                require_admin("admin.keys.rotate", &ctx)?;
+                rotate_key(k, decode_params(params)?).await
+            }
+        })
+        .await;
+
+    let k = keys.clone();
+    server
+        .register_with_context("admin.keys.revoke", move |params, ctx| {
+            let k = k.clone();
+            async move {
+                require_admin("admin.keys.revoke", &ctx)?;
+                revoke_key(k, decode_params(params)?).await
+            }
+        })
+        .await;
+
+    server
+        .mark_mutating(["admin.keys.issue", "admin.keys.rotate", "admin.keys.revoke"])
+        .await;
+
+    let k = keys.clone();
+    server
+        .register_with_context("admin.keys.list", move |_params, ctx| {
+            let k = k.clone();
+            async move {
+                require_admin("admin.keys.list", &ctx)?;
+                list_keys(k).await
+            }
+        })
+        .await;
+}
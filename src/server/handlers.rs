@@ -1,7 +1,239 @@
-use crate::rpc::{RpcServer, RpcErrorObj, INVALID_PARAMS};
-use crate::state::{StateStore, TransactionStatus};
+use crate::rpc::{RpcError, RpcServer, decode_params};
+use crate::state::{StateEvent, StateStore, Transaction, TransactionStatus};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Application error: a transfer was rejected by the state store
+pub const TRANSFER_FAILED: i64 = -32000;
+/// Application error: the referenced transaction does not exist. Kept
+/// clear of the auth codes, which start at `AUTH_ERROR` (-32001).
+pub const TX_NOT_FOUND: i64 = -32009;
+
+/// Params for methods keyed by a single account address
+#[derive(Debug, Deserialize)]
+pub struct AddressParams {
+    pub address: String,
+}
+
+/// Params for `set_balance`
+#[derive(Debug, Deserialize)]
+pub struct SetBalanceParams {
+    pub address: String,
+    pub balance: u64,
+}
+
+/// Params for `transfer`
+#[derive(Debug, Deserialize)]
+pub struct TransferParams {
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+}
+
+/// Params for methods keyed by a transaction id
+#[derive(Debug, Deserialize)]
+pub struct TxidParams {
+    pub txid: String,
+}
+
+fn status_str(status: &TransactionStatus) -> &'static str {
+    match status {
+        TransactionStatus::Pending => "pending",
+        TransactionStatus::Confirmed => "confirmed",
+        TransactionStatus::Failed => "failed",
+    }
+}
+
+fn transaction_json(tx: &Transaction) -> Value {
+    json!({
+        "txid": tx.txid,
+        "from": tx.from,
+        "to": tx.to,
+        "amount": tx.amount,
+        "timestamp": tx.timestamp,
+        "status": status_str(&tx.status),
+    })
+}
+
+/// Get balance - now uses real state
+pub async fn get_balance(state: Arc<StateStore>, p: AddressParams) -> Result<Value, RpcError> {
+    let balance = state.get_balance(&p.address).await.unwrap_or(0);
+
+    Ok(json!({
+        "address": p.address,
+        "balance": balance.to_string()
+    }))
+}
+
+/// Set balance - admin function for testing
+pub async fn set_balance(state: Arc<StateStore>, p: SetBalanceParams) -> Result<Value, RpcError> {
+    state.set_balance(p.address.as_str(), p.balance).await;
+
+    Ok(json!({
+        "address": p.address,
+        "balance": p.balance.to_string(),
+        "success": true
+    }))
+}
+
+/// Transfer - send funds between accounts
+pub async fn transfer(state: Arc<StateStore>, p: TransferParams) -> Result<Value, RpcError> {
+    match state.transfer(&p.from, &p.to, p.amount).await {
+        Ok(tx) => Ok(json!({
+            "txid": tx.txid,
+            "from": tx.from,
+            "to": tx.to,
+            "amount": tx.amount,
+            "status": "pending"
+        })),
+        Err(e) => Err(RpcError::application(TRANSFER_FAILED, e).with_data(json!({
+            "from": p.from,
+            "to": p.to,
+            "amount": p.amount
+        }))),
+    }
+}
+
+/// Get transaction by ID
+pub async fn get_transaction(state: Arc<StateStore>, p: TxidParams) -> Result<Value, RpcError> {
+    match state.get_transaction(&p.txid).await {
+        Some(tx) => Ok(transaction_json(&tx)),
+        None => Err(RpcError::application(TX_NOT_FOUND, "Transaction not found")
+            .with_data(json!({ "txid": p.txid }))),
+    }
+}
+
+/// Confirm transaction
+pub async fn confirm_transaction(state: Arc<StateStore>, p: TxidParams) -> Result<Value, RpcError> {
+    match state.confirm_transaction(&p.txid).await {
+        Ok(_) => Ok(json!({
+            "txid": p.txid,
+            "status": "confirmed",
+            "success": true
+        })),
+        Err(e) => Err(RpcError::application(TX_NOT_FOUND, e).with_data(json!({ "txid": p.txid }))),
+    }
+}
+
+/// Get transactions for address
+pub async fn get_transactions(state: Arc<StateStore>, p: AddressParams) -> Result<Value, RpcError> {
+    let transactions = state.get_transactions_for_address(&p.address).await;
+    let tx_list: Vec<Value> = transactions.iter().map(transaction_json).collect();
+
+    Ok(json!({
+        "address": p.address,
+        "transactions": tx_list
+    }))
+}
+
+/// List all accounts
+pub async fn list_accounts(state: Arc<StateStore>) -> Result<Value, RpcError> {
+    let accounts = state.get_all_accounts().await;
+
+    let acc_list: Vec<Value> = accounts
+        .iter()
+        .map(|acc| {
+            json!({
+                "address": acc.address,
+                "balance": acc.balance.to_string(),
+                "nonce": acc.nonce
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "accounts": acc_list,
+        "count": acc_list.len()
+    }))
+}
+
+/// Every state change from now on, as a stream
+fn state_events(state: &StateStore) -> impl Stream<Item = StateEvent> + Send + 'static {
+    futures::stream::unfold(state.subscribe(), |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => return Some((event, events)),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("State subscription fell behind; skipped {} events", missed);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Register the subscription topics fed by `state`:
+///
+/// - `newTransactions`: every transfer, as `get_transaction` returns it
+/// - `balanceChanged` `{address}`: the new balance of one account
+/// - `txStatus` `{txid}`: status changes of one transaction
+pub async fn register_state_topics(server: &RpcServer, state: Arc<StateStore>) {
+    let s = state.clone();
+    server
+        .register_topic("newTransactions", move |_params| {
+            Ok(state_events(&s)
+                .filter_map(|event| async move {
+                    match event {
+                        StateEvent::NewTransaction(tx) => Some(transaction_json(&tx)),
+                        _ => None,
+                    }
+                })
+                .boxed())
+        })
+        .await;
+
+    let s = state.clone();
+    server
+        .register_topic("balanceChanged", move |params| {
+            let p: AddressParams = decode_params(params)?;
+            Ok(state_events(&s)
+                .filter_map(move |event| {
+                    let watched = p.address.clone();
+                    async move {
+                        match event {
+                            StateEvent::BalanceChanged { address, balance }
+                                if address == watched =>
+                            {
+                                Some(json!({
+                                    "address": address,
+                                    "balance": balance.to_string()
+                                }))
+                            }
+                            _ => None,
+                        }
+                    }
+                })
+                .boxed())
+        })
+        .await;
+
+    let s = state.clone();
+    server
+        .register_topic("txStatus", move |params| {
+            let p: TxidParams = decode_params(params)?;
+            Ok(state_events(&s)
+                .filter_map(move |event| {
+                    let watched = p.txid.clone();
+                    async move {
+                        match event {
+                            StateEvent::TransactionStatus { txid, status } if txid == watched => {
+                                Some(json!({
+                                    "txid": txid,
+                                    "status": status_str(&status)
+                                }))
+                            }
+                            _ => None,
+                        }
+                    }
+                })
+                .boxed())
+        })
+        .await;
+}
 
 #[allow(dead_code)]
 /// Register handlers with persistent state
@@ -11,267 +243,46 @@ pub async fn register_stateful_handlers(server: &RpcServer, state: Arc<StateStor
         .register("ping", |_params| async move { Ok(Value::String("pong".into())) })
         .await;
 
-    // Get balance - now uses real state
-    {
-        let state = state.clone();
-        server
-            .register("get_balance", move |params| {
-                let state = state.clone();
-                async move {
-                    let address = params
-                        .get("address")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'address' parameter".into(),
-                            data: None,
-                        })?;
-
-                    let balance = state.get_balance(address).await.unwrap_or(0);
-
-                    Ok(json!({
-                        "address": address,
-                        "balance": balance.to_string()
-                    }))
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("get_balance", move |p| get_balance(s.clone(), p))
+        .await;
 
-    // Set balance - admin function for testing
-    {
-        let state = state.clone();
-        server
-            .register("set_balance", move |params| {
-                let state = state.clone();
-                async move {
-                    let address = params
-                        .get("address")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'address' parameter".into(),
-                            data: None,
-                        })?;
-
-                    let balance = params
-                        .get("balance")
-                        .and_then(|v| v.as_u64())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing or invalid 'balance' parameter".into(),
-                            data: None,
-                        })?;
-
-                    state.set_balance(address, balance).await;
-
-                    Ok(json!({
-                        "address": address,
-                        "balance": balance.to_string(),
-                        "success": true
-                    }))
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("set_balance", move |p| set_balance(s.clone(), p))
+        .await;
 
-    // Transfer - send funds between accounts
-    {
-        let state = state.clone();
-        server
-            .register("transfer", move |params| {
-                let state = state.clone();
-                async move {
-                    let from = params
-                        .get("from")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'from' parameter".into(),
-                            data: None,
-                        })?;
-
-                    let to = params
-                        .get("to")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'to' parameter".into(),
-                            data: None,
-                        })?;
-
-                    let amount = params
-                        .get("amount")
-                        .and_then(|v| v.as_u64())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing or invalid 'amount' parameter".into(),
-                            data: None,
-                        })?;
-
-                    match state.transfer(from, to, amount).await {
-                        Ok(tx) => Ok(json!({
-                            "txid": tx.txid,
-                            "from": tx.from,
-                            "to": tx.to,
-                            "amount": tx.amount,
-                            "status": "pending"
-                        })),
-                        Err(e) => Err(RpcErrorObj {
-                            code: -32000,
-                            message: e,
-                            data: None,
-                        }),
-                    }
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("transfer", move |p| transfer(s.clone(), p))
+        .await;
 
-    // Get transaction by ID
-    {
-        let state = state.clone();
-        server
-            .register("get_transaction", move |params| {
-                let state = state.clone();
-                async move {
-                    let txid = params
-                        .get("txid")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'txid' parameter".into(),
-                            data: None,
-                        })?;
-
-                    match state.get_transaction(txid).await {
-                        Some(tx) => Ok(json!({
-                            "txid": tx.txid,
-                            "from": tx.from,
-                            "to": tx.to,
-                            "amount": tx.amount,
-                            "timestamp": tx.timestamp,
-                            "status": match tx.status {
-                               TransactionStatus::Pending => "pending",
-                               TransactionStatus::Confirmed => "confirmed",
-                               TransactionStatus::Failed => "failed",
-                            }
-                        })),
-                        None => Err(RpcErrorObj {
-                            code: -32001,
-                            message: "Transaction not found".into(),
-                            data: None,
-                        }),
-                    }
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("get_transaction", move |p| get_transaction(s.clone(), p))
+        .await;
 
-    // Confirm transaction
-    {
-        let state = state.clone();
-        server
-            .register("confirm_transaction", move |params| {
-                let state = state.clone();
-                async move {
-                    let txid = params
-                        .get("txid")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'txid' parameter".into(),
-                            data: None,
-                        })?;
-
-                    match state.confirm_transaction(txid).await {
-                        Ok(_) => Ok(json!({
-                            "txid": txid,
-                            "status": "confirmed",
-                            "success": true
-                        })),
-                        Err(e) => Err(RpcErrorObj {
-                            code: -32001,
-                            message: e,
-                            data: None,
-                        }),
-                    }
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("confirm_transaction", move |p| {
+            confirm_transaction(s.clone(), p)
+        })
+        .await;
 
-    // Get transactions for address
-    {
-        let state = state.clone();
-        server
-            .register("get_transactions", move |params| {
-                let state = state.clone();
-                async move {
-                    let address = params
-                        .get("address")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| RpcErrorObj {
-                            code: INVALID_PARAMS,
-                            message: "Missing 'address' parameter".into(),
-                            data: None,
-                        })?;
-
-                    let transactions = state.get_transactions_for_address(address).await;
-
-                    let tx_list: Vec<Value> = transactions
-                        .iter()
-                        .map(|tx| {
-                            json!({
-                                "txid": tx.txid,
-                                "from": tx.from,
-                                "to": tx.to,
-                                "amount": tx.amount,
-                                "timestamp": tx.timestamp,
-                                "status": match tx.status {
-                                    TransactionStatus::Pending => "pending",
-                                    TransactionStatus::Confirmed => "confirmed",
-                                    TransactionStatus::Failed => "failed",
-                                }
-                            })
-                        })
-                        .collect();
-
-                    Ok(json!({
-                        "address": address,
-                        "transactions": tx_list
-                    }))
-                }
-            })
-            .await;
-    }
+    let s = state.clone();
+    server
+        .register_typed("get_transactions", move |p| get_transactions(s.clone(), p))
+        .await;
 
-    // List all accounts
-    {
-        let state = state.clone();
-        server
-            .register("list_accounts", move |_params| {
-                let state = state.clone();
-                async move {
-                    let accounts = state.get_all_accounts().await;
-
-                    let acc_list: Vec<Value> = accounts
-                        .iter()
-                        .map(|acc| {
-                            json!({
-                                "address": acc.address,
-                                "balance": acc.balance.to_string(),
-                                "nonce": acc.nonce
-                            })
-                        })
-                        .collect();
-
-                    Ok(json!({
-                        "accounts": acc_list,
-                        "count": acc_list.len()
-                    }))
-                }
-            })
-            .await;
-    }
-}
\ No newline at end of file
+    // list_accounts takes no params, so any params value is accepted
+    let s = state.clone();
+    server
+        .register("list_accounts", move |_params| list_accounts(s.clone()))
+        .await;
+    server
+        .mark_mutating(["set_balance", "transfer", "confirm_transaction"])
+        .await;
+
+    register_state_topics(server, state).await;
+}
@@ -18,6 +18,14 @@ pub struct Metrics {
     avg_duration_us: Arc<RwLock<u64>>,
     /// Request counts per method
     method_counts: Arc<RwLock<std::collections::HashMap<String, u64>>>,
+    /// Requests rejected by a rate limit
+    total_rate_limited: AtomicU64,
+    /// Rejections per kind of limit (`key`, `ip`, `method`)
+    rate_limited_counts: Arc<RwLock<std::collections::HashMap<String, u64>>>,
+    /// Connections dropped at accept by an IP filter or a connection limit
+    total_rejected_connections: AtomicU64,
+    /// Connections closed by a read, idle or write timeout
+    total_timed_out_connections: AtomicU64,
 }
 
 #[allow(dead_code)]
@@ -29,6 +37,10 @@ impl Metrics {
             total_errors: AtomicU64::new(0),
             avg_duration_us: Arc::new(RwLock::new(0)),
             method_counts: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            total_rate_limited: AtomicU64::new(0),
+            rate_limited_counts: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            total_rejected_connections: AtomicU64::new(0),
+            total_timed_out_connections: AtomicU64::new(0),
         }
     }
 
@@ -66,6 +78,25 @@ impl Metrics {
         *counts.entry(method.to_string()).or_insert(0) += 1;
     }
 
+    /// Record a request rejected by the `limit` kind of rate limit
+    pub async fn record_rate_limited(&self, limit: &str) {
+        self.total_rate_limited.fetch_add(1, Ordering::Relaxed);
+        let mut counts = self.rate_limited_counts.write().await;
+        *counts.entry(limit.to_string()).or_insert(0) += 1;
+    }
+
+    /// Record a connection refused by an IP filter or a connection limit
+    pub fn record_rejected_connection(&self) {
+        self.total_rejected_connections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a connection closed because the peer was too slow or idle
+    pub fn record_timed_out_connection(&self) {
+        self.total_timed_out_connections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Get current metrics snapshot
     pub async fn snapshot(&self) -> MetricsSnapshot {
         MetricsSnapshot {
@@ -74,6 +105,10 @@ impl Metrics {
             total_errors: self.total_errors.load(Ordering::Relaxed),
             avg_duration_us: *self.avg_duration_us.read().await,
             method_counts: self.method_counts.read().await.clone(),
+            total_rate_limited: self.total_rate_limited.load(Ordering::Relaxed),
+            rate_limited_counts: self.rate_limited_counts.read().await.clone(),
+            total_rejected_connections: self.total_rejected_connections.load(Ordering::Relaxed),
+            total_timed_out_connections: self.total_timed_out_connections.load(Ordering::Relaxed),
         }
     }
 
@@ -84,6 +119,10 @@ impl Metrics {
         self.total_errors.store(0, Ordering::Relaxed);
         *self.avg_duration_us.write().await = 0;
         self.method_counts.write().await.clear();
+        self.total_rate_limited.store(0, Ordering::Relaxed);
+        self.rate_limited_counts.write().await.clear();
+        self.total_rejected_connections.store(0, Ordering::Relaxed);
+        self.total_timed_out_connections.store(0, Ordering::Relaxed);
     }
 }
 
@@ -102,6 +141,10 @@ pub struct MetricsSnapshot {
     pub total_errors: u64,
     pub avg_duration_us: u64,
     pub method_counts: std::collections::HashMap<String, u64>,
+    pub total_rate_limited: u64,
+    pub rate_limited_counts: std::collections::HashMap<String, u64>,
+    pub total_rejected_connections: u64,
+    pub total_timed_out_connections: u64,
 }
 
 #[allow(dead_code)]
@@ -170,6 +213,20 @@ pub fn init_logging() {
         .init();
 }
 
+/// Like `init_logging`, but to stderr, for servers whose stdout carries
+/// the protocol
+pub fn init_stderr_logging() {
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "dice_rpc=debug".into()),
+        )
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .init();
+}
+
 #[allow(dead_code)]
 /// Log server startup
 pub fn log_startup(addr: &str, transport: &str) {
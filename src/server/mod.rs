@@ -1,3 +1,5 @@
+pub mod admin;
 pub mod metrics;
 pub mod handlers;
+#[allow(clippy::module_inception)]
 pub mod server;
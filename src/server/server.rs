@@ -1,29 +1,41 @@
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use crate::rpc::{RpcServer, parse_rpc_request, register_default_handlers};
+use crate::rpc::{RequestContext, RpcServer, TransportKind, register_default_handlers};
+use crate::util::batch::BatchRequest;
 use anyhow::Result;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 pub async fn run(addr: &str) -> Result<()> {
-    let listener = TcpListener::bind(addr).await?;
-    println!("DiceRPC server listening on {}", addr);
-
     // create server and register handlers
     let server = Arc::new(RpcServer::new());
     register_default_handlers(&server).await;
 
+    serve(addr, server).await
+}
+
+/// Serve an already configured `RpcServer` over line-delimited TCP
+pub async fn serve(addr: &str, server: Arc<RpcServer>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("DiceRPC server listening on {}", addr);
+
     loop {
-        let (socket, _) = listener.accept().await?;
+        let (socket, peer) = listener.accept().await?;
         let server = server.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(server, socket).await {
+            if let Err(e) = handle_connection(server, socket, peer).await {
                 eprintln!("connection error: {:?}", e);
             }
         });
     }
 }
 
-async fn handle_connection(server: Arc<RpcServer>, stream: TcpStream) -> Result<()> {
+async fn handle_connection(
+    server: Arc<RpcServer>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> Result<()> {
+    let ctx = RequestContext::new(TransportKind::TcpLine).with_peer_addr(peer);
     let (reader, mut writer) = stream.into_split();
     let mut br = BufReader::new(reader);
     let mut line = String::new();
@@ -42,21 +54,17 @@ async fn handle_connection(server: Arc<RpcServer>, stream: TcpStream) -> Result<
             continue;
         }
 
-        match parse_rpc_request(raw) {
-            Ok(req) => {
-                let resp = server.handle_request(req).await;
-                let resp_text = serde_json::to_string(&resp).unwrap();
-                writer.write_all(resp_text.as_bytes()).await?;
-                writer.write_all(b"\n").await?;
-            }
-            Err(e) => {
-                // return parse error
-                let err_resp = crate::rpc::RpcResponse::with_error(serde_json::Value::Null, -32700, format!("Parse error: {}", e));
-                let resp_text = serde_json::to_string(&err_resp).unwrap();
-                writer.write_all(resp_text.as_bytes()).await?;
-                writer.write_all(b"\n").await?;
-            }
-        }
+        // single requests and batches; notifications are executed but never answered
+        let resp_text = match BatchRequest::parse(raw) {
+            Ok(batch) => match server.handle_batch_with_context(batch, ctx.clone()).await {
+                Some(resp) => serde_json::to_string(&resp).unwrap(),
+                None => continue,
+            },
+            // return parse error / invalid request
+            Err(err_resp) => serde_json::to_string(&err_resp).unwrap(),
+        };
+        writer.write_all(resp_text.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
     }
 
     Ok(())
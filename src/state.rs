@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, broadcast};
 use uuid::Uuid;
 
 /// Represents a blockchain transaction
@@ -30,6 +30,23 @@ pub struct Account {
     pub nonce: u64,
 }
 
+/// Events a `StateStore` receiver may fall behind by before it misses some
+pub const STATE_EVENT_CAPACITY: usize = 1024;
+
+/// A change to the state, published to `StateStore::subscribe` receivers
+#[derive(Debug, Clone)]
+pub enum StateEvent {
+    BalanceChanged {
+        address: String,
+        balance: u64,
+    },
+    NewTransaction(Transaction),
+    TransactionStatus {
+        txid: String,
+        status: TransactionStatus,
+    },
+}
+
 /// In-memory persistent state for the RPC server
 ///
 /// This provides a simple key-value store for balances and transactions
@@ -38,6 +55,7 @@ pub struct Account {
 pub struct StateStore {
     accounts: Arc<RwLock<HashMap<String, Account>>>,
     transactions: Arc<RwLock<HashMap<String, Transaction>>>,
+    events: broadcast::Sender<StateEvent>,
 }
 
 impl StateStore {
@@ -45,9 +63,21 @@ impl StateStore {
         Self {
             accounts: Arc::new(RwLock::new(HashMap::new())),
             transactions: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(STATE_EVENT_CAPACITY).0,
         }
     }
 
+    /// Receive every change made from now on. A receiver more than
+    /// `STATE_EVENT_CAPACITY` events behind skips the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<StateEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: StateEvent) {
+        // No receivers is not an error
+        let _ = self.events.send(event);
+    }
+
     #[allow(dead_code)]
     /// Get account by address, creating if it doesn't exist
     pub async fn get_or_create_account(&self, address: impl Into<String>) -> Account {
@@ -84,10 +114,11 @@ impl StateStore {
             .entry(address.clone())
             .and_modify(|acc| acc.balance = balance)
             .or_insert(Account {
-                address,
+                address: address.clone(),
                 balance,
                 nonce: 0,
             });
+        self.publish(StateEvent::BalanceChanged { address, balance });
     }
 
     #[allow(dead_code)]
@@ -108,16 +139,18 @@ impl StateStore {
         // Deduct from sender
         sender.balance -= amount;
         sender.nonce += 1;
+        let sender_balance = sender.balance;
 
         // Add to receiver (create if doesn't exist)
-        accounts
+        let receiver_balance = accounts
             .entry(to.to_string())
             .and_modify(|acc| acc.balance += amount)
             .or_insert(Account {
                 address: to.to_string(),
                 balance: amount,
                 nonce: 0,
-            });
+            })
+            .balance;
 
         // Create transaction record
         let tx = Transaction {
@@ -138,6 +171,16 @@ impl StateStore {
             .await
             .insert(tx.txid.clone(), tx.clone());
 
+        self.publish(StateEvent::BalanceChanged {
+            address: from.to_string(),
+            balance: sender_balance,
+        });
+        self.publish(StateEvent::BalanceChanged {
+            address: to.to_string(),
+            balance: receiver_balance,
+        });
+        self.publish(StateEvent::NewTransaction(tx.clone()));
+
         Ok(tx)
     }
     
@@ -156,6 +199,10 @@ impl StateStore {
             .ok_or_else(|| "Transaction not found".to_string())?;
 
         tx.status = TransactionStatus::Confirmed;
+        self.publish(StateEvent::TransactionStatus {
+            txid: tx.txid.clone(),
+            status: TransactionStatus::Confirmed,
+        });
         Ok(())
     }
    
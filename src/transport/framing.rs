@@ -1,57 +1,641 @@
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use anyhow::{Result, anyhow};
+use anyhow::bail;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+#[cfg(feature = "tcp")]
+use crate::rpc::{RpcError, RpcResponse};
+use serde_json::{Value, json};
+use std::fmt;
+#[cfg(feature = "tcp")]
+use tokio::time::Instant;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Largest payload accepted in either frame format
+pub const MAX_FRAME_SIZE: usize = 10_000_000;
+
+/// Plain length-prefixed frames, spoken by every peer
+pub const PROTOCOL_V1: u8 = 1;
+/// Frames with a typed header, used once both ends agree via `rpc.hello`
+pub const PROTOCOL_V2: u8 = 2;
+
+/// Method of the v1 request a client sends first to ask for v2 frames
+pub const HELLO_METHOD: &str = "rpc.hello";
+
+/// Sent in the error response or goaway frame when the server closes a
+/// connection; `error.data.reason` says which limit tripped
+pub const CONNECTION_CLOSED: i64 = -32007;
+
+/// First bytes of a v2 header. A v1 frame starts with the high byte of
+/// its length, which is 0 for anything under `MAX_FRAME_SIZE`, so a stray
+/// v1 frame is never mistaken for v2 (or the reverse).
+pub const V2_MAGIC: [u8; 2] = *b"DR";
+/// Size of a v2 header: magic, version, flags, type, 3 reserved bytes,
+/// stream id and payload length
+pub const V2_HEADER_LEN: usize = 16;
+
+/// Why a frame could not be read or written
+#[derive(Debug)]
+pub enum FrameError {
+    /// The stream ended, or the peer dropped a TLS connection without
+    /// `close_notify`, before a whole frame arrived
+    Eof,
+    /// The length prefix is over the codec's limit; nothing was allocated
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// A v2 header with a bad magic, version or message type
+    Malformed(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Eof => write!(f, "Stream ended inside a frame"),
+            FrameError::TooLarge { len, max } => {
+                write!(f, "Frame too large: {} bytes (max {})", len, max)
+            }
+            FrameError::Malformed(reason) => write!(f, "Malformed frame: {}", reason),
+            FrameError::Io(e) => write!(f, "Frame I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FrameError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            FrameError::Eof
+        } else {
+            FrameError::Io(e)
+        }
+    }
+}
 
 /// Frame format: 4-byte length prefix (big-endian) + message payload
 /// This is more robust than newline delimiting and handles binary data properly
-pub struct FrameCodec;
+///
+/// As a `tokio_util` codec it decodes into `BytesMut` split off the read
+/// buffer, so `FramedRead` reuses one buffer for a whole connection:
+///
+/// ```no_run
+/// # use dice_rpc::transport::{FrameCodec, FrameError};
+/// # use futures::StreamExt;
+/// # use tokio_util::codec::FramedRead;
+/// # fn handle(_: bytes::Bytes) {}
+/// # async fn example(stream: tokio::net::TcpStream) -> Result<(), FrameError> {
+/// let mut frames = FramedRead::new(stream, FrameCodec::new().with_max_frame_length(1 << 20));
+/// while let Some(frame) = frames.next().await {
+///     handle(frame?.freeze());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCodec {
+    max_frame_length: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self {
+            max_frame_length: MAX_FRAME_SIZE,
+        }
+    }
+}
 
 impl FrameCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse frames with payloads over `max` bytes, in both directions
+    pub fn with_max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    fn check_length(&self, len: usize) -> Result<(), FrameError> {
+        if len > self.max_frame_length {
+            return Err(FrameError::TooLarge {
+                len,
+                max: self.max_frame_length,
+            });
+        }
+        Ok(())
+    }
+
     /// Writes a length-prefixed frame to the writer
     /// 
     /// Format: [4-byte length][payload]
     /// Length is the size of the payload in bytes (u32, big-endian)
-    pub async fn write_frame<W>(writer: &mut W, data: &[u8]) -> Result<()>
+    pub async fn write_frame<W>(writer: &mut W, data: &[u8]) -> Result<(), FrameError>
     where
         W: AsyncWriteExt + Unpin,
     {
-        if data.len() > u32::MAX as usize {
-            return Err(anyhow!("Message too large: {} bytes", data.len()));
-        }
-
-        let len = data.len() as u32;
-        let len_bytes = len.to_be_bytes();
-        
-        // Write length prefix
-        writer.write_all(&len_bytes).await?;
-        // Write payload
-        writer.write_all(data).await?;
+        let mut buf = BytesMut::new();
+        Self::default().encode(data, &mut buf)?;
+        writer.write_all(&buf).await?;
+        // Buffering writers (TLS) hold the frame until flushed
+        writer.flush().await?;
         
         Ok(())
     }
 
     /// Reads a length-prefixed frame from the reader
     /// 
-    /// Returns the payload bytes or an error if EOF or invalid frame
-    pub async fn read_frame<R>(reader: &mut R) -> Result<Vec<u8>>
+    /// Returns the payload bytes, `FrameError::Eof` once the reader ends,
+    /// or `FrameError::TooLarge` for a payload over `MAX_FRAME_SIZE`
+    pub async fn read_frame<R>(reader: &mut R) -> Result<Vec<u8>, FrameError>
     where
         R: AsyncReadExt + Unpin,
     {
-        // Read 4-byte length prefix
-        let mut len_bytes = [0u8; 4];
-        reader.read_exact(&mut len_bytes).await?;
-        
+        let mut buf = BytesMut::new();
+        Ok(Self::read_frame_buf(reader, &mut buf).await?.into())
+    }
+
+    /// Reads one frame through `buf`, reusing its capacity across calls.
+    ///
+    /// Reads no further than the end of the frame, so the reader can be
+    /// handed on afterwards. Prefer `FramedRead` for a whole connection.
+    pub async fn read_frame_buf<R>(
+        reader: &mut R,
+        buf: &mut BytesMut,
+    ) -> Result<BytesMut, FrameError>
+    where
+        R: AsyncReadExt + Unpin,
+    {
+        let mut codec = Self::default();
+        buf.clear();
+        fill(reader, buf, 4).await?;
+        if let Some(&len_bytes) = buf.first_chunk::<4>() {
+            let len = u32::from_be_bytes(len_bytes) as usize;
+            // Check before reserving so a bad prefix allocates nothing
+            codec.check_length(len)?;
+            fill(reader, buf, 4 + len).await?;
+        }
+        codec
+            .decode(buf)?
+            .ok_or_else(|| FrameError::Malformed("incomplete frame".to_string()))
+    }
+}
+
+/// Read from `reader` until `buf` holds `len` bytes, and no further
+async fn fill<R>(reader: &mut R, buf: &mut BytesMut, len: usize) -> Result<(), FrameError>
+where
+    R: AsyncReadExt + Unpin,
+{
+    buf.reserve(len.saturating_sub(buf.len()));
+    while buf.len() < len {
+        let missing = (len - buf.len()) as u64;
+        if (&mut *reader).take(missing).read_buf(buf).await? == 0 {
+            return Err(FrameError::Eof);
+        }
+    }
+    Ok(())
+}
+
+impl Decoder for FrameCodec {
+    type Item = BytesMut;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, FrameError> {
+        let Some(&len_bytes) = src.first_chunk::<4>() else {
+            return Ok(None);
+        };
         let len = u32::from_be_bytes(len_bytes) as usize;
-        
-        // Sanity check: prevent extremely large allocations
-        if len > 10_000_000 { // 10MB max
-            return Err(anyhow!("Frame too large: {} bytes", len));
+        self.check_length(len)?;
+        if src.len() < 4 + len {
+            // Make room for the rest of the frame in one go
+            src.reserve(4 + len - src.len());
+            return Ok(None);
         }
-        
-        // Read payload
-        let mut payload = vec![0u8; len];
-        reader.read_exact(&mut payload).await?;
-        
-        Ok(payload)
+        src.advance(4);
+        Ok(Some(src.split_to(len)))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, FrameError> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(FrameError::Eof),
+        }
+    }
+}
+
+impl Encoder<&[u8]> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, data: &[u8], dst: &mut BytesMut) -> Result<(), FrameError> {
+        self.check_length(data.len())?;
+        dst.reserve(4 + data.len());
+        dst.put_u32(data.len() as u32);
+        dst.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, data: Bytes, dst: &mut BytesMut) -> Result<(), FrameError> {
+        self.encode(&data[..], dst)
+    }
+}
+
+/// What a v2 frame carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    /// A JSON-RPC request or batch; answered on the same stream id
+    Request = 1,
+    Response = 2,
+    /// Client notifications, and server pushes (subscriptions) on stream 0
+    Notification = 3,
+    /// Answered by a `Pong` echoing the payload
+    Ping = 4,
+    Pong = 5,
+    /// Abandon the request on this stream id
+    Cancel = 6,
+    /// The sender is closing; no new requests after this
+    GoAway = 7,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, FrameError> {
+        Ok(match value {
+            1 => MessageType::Request,
+            2 => MessageType::Response,
+            3 => MessageType::Notification,
+            4 => MessageType::Ping,
+            5 => MessageType::Pong,
+            6 => MessageType::Cancel,
+            7 => MessageType::GoAway,
+            other => {
+                return Err(FrameError::Malformed(format!(
+                    "unknown message type {}",
+                    other
+                )));
+            }
+        })
+    }
+}
+
+/// The flags byte of a v2 header: bit 0 marks a compressed payload, the
+/// high nibble names the payload encoding (`ENCODING_JSON` is 0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameFlags(u8);
+
+impl FrameFlags {
+    const COMPRESSED: u8 = 0x01;
+    const ENCODING_SHIFT: u8 = 4;
+
+    pub const ENCODING_JSON: u8 = 0;
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self.0 & Self::COMPRESSED != 0
+    }
+
+    pub fn with_compressed(self, compressed: bool) -> Self {
+        if compressed {
+            Self(self.0 | Self::COMPRESSED)
+        } else {
+            Self(self.0 & !Self::COMPRESSED)
+        }
+    }
+
+    pub fn encoding(self) -> u8 {
+        self.0 >> Self::ENCODING_SHIFT
+    }
+
+    /// Set the payload encoding; only the low 4 bits of `encoding` are kept
+    pub fn with_encoding(self, encoding: u8) -> Self {
+        Self((self.0 & 0x0f) | (encoding << Self::ENCODING_SHIFT))
+    }
+}
+
+/// One v2 frame. Stream id 0 belongs to the connection itself (pings,
+/// goaway, server pushes); clients number their requests from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: MessageType,
+    pub flags: FrameFlags,
+    pub stream_id: u32,
+    pub payload: Bytes,
+}
+
+impl Frame {
+    pub fn new(kind: MessageType, stream_id: u32, payload: impl Into<Bytes>) -> Self {
+        Self {
+            kind,
+            flags: FrameFlags::default(),
+            stream_id,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn with_flags(mut self, flags: FrameFlags) -> Self {
+        self.flags = flags;
+        self
     }
 }
 
+/// Codec for v2 frames, with the same payload limit rules as `FrameCodec`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameCodecV2 {
+    inner: FrameCodec,
+}
+
+impl FrameCodecV2 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_frame_length(mut self, max: usize) -> Self {
+        self.inner = self.inner.with_max_frame_length(max);
+        self
+    }
+
+    /// The message type, flags, stream id and payload length in `header`
+    fn parse_header(
+        &self,
+        header: &[u8; V2_HEADER_LEN],
+    ) -> Result<(MessageType, FrameFlags, u32, usize), FrameError> {
+        if header[..2] != V2_MAGIC {
+            return Err(FrameError::Malformed(format!(
+                "bad magic {:02x?}",
+                &header[..2]
+            )));
+        }
+        if header[2] != PROTOCOL_V2 {
+            return Err(FrameError::Malformed(format!(
+                "unsupported version {}",
+                header[2]
+            )));
+        }
+        let kind = MessageType::try_from(header[4])?;
+        let stream_id = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let len = u32::from_be_bytes([header[12], header[13], header[14], header[15]]) as usize;
+        self.inner.check_length(len)?;
+        Ok((kind, FrameFlags::from_bits(header[3]), stream_id, len))
+    }
+}
+
+impl Decoder for FrameCodecV2 {
+    type Item = Frame;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        let Some(header) = src.first_chunk::<V2_HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let (kind, flags, stream_id, len) = self.parse_header(header)?;
+        if src.len() < V2_HEADER_LEN + len {
+            src.reserve(V2_HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+        src.advance(V2_HEADER_LEN);
+        Ok(Some(Frame {
+            kind,
+            flags,
+            stream_id,
+            payload: src.split_to(len).freeze(),
+        }))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(FrameError::Eof),
+        }
+    }
+}
+
+impl Encoder<&Frame> for FrameCodecV2 {
+    type Error = FrameError;
+
+    /// Format: ["DR"][version][flags][type][3 reserved][4-byte stream id][4-byte length][payload]
+    /// Integers are big-endian; reserved bytes are written as zero
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+        self.inner.check_length(frame.payload.len())?;
+        dst.reserve(V2_HEADER_LEN + frame.payload.len());
+        dst.put_slice(&V2_MAGIC);
+        dst.put_u8(PROTOCOL_V2);
+        dst.put_u8(frame.flags.bits());
+        dst.put_u8(frame.kind as u8);
+        dst.put_bytes(0, 3);
+        dst.put_u32(frame.stream_id);
+        dst.put_u32(frame.payload.len() as u32);
+        dst.extend_from_slice(&frame.payload);
+        Ok(())
+    }
+}
+
+impl Encoder<Frame> for FrameCodecV2 {
+    type Error = FrameError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+        self.encode(&frame, dst)
+    }
+}
+
+/// Codec for one framed connection: v1 frames, read as requests on stream
+/// 0, until `upgrade` is called after a successful `rpc.hello`; v2 after
+#[cfg(feature = "tcp")]
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionCodec {
+    v1: FrameCodec,
+    v2: FrameCodecV2,
+    upgraded: bool,
+    /// When the first bytes of a still unfinished frame were decoded
+    partial_since: Option<Instant>,
+}
+
+#[cfg(feature = "tcp")]
+impl ConnectionCodec {
+    pub(crate) fn new(max_frame_length: usize) -> Self {
+        Self {
+            v1: FrameCodec::new().with_max_frame_length(max_frame_length),
+            v2: FrameCodecV2::new().with_max_frame_length(max_frame_length),
+            upgraded: false,
+            partial_since: None,
+        }
+    }
+
+    pub(crate) fn upgrade(&mut self) {
+        self.upgraded = true;
+    }
+
+    /// Set while part of a frame is buffered, for read timeouts
+    pub(crate) fn partial_since(&self) -> Option<Instant> {
+        self.partial_since
+    }
+
+    fn decode_any(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<Frame>, FrameError> {
+        let frame = match (self.upgraded, eof) {
+            (true, false) => self.v2.decode(src)?,
+            (true, true) => self.v2.decode_eof(src)?,
+            (false, false) => self.v1.decode(src)?.map(Self::request),
+            (false, true) => self.v1.decode_eof(src)?.map(Self::request),
+        };
+        if src.is_empty() {
+            self.partial_since = None;
+        } else if frame.is_some() {
+            // What is left belongs to the next frame
+            self.partial_since = Some(Instant::now());
+        } else {
+            self.partial_since.get_or_insert_with(Instant::now);
+        }
+        Ok(frame)
+    }
+
+    fn request(payload: BytesMut) -> Frame {
+        Frame::new(MessageType::Request, 0, payload.freeze())
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Decoder for ConnectionCodec {
+    type Item = Frame;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        self.decode_any(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        self.decode_any(src, true)
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Encoder<Frame> for ConnectionCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+        if self.upgraded {
+            self.v2.encode(&frame, dst)
+        } else {
+            self.v1.encode(&frame.payload[..], dst)
+        }
+    }
+}
+
+impl FrameCodec {
+    /// Writes a v2 frame
+    pub async fn write_frame_v2<W>(writer: &mut W, frame: &Frame) -> Result<(), FrameError>
+    where
+        W: AsyncWriteExt + Unpin,
+    {
+        let mut buf = BytesMut::new();
+        FrameCodecV2::default().encode(frame, &mut buf)?;
+        writer.write_all(&buf).await?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Reads a v2 frame, rejecting a bad magic, another version, an unknown
+    /// message type or an oversized payload
+    pub async fn read_frame_v2<R>(reader: &mut R) -> Result<Frame, FrameError>
+    where
+        R: AsyncReadExt + Unpin,
+    {
+        let mut codec = FrameCodecV2::default();
+        let mut buf = BytesMut::new();
+        fill(reader, &mut buf, V2_HEADER_LEN).await?;
+        if let Some(header) = buf.first_chunk::<V2_HEADER_LEN>() {
+            let (_, _, _, len) = codec.parse_header(header)?;
+            fill(reader, &mut buf, V2_HEADER_LEN + len).await?;
+        }
+        codec
+            .decode(&mut buf)?
+            .ok_or_else(|| FrameError::Malformed("incomplete frame".to_string()))
+    }
+
+    /// Ask the peer for v2 framing, as the first frame on a connection.
+    ///
+    /// The offer is an ordinary v1 request, so a server that only speaks
+    /// v1 answers with an error ("method not found", or an auth error when
+    /// it authenticates every request) and the connection carries on as
+    /// v1. Only a server closing the connection fails the handshake.
+    /// Returns the version both ends now use.
+    pub async fn negotiate<S>(stream: &mut S) -> anyhow::Result<u8>
+    where
+        S: AsyncReadExt + AsyncWriteExt + Unpin,
+    {
+        let hello = json!({
+            "jsonrpc": "2.0",
+            "method": HELLO_METHOD,
+            "params": {"versions": [PROTOCOL_V1, PROTOCOL_V2]},
+            "id": 0
+        });
+        Self::write_frame(stream, &serde_json::to_vec(&hello)?).await?;
+        let reply: Value = serde_json::from_slice(&Self::read_frame(stream).await?)?;
+        // A server at its connection limit answers with an error and closes
+        if reply["error"]["code"].as_i64() == Some(CONNECTION_CLOSED) {
+            bail!("Server refused the connection: {}", reply["error"]);
+        }
+        match reply["result"]["version"].as_u64() {
+            Some(2) => Ok(PROTOCOL_V2),
+            Some(1) | None => Ok(PROTOCOL_V1),
+            Some(other) => bail!("Server chose unknown frame version {}", other),
+        }
+    }
+}
+
+/// The reply to an `rpc.hello` v1 frame and the version it settles on, or
+/// `None` when `frame` is not a hello. The highest version both ends
+/// offer wins; without one the connection stays on v1.
+#[cfg(feature = "tcp")]
+pub(crate) fn answer_hello(frame: &[u8]) -> Option<(u8, Vec<u8>)> {
+    let request: Value = serde_json::from_slice(frame).ok()?;
+    if request.get("method")?.as_str()? != HELLO_METHOD {
+        return None;
+    }
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let offered: Vec<u64> = request["params"]["versions"]
+        .as_array()
+        .map(|versions| versions.iter().filter_map(Value::as_u64).collect())
+        .unwrap_or_default();
+
+    let supported = [PROTOCOL_V2, PROTOCOL_V1];
+    let (version, resp) = match supported
+        .into_iter()
+        .find(|v| offered.contains(&u64::from(*v)))
+    {
+        Some(version) => (
+            version,
+            RpcResponse::with_result(id, json!({"version": version})),
+        ),
+        None => {
+            let err = RpcError::invalid_params("No common frame version")
+                .with_data(json!({"supported": [PROTOCOL_V1, PROTOCOL_V2]}));
+            (PROTOCOL_V1, RpcResponse::with_error_obj(id, err.into()))
+        }
+    };
+    Some((version, serde_json::to_vec(&resp).ok()?))
+}
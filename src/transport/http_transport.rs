@@ -1,20 +1,39 @@
-use crate::middleware::auth::{AuthMiddleware, AuthenticatedServer};
-use crate::rpc::{RpcResponse, RpcServer};
-use crate::server::metrics::{Metrics, RequestTracer};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::clientcert::PeerCertificate;
+use crate::middleware::ipfilter::IpFilter;
+use crate::middleware::pipeline::{MetricsMiddleware, RpcMiddleware};
+use crate::middleware::ratelimit::{RATE_LIMITED, RateLimiter};
+use crate::rpc::{RequestContext, RpcError, RpcResponse, RpcServer, TransportKind, parse_error};
+use crate::server::metrics::Metrics;
+use crate::transport::tls::{HANDSHAKE_TIMEOUT, HTTP_ALPN, TlsConfig, peer_certificate};
+use crate::transport::websocket::{DEFAULT_WS_KEEPALIVE, ws_handler};
 use crate::util::batch::{BatchRequest, BatchResponse};
 use axum::{
-    Json, Router,
-    extract::State,
-    http::StatusCode,
+    Extension, Json, Router,
+    body::Bytes,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
 };
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::Service;
+use tracing::{debug, error, warn};
 
 /// Example usage:
-/// ```rust
+/// ```no_run
 /// use dice_rpc::*;
+/// # use dice_rpc::rpc::register_default_handlers;
+/// # use dice_rpc::transport::HttpTransport;
+/// # use std::sync::Arc;
 ///
 /// #[tokio::main]
 /// async fn main() -> anyhow::Result<()> {
@@ -36,14 +55,18 @@ use std::sync::Arc;
 ///     Ok(())
 /// }
 /// ```
-
+///
 /// HTTP transport layer for RPC server
-
 #[allow(dead_code)]
 pub struct HttpTransport {
     server: Arc<RpcServer>,
     auth: Option<Arc<AuthMiddleware>>,
     metrics: Option<Arc<Metrics>>,
+    ip_filter: Option<Arc<IpFilter>>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    middleware: Vec<Arc<dyn RpcMiddleware>>,
+    tls: Option<TlsConfig>,
+    ws_keepalive: Duration,
 }
 
 #[allow(dead_code)]
@@ -53,6 +76,11 @@ impl HttpTransport {
             server,
             auth: None,
             metrics: None,
+            ip_filter: None,
+            rate_limit: None,
+            middleware: Vec::new(),
+            tls: None,
+            ws_keepalive: DEFAULT_WS_KEEPALIVE,
         }
     }
 
@@ -66,17 +94,61 @@ impl HttpTransport {
         self
     }
 
+    /// Refuse connections and method calls by peer address; connections
+    /// are only screened by `serve`, method calls by the router too
+    pub fn with_ip_filter(mut self, filter: Arc<IpFilter>) -> Self {
+        self.ip_filter = Some(filter);
+        self
+    }
+
+    /// Apply `limiter`: its per-IP limit before auth, so failed attempts
+    /// count, and the rest after; may be shared with other transports
+    pub fn with_rate_limit(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Add a layer applied after metrics, auth and rate limiting
+    pub fn with_middleware(mut self, layer: impl RpcMiddleware) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
+    /// Serve HTTPS from `serve`. Unless `tls` names its own ALPN protocols,
+    /// HTTP/2 and HTTP/1.1 are offered; certificate reloads apply to new
+    /// connections
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Ping `/ws` clients this often; one that stays silent for two
+    /// intervals is disconnected
+    pub fn with_ws_keepalive(mut self, interval: Duration) -> Self {
+        self.ws_keepalive = interval;
+        self
+    }
+
     /// Create the axum router
     pub fn router(self) -> Router {
-        let state = Arc::new(self);
+        let layers = self.layers();
+        let header_auth = self.auth.clone().filter(|auth| auth.uses_headers());
+        let state = Arc::new(HttpState {
+            server: self.server,
+            layers,
+            header_auth,
+            rate_limit: self.rate_limit,
+            ws_keepalive: self.ws_keepalive,
+        });
 
         let mut router = Router::new()
             .route("/", post(rpc_handler))
             .route("/rpc", post(rpc_handler))
-            .with_state(state.clone());
+            .route("/ws", get(ws_handler))
+            .with_state(state);
 
         // Add metrics endpoints if metrics are enabled
-        if let Some(ref metrics) = state.metrics {
+        if let Some(ref metrics) = self.metrics {
             router = router.merge(crate::transport::metrics_endpoint::metrics_router(
                 metrics.clone(),
             ));
@@ -88,82 +160,271 @@ impl HttpTransport {
     /// Start the HTTP server
     pub async fn serve(self, addr: &str) -> anyhow::Result<()> {
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        println!("HTTP RPC server listening on {}", addr);
+        let auth = self.auth.as_deref();
+        let acceptor = match &self.tls {
+            Some(tls) if tls.alpn().is_empty() => {
+                Some(tls.clone().with_alpn(HTTP_ALPN).acceptor_for(auth)?)
+            }
+            Some(tls) => Some(tls.acceptor_for(auth)?),
+            None => None,
+        };
+        let scheme = if acceptor.is_some() { "HTTPS" } else { "HTTP" };
+        println!("{} RPC server listening on {}", scheme, addr);
+
+        let ip_filter = self.ip_filter.clone();
+        let metrics = self.metrics.clone();
+        let router = self.router();
+
+        // Our own accept loop, so refused peers are dropped before any
+        // HTTP is spoken
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            if let Some(filter) = &ip_filter
+                && !filter.admits(peer.ip())
+            {
+                warn!("Refused HTTP connection from {}", peer);
+                if let Some(metrics) = &metrics {
+                    metrics.record_rejected_connection();
+                }
+                continue;
+            }
+
+            let router = router.clone();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Some(acceptor) = acceptor else {
+                    return serve_connection(stream, peer, None, router).await;
+                };
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let cert = peer_certificate(stream.get_ref().1);
+                        serve_connection(stream, peer, cert, router).await
+                    }
+                    Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => warn!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    }
+}
+
+/// Speak HTTP/1.1 or HTTP/2 (with upgrades) to one client until it leaves
+async fn serve_connection<S>(
+    stream: S,
+    peer: SocketAddr,
+    cert: Option<PeerCertificate>,
+    router: Router,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+        req.extensions_mut().insert(ConnectInfo(peer));
+        if let Some(cert) = &cert {
+            req.extensions_mut().insert(cert.clone());
+        }
+        // A router is always ready, so it can be called directly
+        router.clone().call(req)
+    });
+    let conn = auto::Builder::new(TokioExecutor::new());
+    if let Err(e) = conn
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+    {
+        debug!("HTTP connection from {} ended: {}", peer, e);
+    }
+}
+
+/// Shared state of the `/rpc` and `/ws` routes
+pub(crate) struct HttpState {
+    pub(crate) server: Arc<RpcServer>,
+    pub(crate) layers: Vec<Arc<dyn RpcMiddleware>>,
+    /// Set when auth reads its key from a header: checked once per HTTP
+    /// request (or WebSocket upgrade)
+    pub(crate) header_auth: Option<Arc<AuthMiddleware>>,
+    pub(crate) rate_limit: Option<Arc<RateLimiter>>,
+    pub(crate) ws_keepalive: Duration,
+}
+
+impl HttpState {
+    /// Check header credentials, if auth reads them from headers. Failed
+    /// attempts take a per-IP token and are refused with 429 once the
+    /// peer's bucket is empty, so keys cannot be guessed at full speed
+    pub(crate) async fn authenticate_headers(
+        &self,
+        ctx: &mut RequestContext,
+        body: &[u8],
+    ) -> Result<(), Response> {
+        let Some(auth) = &self.header_auth else {
+            return Ok(());
+        };
+        if let Some(limiter) = &self.rate_limit {
+            limiter.admit_peer(ctx).await.map_err(too_many_requests)?;
+        }
+        match auth.authenticate_http(ctx, body).await {
+            Ok(principal) => {
+                ctx.principal = Some(principal);
+                auth.strip_credentials(ctx);
+                Ok(())
+            }
+            // A full nonce cache is no fault of the caller's credentials
+            Err(err) if err.code == RATE_LIMITED => Err(too_many_requests(err)),
+            Err(err) => {
+                if let Some(limiter) = &self.rate_limit {
+                    limiter.charge_peer(ctx);
+                }
+                Err(unauthorized(err))
+            }
+        }
+    }
+}
 
-        axum::serve(listener, self.router()).await?;
-        Ok(())
+impl HttpTransport {
+    /// The per-request stack for this transport: metrics, IP rules, the
+    /// per-IP rate limit, auth, per-key and per-method rate limits, then
+    /// extras
+    fn layers(&self) -> Vec<Arc<dyn RpcMiddleware>> {
+        let mut layers: Vec<Arc<dyn RpcMiddleware>> = Vec::new();
+        if let Some(metrics) = &self.metrics {
+            layers.push(Arc::new(MetricsMiddleware::new(metrics.clone())));
+        }
+        if let Some(filter) = &self.ip_filter {
+            layers.push(filter.clone());
+        }
+        if let Some(limiter) = &self.rate_limit {
+            layers.push(limiter.peer_layer());
+        }
+        if let Some(auth) = &self.auth {
+            layers.push(auth.clone());
+        }
+        if let Some(limiter) = &self.rate_limit {
+            layers.push(limiter.caller_layer());
+        }
+        layers.extend(self.middleware.iter().cloned());
+        layers
+    }
+}
+
+/// Build the request context for an HTTP call
+pub(crate) fn request_context(peer: Option<SocketAddr>, headers: &HeaderMap) -> RequestContext {
+    let mut map: HashMap<String, String> = HashMap::new();
+    for (name, value) in headers {
+        if let Ok(value) = value.to_str() {
+            map.entry(name.as_str().to_string())
+                .and_modify(|v| {
+                    v.push_str(", ");
+                    v.push_str(value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+    }
+
+    let ctx = RequestContext::new(TransportKind::Http).with_headers(map);
+    match peer {
+        Some(addr) => ctx.with_peer_addr(addr),
+        None => ctx,
     }
 }
 
 /// Main RPC handler for HTTP requests
 async fn rpc_handler(
-    State(transport): State<Arc<HttpTransport>>,
-    Json(payload): Json<Value>,
+    State(state): State<Arc<HttpState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    cert: Option<Extension<PeerCertificate>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Response {
-    // Parse as batch request (handles both single and batch)
-    let batch_req = match serde_json::from_value::<BatchRequest>(payload) {
+    let mut ctx = request_context(connect_info.map(|ConnectInfo(addr)| addr), &headers);
+    if let Some(Extension(cert)) = cert {
+        ctx.extensions.insert(cert);
+    }
+
+    // Header credentials (and signatures over the raw body) cover the whole
+    // HTTP request, batch included; a failure is answered with 401 before
+    // the body is even parsed
+    if let Err(response) = state.authenticate_headers(&mut ctx, &body).await {
+        return response;
+    }
+
+    // Parse the raw body ourselves so malformed JSON gets a JSON-RPC
+    // parse error instead of axum's plain-text rejection
+    let batch_req = match serde_json::from_slice::<Value>(&body)
+        .map_err(parse_error)
+        .and_then(BatchRequest::from_value)
+    {
         Ok(req) => req,
-        Err(e) => {
-            let error_response =
-                RpcResponse::with_error(Value::Null, -32700, format!("Parse error: {}", e));
+        Err(error_response) => {
             return (StatusCode::OK, Json(error_response)).into_response();
         }
     };
 
-    //METRICS TRACKING
-    let method = match &batch_req {
-        BatchRequest::Single(req) => req.method.clone(),
-        BatchRequest::Batch(reqs) => format!("batch({})", reqs.len()),
-    };
-
-    let tracer = if let Some(metrics) = &transport.metrics {
-        Some(RequestTracer::new(&method, metrics.clone()))
-    } else {
-        None
-    };
-
-    // Handle with or without authentication
-    let batch_resp = if let Some(auth) = &transport.auth {
-        handle_authenticated_batch(&transport.server, batch_req, auth).await
-    } else {
-        transport.server.handle_batch(batch_req).await
-    };
-
-    // ← CHECK FOR ERRORS AND RECORD
-    if let Some(tracer) = tracer {
-        let has_error = match &batch_resp {
-            BatchResponse::Single(resp) => resp.error.is_some(),
-            BatchResponse::Batch(resps) => resps.iter().any(|r| r.error.is_some()),
-        };
+    // Auth, metrics and any extra layers run per entry inside the server
+    let batch_resp = state
+        .server
+        .handle_batch_with_middleware(batch_req, ctx, &state.layers)
+        .await;
 
-        if has_error {
-            tracer.error("Request returned error").await;
-        } else {
-            tracer.success().await;
-        }
+    match batch_resp {
+        Some(batch_resp) => match retry_after(&batch_resp) {
+            Some(secs) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, secs.to_string())],
+                Json(batch_resp),
+            )
+                .into_response(),
+            None => (StatusCode::OK, Json(batch_resp)).into_response(),
+        },
+        // Only notifications: nothing to return
+        None => StatusCode::NO_CONTENT.into_response(),
     }
+}
 
-    (StatusCode::OK, Json(batch_resp)).into_response()
+/// 401 with the auth error as a JSON-RPC error response
+fn unauthorized(err: RpcError) -> Response {
+    let body = RpcResponse::with_error_obj(Value::Null, err.into());
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(body),
+    )
+        .into_response()
 }
 
-/// Handle batch request with authentication
-async fn handle_authenticated_batch(
-    server: &RpcServer,
-    batch: BatchRequest,
-    auth: &AuthMiddleware,
-) -> BatchResponse {
-    match batch {
-        BatchRequest::Single(req) => {
-            BatchResponse::Single(server.handle_authenticated_request(req, auth).await)
-        }
-        BatchRequest::Batch(requests) => {
-            let futures: Vec<_> = requests
-                .into_iter()
-                .map(|req| server.handle_authenticated_request(req, auth))
-                .collect();
-
-            let responses = futures::future::join_all(futures).await;
-            BatchResponse::Batch(responses)
-        }
-    }
+/// 429 for a request refused before it reached the layers
+fn too_many_requests(err: RpcError) -> Response {
+    let wait_ms = err
+        .data
+        .as_ref()
+        .and_then(|d| d["retry_after_ms"].as_u64())
+        .unwrap_or(0);
+    let body = RpcResponse::with_error_obj(Value::Null, err.into());
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, wait_ms.div_ceil(1000).to_string())],
+        Json(body),
+    )
+        .into_response()
+}
+
+/// Seconds to put in `Retry-After` when every response was rate limited;
+/// a batch with any other outcome is still answered with 200
+fn retry_after(batch_resp: &BatchResponse) -> Option<u64> {
+    let responses = match batch_resp {
+        BatchResponse::Single(resp) => std::slice::from_ref(resp),
+        BatchResponse::Batch(resps) => resps.as_slice(),
+    };
+    responses.iter().try_fold(0, |secs: u64, resp| {
+        let error = resp.error.as_ref().filter(|e| e.code == RATE_LIMITED)?;
+        let wait_ms = error
+            .data
+            .as_ref()
+            .and_then(|d| d["retry_after_ms"].as_u64())
+            .unwrap_or(0);
+        Some(secs.max(wait_ms.div_ceil(1000)))
+    })
 }
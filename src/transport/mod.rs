@@ -1,18 +1,31 @@
 pub mod framing;
 pub mod shutdown;
 pub mod metrics_endpoint;
+pub mod tls;
+pub mod stdio;
 
 #[cfg(feature = "http")]
 pub mod http_transport;
 
+#[cfg(feature = "http")]
+pub mod websocket;
+
 #[cfg(feature = "tcp")]
 pub mod tcp;
 
-pub use framing::FrameCodec;
+#[cfg(all(feature = "tcp", unix))]
+pub mod unix;
+
+pub use framing::{Frame, FrameCodec, FrameCodecV2, FrameError, FrameFlags, MessageType};
 pub use shutdown::ShutdownCoordinator;
+pub use tls::{TlsClientConfig, TlsConfig};
+pub use stdio::{StdioClient, StdioFraming, StdioServerConfig, run_stdio};
 
 #[cfg(feature = "http")]
 pub use http_transport::HttpTransport;
 
 #[cfg(feature = "tcp")]
-pub use tcp::{TcpServerConfig, run_with_framing};
\ No newline at end of file
+pub use tcp::{TcpServerConfig, run_with_framing};
+
+#[cfg(all(feature = "tcp", unix))]
+pub use unix::{UnixServerConfig, run_unix};
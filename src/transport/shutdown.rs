@@ -10,9 +10,10 @@ pub struct ShutdownCoordinator {
 
 /// Example server with graceful shutdown
 /// 
-/// ```rust
-/// use dice_rpc::shutdown::*;
-/// use std::time::Duration;
+/// ```no_run
+/// use dice_rpc::transport::shutdown::*;
+/// # async fn run_server() -> anyhow::Result<()> { Ok(()) }
+/// # async fn perform_cleanup() {}
 /// 
 /// #[tokio::main]
 /// async fn main() -> anyhow::Result<()> {
@@ -40,7 +41,6 @@ pub struct ShutdownCoordinator {
 ///     Ok(())
 /// }
 /// ```
-
 impl ShutdownCoordinator {
     pub fn new() -> Self {
         let (tx, _) = broadcast::channel(1);
@@ -0,0 +1,291 @@
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::pipeline::{MetricsMiddleware, RpcMiddleware};
+use crate::rpc::{RequestContext, RpcServer, TransportKind, parse_error};
+use crate::server::metrics::Metrics;
+use crate::transport::framing::FrameCodec;
+use crate::util::batch::BatchRequest;
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tracing::{debug, info};
+
+/// Largest `Content-Length` body accepted, matching the `FrameCodec` limit
+pub const MAX_MESSAGE_SIZE: usize = 10_000_000;
+
+/// How messages are delimited on stdin and stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StdioFraming {
+    /// `FrameCodec` frames: a 4-byte big-endian length, then the payload
+    #[default]
+    LengthPrefixed,
+    /// Language Server Protocol style: `Content-Length: N` and any other
+    /// headers, a blank line, then N bytes of JSON
+    ContentLength,
+}
+
+impl std::str::FromStr for StdioFraming {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "length" => Ok(StdioFraming::LengthPrefixed),
+            "lsp" | "content-length" => Ok(StdioFraming::ContentLength),
+            other => bail!("unknown framing {:?} (expected length or lsp)", other),
+        }
+    }
+}
+
+impl StdioFraming {
+    /// The next message, or `None` once the input ends between messages
+    pub async fn read_message<R>(self, reader: &mut R) -> Result<Option<Vec<u8>>>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        match self {
+            StdioFraming::LengthPrefixed => {
+                if reader.fill_buf().await?.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(FrameCodec::read_frame(reader).await?))
+            }
+            StdioFraming::ContentLength => read_content_length(reader).await,
+        }
+    }
+
+    pub async fn write_message<W>(self, writer: &mut W, data: &[u8]) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            StdioFraming::LengthPrefixed => Ok(FrameCodec::write_frame(writer, data).await?),
+            StdioFraming::ContentLength => {
+                let header = format!("Content-Length: {}\r\n\r\n", data.len());
+                writer.write_all(header.as_bytes()).await?;
+                writer.write_all(data).await?;
+                writer.flush().await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn read_content_length<R>(reader: &mut R) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut length = None;
+    let mut line = String::new();
+    let mut first = true;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            if first {
+                return Ok(None);
+            }
+            bail!("Input ended inside message headers");
+        }
+        first = false;
+        let header = line.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            break;
+        }
+        // Other headers (Content-Type) are allowed and ignored
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            let value = value.trim();
+            length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Invalid Content-Length: {:?}", value))?,
+            );
+        }
+    }
+
+    let length = length.ok_or_else(|| anyhow!("Message without Content-Length header"))?;
+    if length > MAX_MESSAGE_SIZE {
+        bail!("Message too large: {} bytes", length);
+    }
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+pub struct StdioServerConfig {
+    pub server: Arc<RpcServer>,
+    pub framing: StdioFraming,
+    pub auth: Option<Arc<AuthMiddleware>>,
+    pub metrics: Arc<Metrics>,
+    /// Extra layers applied after metrics and auth
+    pub middleware: Vec<Arc<dyn RpcMiddleware>>,
+}
+
+impl StdioServerConfig {
+    pub fn new(server: Arc<RpcServer>) -> Self {
+        Self {
+            server,
+            framing: StdioFraming::default(),
+            auth: None,
+            metrics: Arc::new(Metrics::new()),
+            middleware: Vec::new(),
+        }
+    }
+
+    pub fn with_framing(mut self, framing: StdioFraming) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: Arc<AuthMiddleware>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_middleware(mut self, layer: impl RpcMiddleware) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
+    fn layers(&self) -> Vec<Arc<dyn RpcMiddleware>> {
+        let mut layers: Vec<Arc<dyn RpcMiddleware>> =
+            vec![Arc::new(MetricsMiddleware::new(self.metrics.clone()))];
+        if let Some(auth) = &self.auth {
+            layers.push(auth.clone());
+        }
+        layers.extend(self.middleware.iter().cloned());
+        layers
+    }
+}
+
+/// Serve requests from stdin, writing responses to stdout, until stdin
+/// closes. Nothing else may write to stdout meanwhile; log to stderr.
+pub async fn run_stdio(config: StdioServerConfig) -> Result<()> {
+    info!("DiceRPC stdio server ({:?}) reading stdin", config.framing);
+    serve(config, tokio::io::stdin(), tokio::io::stdout()).await
+}
+
+/// Serve requests read from `reader` with responses to `writer`, in order,
+/// until `reader` ends
+pub async fn serve<R, W>(config: StdioServerConfig, reader: R, mut writer: W) -> Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(reader);
+    let layers = config.layers();
+    let ctx = RequestContext::new(TransportKind::Stdio);
+
+    while let Some(message) = config.framing.read_message(&mut reader).await? {
+        let reply = match serde_json::from_slice::<Value>(&message)
+            .map_err(parse_error)
+            .and_then(BatchRequest::from_value)
+        {
+            Ok(batch) => match config
+                .server
+                .handle_batch_with_middleware(batch, ctx.clone(), &layers)
+                .await
+            {
+                Some(resp) => serde_json::to_vec(&resp)?,
+                None => continue,
+            },
+            Err(error_resp) => serde_json::to_vec(&error_resp)?,
+        };
+        config.framing.write_message(&mut writer, &reply).await?;
+    }
+
+    debug!("stdin closed; stdio server exiting");
+    Ok(())
+}
+
+/// A server running as a child process, spoken to over its stdin and
+/// stdout. Its stderr is inherited, so its logs show up alongside ours.
+///
+/// ```no_run
+/// # use dice_rpc::transport::{StdioClient, StdioFraming};
+/// # use serde_json::json;
+/// # use tokio::process::Command;
+/// # async fn example() -> anyhow::Result<()> {
+/// let mut cmd = Command::new("dice_rpc");
+/// cmd.arg("stdio-server");
+/// let mut client = StdioClient::spawn(cmd, StdioFraming::LengthPrefixed)?;
+/// let balance = client.call("get_balance", json!({"address": "0xAlice"})).await?;
+/// client.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StdioClient {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    framing: StdioFraming,
+    next_id: u64,
+}
+
+impl StdioClient {
+    pub fn spawn(mut command: Command, framing: StdioFraming) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("cannot spawn stdio server")?;
+        let stdin = child.stdin.take().context("child has no stdin")?;
+        let stdout = child.stdout.take().context("child has no stdout")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            framing,
+            next_id: 1,
+        })
+    }
+
+    /// Send a request or batch and wait for its response; `None` when it
+    /// was only notifications
+    pub async fn request(&mut self, request: &Value) -> Result<Option<Value>> {
+        let bytes = serde_json::to_vec(request)?;
+        self.framing.write_message(&mut self.stdin, &bytes).await?;
+        let expects_reply = match request {
+            Value::Array(entries) => entries.iter().any(|e| e.get("id").is_some()),
+            _ => request.get("id").is_some(),
+        };
+        if !expects_reply {
+            return Ok(None);
+        }
+        let reply = self
+            .framing
+            .read_message(&mut self.stdout)
+            .await?
+            .ok_or_else(|| anyhow!("stdio server exited"))?;
+        Ok(Some(serde_json::from_slice(&reply)?))
+    }
+
+    /// Call `method` and return its response
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let req = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": id});
+        self.request(&req)
+            .await?
+            .ok_or_else(|| anyhow!("no response to {}", method))
+    }
+
+    /// Close the child's stdin and wait for it to exit
+    pub async fn shutdown(self) -> Result<std::process::ExitStatus> {
+        let Self {
+            mut child, stdin, ..
+        } = self;
+        drop(stdin);
+        Ok(child.wait().await?)
+    }
+}
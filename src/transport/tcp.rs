@@ -1,21 +1,81 @@
 use tokio::net::{TcpListener, TcpStream};
-use crate::rpc::{RpcServer, parse_rpc_request};
-use crate::transport::framing::FrameCodec;
-use crate::util::batch::{BatchRequest, BatchResponse};
+use crate::rpc::{RequestContext, RpcError, RpcResponse, RpcServer, TransportKind, parse_error};
+use crate::transport::framing::{
+    ConnectionCodec, Frame, FrameCodec, FrameError, FrameFlags, MAX_FRAME_SIZE, MessageType,
+    PROTOCOL_V2, answer_hello,
+};
+pub use crate::transport::framing::CONNECTION_CLOSED;
+use crate::util::batch::BatchRequest;
 use crate::middleware::auth::AuthMiddleware;
-use crate::server::metrics::{Metrics, RequestTracer};
-use crate::transport::shutdown::ShutdownCoordinator;use anyhow::Result;
-use std::sync::Arc;
-use tracing::{info, error};
-use tokio::io::AsyncWriteExt;
+use crate::middleware::clientcert::PeerCertificate;
+use crate::middleware::ipfilter::IpFilter;
+use crate::middleware::pipeline::{MetricsMiddleware, RpcMiddleware};
+use crate::middleware::ratelimit::RateLimiter;
+use crate::middleware::session::SessionMiddleware;
+use crate::middleware::signing::SignedEnvelope;
+use crate::server::metrics::Metrics;
+use crate::transport::shutdown::ShutdownCoordinator;
+use crate::transport::tls::{HANDSHAKE_TIMEOUT, TlsConfig, peer_certificate};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::io::AsyncBufReadExt;
 use tokio::io::BufReader;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+
+/// Requests one framed connection may have running at once, unless
+/// configured otherwise
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+/// How long a frame may take to arrive once it has started, unless
+/// configured otherwise
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a refused connection gets to receive its error frame
+const REFUSAL_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub struct TcpServerConfig {
     pub addr: String,
     pub server: Arc<RpcServer>,
     pub auth: Option<Arc<AuthMiddleware>>,
     pub metrics: Arc<Metrics>,
+    /// Peers refused at accept, and per-method address restrictions
+    pub ip_filter: Option<Arc<IpFilter>>,
+    /// Per-IP limits checked before auth and sessions, the rest after;
+    /// may be shared with other transports
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    /// Extra layers applied after metrics, auth and rate limiting
+    pub middleware: Vec<Arc<dyn RpcMiddleware>>,
+    /// When set (and auth is enabled), clients may authenticate once per
+    /// connection via `rpc.auth`; the session lasts this long
+    pub session_ttl: Option<Duration>,
+    /// Serve TLS instead of plain TCP
+    pub tls: Option<TlsConfig>,
+    /// Requests dispatched concurrently per connection; also the size of
+    /// its response queue
+    pub max_in_flight: usize,
+    /// Largest frame payload read or written, in bytes
+    pub max_frame_length: usize,
+    /// Time allowed for a frame to arrive once its first bytes have
+    pub read_timeout: Option<Duration>,
+    /// Close connections that send no frame for this long
+    pub idle_timeout: Option<Duration>,
+    /// Close connections that do not take a response within this long
+    pub write_timeout: Option<Duration>,
+    /// Open connections allowed at once, across all peers
+    pub max_connections: Option<usize>,
+    /// Open connections allowed at once from one IP address
+    pub max_connections_per_ip: Option<usize>,
 }
 
 impl TcpServerConfig {
@@ -25,6 +85,18 @@ impl TcpServerConfig {
             server,
             auth: None,
             metrics: Arc::new(Metrics::new()),
+            ip_filter: None,
+            rate_limit: None,
+            middleware: Vec::new(),
+            session_ttl: None,
+            tls: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_frame_length: MAX_FRAME_SIZE,
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+            idle_timeout: None,
+            write_timeout: None,
+            max_connections: None,
+            max_connections_per_ip: None,
         }
     }
 
@@ -37,12 +109,139 @@ impl TcpServerConfig {
         self.metrics = metrics;
         self
     }
+
+    /// Refuse connections and method calls by peer address; the rules
+    /// can be reloaded through `filter` while the server runs
+    pub fn with_ip_filter(mut self, filter: Arc<IpFilter>) -> Self {
+        self.ip_filter = Some(filter);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    pub fn with_middleware(mut self, layer: impl RpcMiddleware) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
+    /// Enable per-connection sessions opened with `rpc.auth`
+    pub fn with_sessions(mut self, ttl: Duration) -> Self {
+        self.session_ttl = Some(ttl);
+        self
+    }
+
+    /// Require TLS; certificates reloaded through `tls` (or a clone of it)
+    /// apply to new connections
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Let each connection run up to `limit` requests at once; with 1,
+    /// requests are answered strictly in order
+    pub fn with_max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = limit;
+        self
+    }
+
+    /// Refuse frames with payloads over `max` bytes; a peer sending one is
+    /// disconnected, and a response over it is dropped
+    pub fn with_max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+
+    /// Close a connection whose next frame, once started, is not complete
+    /// within `timeout` (30 seconds by default)
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Wait as long as it takes for a started frame to complete
+    pub fn without_read_timeout(mut self) -> Self {
+        self.read_timeout = None;
+        self
+    }
+
+    /// Close connections that send no frame for `timeout`. Requests still
+    /// running are answered first. Subscribers should ping to stay open.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections whose peer does not read a response within `timeout`
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Refuse connections beyond `limit` open at once
+    pub fn with_max_connections(mut self, limit: usize) -> Self {
+        self.max_connections = Some(limit);
+        self
+    }
+
+    /// Refuse connections beyond `limit` open at once from one IP address
+    pub fn with_max_connections_per_ip(mut self, limit: usize) -> Self {
+        self.max_connections_per_ip = Some(limit);
+        self
+    }
+
+    pub(crate) fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits {
+            max_in_flight: self.max_in_flight,
+            max_frame_length: self.max_frame_length,
+            read_timeout: self.read_timeout,
+            idle_timeout: self.idle_timeout,
+            write_timeout: self.write_timeout,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// The per-request stack for one connection: metrics, IP rules, the
+    /// per-IP rate limit, the connection's session, auth, per-key and
+    /// per-method rate limits, then extras
+    fn connection_layers(&self) -> Vec<Arc<dyn RpcMiddleware>> {
+        let mut layers: Vec<Arc<dyn RpcMiddleware>> =
+            vec![Arc::new(MetricsMiddleware::new(self.metrics.clone()))];
+        if let Some(filter) = &self.ip_filter {
+            layers.push(filter.clone());
+        }
+        if let Some(limiter) = &self.rate_limit {
+            layers.push(limiter.peer_layer());
+        }
+        if let Some(auth) = &self.auth {
+            if let Some(ttl) = self.session_ttl {
+                layers.push(Arc::new(SessionMiddleware::new(auth.clone(), ttl)));
+            }
+            layers.push(auth.clone());
+        }
+        if let Some(limiter) = &self.rate_limit {
+            layers.push(limiter.caller_layer());
+        }
+        layers.extend(self.middleware.iter().cloned());
+        layers
+    }
 }
 
 /// Run TCP server with length-prefixed framing
 pub async fn run_with_framing(config: TcpServerConfig) -> Result<()> {
     let listener = TcpListener::bind(&config.addr).await?;
-    info!("DiceRPC TCP server (framed) listening on {}", config.addr);
+    let acceptor = config
+        .tls
+        .as_ref()
+        .map(|tls| tls.acceptor_for(config.auth.as_deref()))
+        .transpose()?;
+    info!(
+        "DiceRPC TCP server (framed{}) listening on {}",
+        if acceptor.is_some() { ", TLS" } else { "" },
+        config.addr
+    );
 
     let shutdown = Arc::new(ShutdownCoordinator::new());
     let shutdown_clone = shutdown.clone();
@@ -52,22 +251,61 @@ pub async fn run_with_framing(config: TcpServerConfig) -> Result<()> {
         shutdown_clone.wait_for_signal().await;
     });
 
-    let server = config.server;
-    let auth = config.auth;
-    let metrics = config.metrics;
+    let slots = ConnectionSlots::new(config.max_connections, config.max_connections_per_ip);
+    let config = Arc::new(config);
     let mut shutdown_rx = shutdown.subscribe();
 
     loop {
         tokio::select! {
             accept_result = listener.accept() => {
                 match accept_result {
-                    Ok((socket, _)) => {
-                        let server = server.clone();
-                        let auth = auth.clone();
-                        let metrics = metrics.clone();
+                    Ok((socket, peer)) => {
+                        if let Some(filter) = &config.ip_filter
+                            && !filter.admits(peer.ip())
+                        {
+                            warn!("Refused TCP connection from {}", peer);
+                            config.metrics.record_rejected_connection();
+                            continue;
+                        }
+                        let slot = match slots.acquire(peer.ip()) {
+                            Ok(slot) => slot,
+                            Err(reason) => {
+                                warn!("Refused TCP connection from {}: {}", peer, reason);
+                                config.metrics.record_rejected_connection();
+                                // TLS clients cannot read a plaintext frame
+                                if acceptor.is_none() {
+                                    tokio::spawn(refuse(socket, reason));
+                                }
+                                continue;
+                            }
+                        };
+                        let server = config.server.clone();
+                        let layers = config.connection_layers();
+                        let signed_auth = config.auth.clone().filter(|a| a.expects_signatures());
+                        let acceptor = acceptor.clone();
+                        let limits = config.connection_limits();
                         
                         tokio::spawn(async move {
-                            if let Err(e) = handle_framed_connection(server, socket, auth, metrics).await {
+                            let _slot = slot;
+                            let result = match acceptor {
+                                Some(acceptor) => {
+                                    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                                        Ok(Ok(stream)) => stream,
+                                        Ok(Err(e)) => {
+                                            warn!("TLS handshake with {} failed: {}", peer, e);
+                                            return;
+                                        }
+                                        Err(_) => {
+                                            warn!("TLS handshake with {} timed out", peer);
+                                            return;
+                                        }
+                                    };
+                                    let cert = peer_certificate(stream.get_ref().1);
+                                    handle_framed_connection(server, stream, framed_context(peer, cert), layers, signed_auth, limits).await
+                                }
+                                None => handle_framed_connection(server, socket, framed_context(peer, None), layers, signed_auth, limits).await,
+                            };
+                            if let Err(e) = result {
                                 error!("Connection error: {:?}", e);
                             }
                         });
@@ -87,126 +325,448 @@ pub async fn run_with_framing(config: TcpServerConfig) -> Result<()> {
     Ok(())
 }
 
-async fn handle_framed_connection(
+/// Holds the global and per-IP connection counts
+struct ConnectionSlots {
+    total: Option<Arc<Semaphore>>,
+    per_ip: Option<usize>,
+    by_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// One accepted connection's share of `ConnectionSlots`, given back on drop
+struct ConnectionSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    ip: IpAddr,
+    by_ip: Option<Arc<Mutex<HashMap<IpAddr, usize>>>>,
+}
+
+impl ConnectionSlots {
+    fn new(total: Option<usize>, per_ip: Option<usize>) -> Self {
+        Self {
+            total: total.map(|limit| Arc::new(Semaphore::new(limit))),
+            per_ip,
+            by_ip: Arc::default(),
+        }
+    }
+
+    /// A slot for a connection from `ip`, or the name of the limit it is over
+    fn acquire(&self, ip: IpAddr) -> Result<ConnectionSlot, &'static str> {
+        let permit = match &self.total {
+            Some(total) => Some(
+                total
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| "max_connections")?,
+            ),
+            None => None,
+        };
+        let by_ip = match self.per_ip {
+            Some(limit) => {
+                let mut counts = self.by_ip.lock().unwrap_or_else(|e| e.into_inner());
+                let count = counts.entry(ip).or_insert(0);
+                if *count >= limit {
+                    return Err("max_connections_per_ip");
+                }
+                *count += 1;
+                Some(self.by_ip.clone())
+            }
+            None => None,
+        };
+        Ok(ConnectionSlot {
+            _permit: permit,
+            ip,
+            by_ip,
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        if let Some(by_ip) = &self.by_ip {
+            let mut counts = by_ip.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(count) = counts.get_mut(&self.ip) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&self.ip);
+                }
+            }
+        }
+    }
+}
+
+/// Answer a connection over a limit with an error frame, then close it
+async fn refuse(mut socket: TcpStream, reason: &'static str) {
+    let Outgoing::Frame(frame) = goaway(reason) else {
+        return;
+    };
+    let write = FrameCodec::write_frame(&mut socket, &frame.payload);
+    let _ = tokio::time::timeout(REFUSAL_WRITE_TIMEOUT, write).await;
+    let _ = socket.shutdown().await;
+}
+
+/// The context every request on a framed TCP connection starts from
+fn framed_context(peer: SocketAddr, cert: Option<PeerCertificate>) -> RequestContext {
+    let mut ctx = RequestContext::new(TransportKind::TcpFramed).with_peer_addr(peer);
+    if let Some(cert) = cert {
+        ctx.extensions.insert(cert);
+    }
+    ctx
+}
+
+/// Serve length-prefixed frames on `stream` until the peer hangs up; every
+/// request starts from `base_ctx`. Shared by the TCP and Unix transports.
+///
+/// Frames are dispatched as they arrive, each in its own task, with at most
+/// `max_in_flight` running at once; reading pauses at the limit. Responses
+/// are written as they complete, so they can come back out of order and are
+/// matched to requests by id. One writer task owns the write half and
+/// drains a queue of the same size, so a peer that stops reading stalls its
+/// own requests rather than buffering without bound.
+///
+/// A first frame calling `rpc.hello` may switch the connection to v2
+/// frames; responses then go back on the stream id of their request.
+///
+/// A read or idle timeout, or a frame that cannot be decoded, stops
+/// reading and closes the connection with a goaway frame (an error
+/// response with a null id on v1) once running requests answer.
+pub(crate) async fn handle_framed_connection<S>(
     server: Arc<RpcServer>,
-    mut stream: TcpStream,
-    auth: Option<Arc<AuthMiddleware>>,
-    metrics: Arc<Metrics>,
-) -> Result<()> {
+    stream: S,
+    mut base_ctx: RequestContext,
+    layers: Vec<Arc<dyn RpcMiddleware>>,
+    signed_auth: Option<Arc<AuthMiddleware>>,
+    limits: ConnectionLimits,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let max_in_flight = limits.max_in_flight.max(1);
+    let (read_half, write_half) = tokio::io::split(stream);
+    let mut frames = FramedRead::new(read_half, ConnectionCodec::new(limits.max_frame_length));
+    let (tx, mut rx) = mpsc::channel::<Outgoing>(max_in_flight);
+    let (write_timeout, metrics) = (limits.write_timeout, limits.metrics.clone());
+    let writer = tokio::spawn(async move {
+        let mut sink = FramedWrite::new(write_half, ConnectionCodec::new(limits.max_frame_length));
+        while let Some(out) = rx.recv().await {
+            // Frames queued together go out in one write
+            let write = send_outgoing(&mut sink, out, rx.is_empty());
+            match write_timeout {
+                Some(limit) => {
+                    if tokio::time::timeout(limit, write).await.is_err() {
+                        metrics.record_timed_out_connection();
+                        anyhow::bail!("write timed out after {:?}", limit);
+                    }
+                }
+                None => write.await?,
+            }
+        }
+        anyhow::Ok(())
+    });
+
+    // Subscription notifications go through the same queue as responses
+    let subscriber = server.subscriber().map(|(subscriber, mut notifications)| {
+        base_ctx.extensions.insert(subscriber.clone());
+        let tx = tx.clone();
+        let forwarder = tokio::spawn(async move {
+            while let Some(notification) = notifications.recv().await {
+                let Ok(bytes) = serde_json::to_vec(&notification) else {
+                    continue;
+                };
+                let push = Frame::new(MessageType::Notification, 0, bytes);
+                if tx.send(Outgoing::Frame(push)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        (subscriber, forwarder)
+    });
+    let overflowed = async {
+        match &subscriber {
+            Some((subscriber, _)) => subscriber.overflowed().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(overflowed);
+
+    let layers: Arc<[Arc<dyn RpcMiddleware>]> = layers.into();
+    let in_flight = Arc::new(Semaphore::new(max_in_flight));
+    let mut first = true;
+    let mut closing = None;
+    let mut failed = None;
+
     loop {
         // Read framed message
-        let frame = match FrameCodec::read_frame(&mut stream).await {
-            Ok(f) => f,
-            Err(e) => {
-                if e.to_string().contains("unexpected end of file") {
-                    // Client disconnected
+        let frame = tokio::select! {
+            frame = next_frame(&mut frames, &limits) => frame,
+            _ = &mut overflowed => {
+                warn!("Closing connection from {:?}: subscription buffer overflowed", base_ctx.peer_addr);
+                break;
+            }
+            // The writer gave up on a peer that stopped reading
+            _ = tx.closed() => break,
+        };
+        let frame = match frame {
+            NextFrame::Frame(Ok(f)) => f,
+            // Client disconnected (over TLS, possibly without close_notify)
+            NextFrame::Closed | NextFrame::Frame(Err(FrameError::Eof)) => break,
+            NextFrame::Frame(Err(e)) => {
+                closing = match &e {
+                    FrameError::TooLarge { .. } => Some("frame_too_large"),
+                    FrameError::Malformed(_) => Some("malformed_frame"),
+                    FrameError::Eof | FrameError::Io(_) => None,
+                };
+                failed = Some(e);
+                break;
+            }
+            NextFrame::TimedOut(reason) => {
+                info!(
+                    "Closing connection from {:?}: {}",
+                    base_ctx.peer_addr, reason
+                );
+                limits.metrics.record_timed_out_connection();
+                closing = Some(reason);
+                break;
+            }
+        };
+
+        // The handshake is only honoured as the very first frame
+        if std::mem::take(&mut first)
+            && let Some((negotiated, reply)) = answer_hello(&frame.payload)
+        {
+            let reply = Frame::new(MessageType::Response, 0, reply);
+            if tx.send(Outgoing::Frame(reply)).await.is_err() {
+                break;
+            }
+            if negotiated == PROTOCOL_V2 {
+                debug!("{:?} switched to v2 frames", base_ctx.peer_addr);
+                frames.decoder_mut().upgrade();
+                let _ = tx.send(Outgoing::Upgrade).await;
+            }
+            continue;
+        }
+
+        let stream_id = frame.stream_id;
+        match frame.kind {
+            MessageType::Request | MessageType::Notification => {}
+            MessageType::Ping => {
+                let pong = Frame::new(MessageType::Pong, stream_id, frame.payload);
+                if tx.send(Outgoing::Frame(pong)).await.is_err() {
                     break;
                 }
-                return Err(e);
+                continue;
+            }
+            MessageType::GoAway => {
+                debug!("{:?} sent goaway", base_ctx.peer_addr);
+                break;
             }
+            kind @ (MessageType::Response | MessageType::Pong | MessageType::Cancel) => {
+                debug!("Ignoring {:?} frame from {:?}", kind, base_ctx.peer_addr);
+                continue;
+            }
+        }
+        // Compression and other encodings are not implemented yet
+        if frame.flags != FrameFlags::default() {
+            let err = RpcError::invalid_request("Unsupported frame flags")
+                .with_data(serde_json::json!({"flags": frame.flags.bits()}));
+            let resp = RpcResponse::with_error_obj(serde_json::Value::Null, err.into());
+            let reply = Frame::new(MessageType::Response, stream_id, serde_json::to_vec(&resp)?);
+            if tx.send(Outgoing::Frame(reply)).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        let wants_reply = frame.kind == MessageType::Request;
+        let frame = frame.payload;
+
+        // A signed envelope authenticates the message it wraps; unsigned
+        // frames go on and are rejected entry by entry by the auth layer
+        let mut ctx = base_ctx.clone();
+        let frame = match signed_auth.as_ref().zip(SignedEnvelope::parse(&frame)) {
+            Some((auth, envelope)) => match auth.verify_signed(&envelope.request()) {
+                Ok(principal) => {
+                    ctx.principal = Some(principal);
+                    Bytes::from(envelope.payload)
+                }
+                Err(err) => {
+                    let resp = RpcResponse::with_error_obj(serde_json::Value::Null, err.into());
+                    let reply =
+                        Frame::new(MessageType::Response, stream_id, serde_json::to_vec(&resp)?);
+                    if tx.send(Outgoing::Frame(reply)).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            },
+            None => frame,
         };
 
-        // Parse as JSON string
-        let raw = String::from_utf8(frame)?;
-        
-        // Parse as batch request
-        let batch_req = match BatchRequest::parse(&raw) {
+        // Parse as JSON string, then as batch request; invalid UTF-8 is a parse error
+        let batch_req = match std::str::from_utf8(&frame)
+            .map_err(parse_error)
+            .and_then(BatchRequest::parse)
+        {
             Ok(req) => req,
-            Err(e) => {
-                let error_resp = crate::rpc::RpcResponse::with_error(
-                    serde_json::Value::Null,
-                    -32700,
-                    format!("Parse error: {}", e),
+            Err(error_resp) => {
+                let reply = Frame::new(
+                    MessageType::Response,
+                    stream_id,
+                    serde_json::to_vec(&error_resp)?,
                 );
-                let resp_bytes = serde_json::to_vec(&error_resp)?;
-                FrameCodec::write_frame(&mut stream, &resp_bytes).await?;
+                if tx.send(Outgoing::Frame(reply)).await.is_err() {
+                    break;
+                }
                 continue;
             }
         };
 
-        // Track request
-        let method = match &batch_req {
-            BatchRequest::Single(req) => req.method.clone(),
-            BatchRequest::Batch(reqs) => format!("batch({})", reqs.len()),
-        };
-        
-        let tracer = RequestTracer::new(&method, metrics.clone());
+        let permit = in_flight.clone().acquire_owned().await?;
+        let (server, layers, tx) = (server.clone(), layers.clone(), tx.clone());
+        tokio::spawn(async move {
+            // Auth, metrics and any extra layers run per entry inside the server
+            let batch_resp = server
+                .handle_batch_with_middleware(batch_req, ctx, &layers)
+                .await;
 
-        // Handle request
-        let batch_resp = if let Some(ref auth_arc) = auth {
-            // pass an Arc<RpcServer> and a reference to the middleware implementation
-           handle_authenticated_batch(server.clone(), batch_req, &auth_arc).await
-        } else {
-            server_handle_batch(server.clone(), batch_req).await
-        };
+            // Send response (notifications get no frame at all)
+            if let Some(batch_resp) = batch_resp
+                && wants_reply
+                && let Ok(resp_bytes) = serde_json::to_vec(&batch_resp)
+            {
+                let reply = Frame::new(MessageType::Response, stream_id, resp_bytes);
+                let _ = tx.send(Outgoing::Frame(reply)).await;
+            }
+            drop(permit);
+        });
+    }
 
-        // Check if response contains errors
-        let has_error = match &batch_resp {
-            BatchResponse::Single(resp) => resp.error.is_some(),
-            BatchResponse::Batch(resps) => resps.iter().any(|r| r.error.is_some()),
+    // Requests still running get to answer a peer that only closed its
+    // write side; the writer stops once they and the forwarder are done
+    if let Some((_, forwarder)) = &subscriber {
+        forwarder.abort();
+    }
+    // When the server closes the connection, the goaway follows the last
+    // response; requests that outlast the write timeout are dropped
+    if let Some(reason) = closing {
+        let running = in_flight.clone().acquire_many_owned(max_in_flight as u32);
+        let drained = match write_timeout {
+            Some(limit) => tokio::time::timeout(limit, running).await.is_ok(),
+            None => running.await.is_ok(),
         };
-
-        if has_error {
-            tracer.error("Request returned error").await;
+        if drained {
+            let _ = tx.send(goaway(reason)).await;
         } else {
-            tracer.success().await;
+            writer.abort();
         }
-
-        // Send response
-        let resp_bytes = serde_json::to_vec(&batch_resp)?;
-        FrameCodec::write_frame(&mut stream, &resp_bytes).await?;
     }
+    drop(tx);
+    if let Ok(Err(e)) = writer.await {
+        debug!("Dropped responses for {:?}: {}", base_ctx.peer_addr, e);
+    }
+    match failed {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
 
-    Ok(())
+/// Limits applied to each framed connection, shared by the TCP and Unix
+/// transports
+pub(crate) struct ConnectionLimits {
+    pub(crate) max_in_flight: usize,
+    pub(crate) max_frame_length: usize,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    /// Where timed out connections are counted
+    pub(crate) metrics: Arc<Metrics>,
 }
 
+/// A frame queued for a framed connection's writer task
+enum Outgoing {
+    Frame(Frame),
+    /// Write every frame queued after this one in v2 format
+    Upgrade,
+}
 
-async fn handle_authenticated_batch(
-    server: Arc<RpcServer>,
-    batch: BatchRequest,
-    _auth: &AuthMiddleware,
-) -> BatchResponse {
-    match batch {
-        BatchRequest::Single(req) => {
-            // Use the existing handle_request method on RpcServer
-            BatchResponse::Single(server.handle_request(req).await)
-        }
-        BatchRequest::Batch(requests) => {
-            // Spawn futures that call handle_request on clones of the Arc<RpcServer>
-            let futures: Vec<_> = requests
-                .into_iter()
-                .map(|req| {
-                    let srv = server.clone();
-                    async move { srv.handle_request(req).await }
-                })
-                .collect();
-
-            let responses = futures::future::join_all(futures).await;
-            BatchResponse::Batch(responses)
-        }
+/// Why the server is closing a connection, as the last frame it sends:
+/// read by v1 peers as an error response with a null id
+fn goaway(reason: &'static str) -> Outgoing {
+    let err = RpcError::new(CONNECTION_CLOSED, "Connection closed by server")
+        .with_data(serde_json::json!({"reason": reason}));
+    let resp = RpcResponse::with_error_obj(serde_json::Value::Null, err.into());
+    let payload = serde_json::to_vec(&resp).unwrap_or_default();
+    Outgoing::Frame(Frame::new(MessageType::GoAway, 0, payload))
+}
+
+async fn send_outgoing<W>(
+    sink: &mut FramedWrite<W, ConnectionCodec>,
+    out: Outgoing,
+    flush: bool,
+) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+{
+    match out {
+        Outgoing::Frame(frame) => match sink.feed(frame).await {
+            Err(FrameError::TooLarge { len, max }) => {
+                warn!("Dropping {} byte frame over the {} byte limit", len, max);
+            }
+            result => result?,
+        },
+        Outgoing::Upgrade => sink.encoder_mut().upgrade(),
     }
+    if flush {
+        sink.flush().await?;
+    }
+    Ok(())
 }
 
-async fn server_handle_batch(server: Arc<RpcServer>, batch: BatchRequest) -> BatchResponse {
-    match batch {
-        BatchRequest::Single(req) => {
-            // Delegate single request to RpcServer::handle_request
-            BatchResponse::Single(server.handle_request(req).await)
+/// What waiting for a connection's next frame ended with
+enum NextFrame {
+    Frame(Result<Frame, FrameError>),
+    Closed,
+    /// The limit that tripped: `read_timeout` or `idle_timeout`
+    TimedOut(&'static str),
+}
+
+/// The next frame, within the read timeout once one has started arriving
+/// and the idle timeout until then
+async fn next_frame<R>(
+    frames: &mut FramedRead<R, ConnectionCodec>,
+    limits: &ConnectionLimits,
+) -> NextFrame
+where
+    R: AsyncRead + Unpin,
+{
+    let mut idle = limits.idle_timeout.map(|t| Box::pin(tokio::time::sleep(t)));
+    let mut read_deadline: Option<Pin<Box<tokio::time::Sleep>>> = None;
+    std::future::poll_fn(|cx| {
+        match frames.poll_next_unpin(cx) {
+            Poll::Ready(Some(frame)) => return Poll::Ready(NextFrame::Frame(frame)),
+            Poll::Ready(None) => return Poll::Ready(NextFrame::Closed),
+            Poll::Pending => {}
         }
-        BatchRequest::Batch(requests) => {
-            // Spawn futures that call handle_request on clones of the Arc<RpcServer>
-            let futures: Vec<_> = requests
-                .into_iter()
-                .map(|req| {
-                    let srv = server.clone();
-                    async move { srv.handle_request(req).await }
-                })
-                .collect();
-
-            let responses = futures::future::join_all(futures).await;
-            BatchResponse::Batch(responses)
+        // Decoding notes when a frame starts; the timer starts from there
+        match frames.decoder().partial_since() {
+            Some(started) => {
+                if let Some(timeout) = limits.read_timeout {
+                    let deadline = read_deadline.get_or_insert_with(|| {
+                        Box::pin(tokio::time::sleep_until(started + timeout))
+                    });
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(NextFrame::TimedOut("read_timeout"));
+                    }
+                }
+            }
+            None => {
+                if let Some(idle) = &mut idle
+                    && idle.as_mut().poll(cx).is_ready()
+                {
+                    return Poll::Ready(NextFrame::TimedOut("idle_timeout"));
+                }
+            }
         }
-    }
+        Poll::Pending
+    })
+    .await
 }
 
 /// Legacy newline-delimited server (for backwards compatibility)
@@ -218,17 +778,22 @@ pub async fn run(addr: &str) -> Result<()> {
     crate::rpc::register_default_handlers(&server).await;
 
     loop {
-        let (socket, _) = listener.accept().await?;
+        let (socket, peer) = listener.accept().await?;
         let server = server.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_connection_legacy(server, socket).await {
+            if let Err(e) = handle_connection_legacy(server, socket, peer).await {
                 error!("Connection error: {:?}", e);
             }
         });
     }
 }
 
-async fn handle_connection_legacy(server: Arc<RpcServer>, stream: TcpStream) -> Result<()> {
+async fn handle_connection_legacy(
+    server: Arc<RpcServer>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> Result<()> {
+    let ctx = RequestContext::new(TransportKind::TcpLine).with_peer_addr(peer);
     let (reader, mut writer) = stream.into_split();
     let mut br = BufReader::new(reader);
     let mut line = String::new();
@@ -245,24 +810,15 @@ async fn handle_connection_legacy(server: Arc<RpcServer>, stream: TcpStream) ->
             continue;
         }
 
-        match parse_rpc_request(raw) {
-            Ok(req) => {
-                let resp = server.handle_request(req).await;
-                let resp_text = serde_json::to_string(&resp)?;
-                writer.write_all(resp_text.as_bytes()).await?;
-                writer.write_all(b"\n").await?;
-            }
-            Err(e) => {
-                let err_resp = crate::rpc::RpcResponse::with_error(
-                    serde_json::Value::Null,
-                    -32700,
-                    format!("Parse error: {}", e),
-                );
-                let resp_text = serde_json::to_string(&err_resp)?;
-                writer.write_all(resp_text.as_bytes()).await?;
-                writer.write_all(b"\n").await?;
-            }
-        }
+        let resp_text = match BatchRequest::parse(raw) {
+            Ok(batch) => match server.handle_batch_with_context(batch, ctx.clone()).await {
+                Some(resp) => serde_json::to_string(&resp)?,
+                None => continue,
+            },
+            Err(err_resp) => serde_json::to_string(&err_resp)?,
+        };
+        writer.write_all(resp_text.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
     }
 
     Ok(())
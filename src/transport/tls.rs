@@ -0,0 +1,360 @@
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::clientcert::PeerCertificate;
+use anyhow::{Context, Result};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// How long a client gets to finish the TLS handshake after connecting
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ALPN protocols `HttpTransport` offers when the config names none
+pub const HTTP_ALPN: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+pub(crate) fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Parse the CA certificates in `pem` into a root store
+pub(crate) fn root_store(pem: &[u8]) -> Result<RootCertStore> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid CA certificate PEM")?;
+    if certs.is_empty() {
+        anyhow::bail!("no CA certificates in PEM");
+    }
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+/// A certificate chain and private key, both PEM files
+#[derive(Debug, Clone)]
+struct PemPair {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl PemPair {
+    fn load(&self) -> Result<Arc<CertifiedKey>> {
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("cannot read certificates from {}", self.cert.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("no certificates in {}", self.cert.display());
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .with_context(|| format!("cannot read private key from {}", self.key.display()))?;
+        let key = CertifiedKey::from_der(certs, key, &provider()).with_context(|| {
+            format!(
+                "{} does not match {}",
+                self.key.display(),
+                self.cert.display()
+            )
+        })?;
+        Ok(Arc::new(key))
+    }
+}
+
+/// Certificates loaded from disk: the default one and those chosen by SNI
+#[derive(Debug)]
+struct CertSet {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+/// Picks the certificate for each handshake from whatever set is current,
+/// so a reload applies to new connections without rebuilding the config
+#[derive(Debug)]
+struct SniResolver {
+    certs: Arc<RwLock<Arc<CertSet>>>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certs = self.certs.read().unwrap().clone();
+        let by_name = client_hello
+            .server_name()
+            .and_then(|name| certs.by_name.get(&name.to_ascii_lowercase()));
+        Some(by_name.unwrap_or(&certs.default).clone())
+    }
+}
+
+/// Server-side TLS from PEM files, with SNI, ALPN and reload.
+///
+/// Clones share their certificates and SNI names, so a name added or a
+/// reload through any clone updates every transport built from it. Handshakes that start after `reload` use the
+/// new files; established connections keep theirs.
+///
+/// ```no_run
+/// # use dice_rpc::transport::{HttpTransport, TcpServerConfig, TlsConfig};
+/// # use std::sync::Arc;
+/// # fn main() -> anyhow::Result<()> {
+/// # let server = Arc::new(dice_rpc::RpcServer::new());
+/// let tls = TlsConfig::from_pem_files("server.crt", "server.key")?
+///     .with_sni_cert("admin.example.com", "admin.crt", "admin.key")?;
+/// let tcp = TcpServerConfig::new("0.0.0.0:4443", server.clone()).with_tls(tls.clone());
+/// let http = HttpTransport::new(server).with_tls(tls.clone());
+///
+/// // After renewing the certificates on disk
+/// tls.reload()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    default: PemPair,
+    sni: Arc<RwLock<Vec<(String, PemPair)>>>,
+    alpn: Vec<Vec<u8>>,
+    certs: Arc<RwLock<Arc<CertSet>>>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+}
+
+impl TlsConfig {
+    /// Serve the chain in `cert` with the key in `key`
+    pub fn from_pem_files(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Result<Self> {
+        let default = PemPair {
+            cert: cert.into(),
+            key: key.into(),
+        };
+        let certs = CertSet {
+            default: default.load()?,
+            by_name: HashMap::new(),
+        };
+        Ok(Self {
+            default,
+            sni: Arc::default(),
+            alpn: Vec::new(),
+            certs: Arc::new(RwLock::new(Arc::new(certs))),
+            client_verifier: None,
+        })
+    }
+
+    /// Serve a different certificate to clients asking for `server_name`
+    pub fn with_sni_cert(
+        self,
+        server_name: impl Into<String>,
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        let pair = PemPair {
+            cert: cert.into(),
+            key: key.into(),
+        };
+        // Clones see the list, so only a pair that loads joins it
+        pair.load()?;
+        let entry = (server_name.into().to_ascii_lowercase(), pair);
+        self.sni.write().unwrap().push(entry);
+        self.reload()?;
+        Ok(self)
+    }
+
+    /// Offer these ALPN protocols, most preferred first
+    pub fn with_alpn<P: Into<Vec<u8>>>(mut self, protocols: impl IntoIterator<Item = P>) -> Self {
+        self.alpn = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Require every client to present a certificate `verifier` accepts.
+    /// Transports do this themselves for `AuthStrategy::ClientCert`.
+    pub fn with_client_verifier(mut self, verifier: Arc<dyn ClientCertVerifier>) -> Self {
+        self.client_verifier = Some(verifier);
+        self
+    }
+
+    pub fn alpn(&self) -> &[Vec<u8>] {
+        &self.alpn
+    }
+
+    /// Re-read every certificate and key; on error the current ones stay
+    pub fn reload(&self) -> Result<()> {
+        let sni = self.sni.read().unwrap().clone();
+        let mut by_name = HashMap::new();
+        for (name, pair) in sni {
+            by_name.insert(name, pair.load()?);
+        }
+        let certs = CertSet {
+            default: self.default.load()?,
+            by_name,
+        };
+        *self.certs.write().unwrap() = Arc::new(certs);
+        Ok(())
+    }
+
+    /// A rustls server config that follows reloads
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>> {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_verifier {
+            Some(verifier) => builder.with_client_cert_verifier(verifier.clone()),
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(SniResolver {
+            certs: self.certs.clone(),
+        }));
+        config.alpn_protocols = self.alpn.clone();
+        Ok(Arc::new(config))
+    }
+
+    pub fn acceptor(&self) -> Result<TlsAcceptor> {
+        Ok(TlsAcceptor::from(self.server_config()?))
+    }
+
+    /// The acceptor for a transport authenticating with `auth`, which asks
+    /// for client certificates when it uses `AuthStrategy::ClientCert`
+    pub(crate) fn acceptor_for(&self, auth: Option<&AuthMiddleware>) -> Result<TlsAcceptor> {
+        match auth.and_then(|auth| auth.client_cert_verifier()) {
+            Some(verifier) => self.clone().with_client_verifier(verifier).acceptor(),
+            None => self.acceptor(),
+        }
+    }
+}
+
+/// Client-side TLS that trusts the CAs it is given (and nothing else)
+///
+/// ```no_run
+/// # use dice_rpc::transport::{FrameCodec, TlsClientConfig};
+/// # async fn example() -> anyhow::Result<()> {
+/// let tls = TlsClientConfig::new().with_ca_file("ca.crt")?;
+/// let mut stream = tls.connect("127.0.0.1:4443", "rpc.example.com").await?;
+/// FrameCodec::write_frame(&mut stream, br#"{"jsonrpc":"2.0","method":"ping","id":1}"#).await?;
+///
+/// let http = tls.http_client()?; // reqwest client for HttpTransport
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    roots: RootCertStore,
+    ca_pem: Vec<Vec<u8>>,
+    alpn: Vec<Vec<u8>>,
+    /// PEM certificate chain and PKCS#8 key presented for mutual TLS
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Default for TlsClientConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsClientConfig {
+    /// A config with no trusted CAs yet
+    pub fn new() -> Self {
+        Self {
+            roots: RootCertStore::empty(),
+            ca_pem: Vec::new(),
+            alpn: Vec::new(),
+            identity: None,
+        }
+    }
+
+    /// Trust the CA certificates in a PEM file
+    pub fn with_ca_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        self.with_ca_pem(&pem)
+    }
+
+    /// Trust the CA certificates in PEM text
+    pub fn with_ca_pem(mut self, pem: &[u8]) -> Result<Self> {
+        self.roots.roots.extend(root_store(pem)?.roots);
+        self.ca_pem.push(pem.to_vec());
+        Ok(self)
+    }
+
+    /// Present this certificate chain and PKCS#8 key to servers that ask
+    /// for one (`AuthStrategy::ClientCert`)
+    pub fn with_client_cert(self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self> {
+        let (cert, key) = (cert.as_ref(), key.as_ref());
+        let cert =
+            std::fs::read(cert).with_context(|| format!("cannot read {}", cert.display()))?;
+        let key = std::fs::read(key).with_context(|| format!("cannot read {}", key.display()))?;
+        self.with_client_cert_pem(&cert, &key)
+    }
+
+    /// `with_client_cert` from PEM text
+    pub fn with_client_cert_pem(mut self, cert: &[u8], key: &[u8]) -> Result<Self> {
+        // Parse now so a bad pair fails here rather than at connect time
+        client_identity(cert, key)?;
+        self.identity = Some((cert.to_vec(), key.to_vec()));
+        Ok(self)
+    }
+
+    /// Ask for these ALPN protocols
+    pub fn with_alpn<P: Into<Vec<u8>>>(mut self, protocols: impl IntoIterator<Item = P>) -> Self {
+        self.alpn = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(self.roots.clone());
+        let mut config = match &self.identity {
+            Some((cert, key)) => {
+                let (certs, key) = client_identity(cert, key)?;
+                builder.with_client_auth_cert(certs, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self.alpn.clone();
+        Ok(Arc::new(config))
+    }
+
+    /// Connect to `addr` and verify its certificate against `server_name`
+    pub async fn connect(&self, addr: &str, server_name: &str) -> Result<TlsStream<TcpStream>> {
+        let name = ServerName::try_from(server_name.to_string())
+            .with_context(|| format!("invalid server name {:?}", server_name))?;
+        let tcp = TcpStream::connect(addr).await?;
+        let stream = TlsConnector::from(self.client_config()?)
+            .connect(name, tcp)
+            .await?;
+        Ok(stream)
+    }
+
+    /// An HTTP client that trusts the same CAs, for `https://` endpoints
+    pub fn http_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+        for pem in &self.ca_pem {
+            for cert in reqwest::Certificate::from_pem_bundle(pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some((cert, key)) = &self.identity {
+            builder = builder.identity(reqwest::Identity::from_pkcs8_pem(cert, key)?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The verified certificate a client presented, if the server asked for one
+pub(crate) fn peer_certificate(conn: &rustls::ServerConnection) -> Option<PeerCertificate> {
+    let cert = conn.peer_certificates()?.first()?;
+    Some(PeerCertificate(cert.clone().into_owned()))
+}
+
+type ClientIdentity = (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>);
+
+fn client_identity(cert: &[u8], key: &[u8]) -> Result<ClientIdentity> {
+    let certs = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid client certificate PEM")?;
+    if certs.is_empty() {
+        anyhow::bail!("no client certificate in PEM");
+    }
+    let key = PrivateKeyDer::from_pem_slice(key).context("invalid client key PEM")?;
+    Ok((certs, key))
+}
@@ -0,0 +1,230 @@
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::peercred::PeerCredentials;
+use crate::middleware::pipeline::{MetricsMiddleware, RpcMiddleware};
+use crate::middleware::ratelimit::RateLimiter;
+use crate::rpc::{RequestContext, RpcServer, TransportKind};
+use crate::server::metrics::Metrics;
+use crate::transport::framing::{FrameCodec, MAX_FRAME_SIZE};
+use crate::transport::shutdown::ShutdownCoordinator;
+use crate::transport::tcp::{ConnectionLimits, DEFAULT_MAX_IN_FLIGHT, handle_framed_connection};
+use anyhow::{Result, bail};
+use serde_json::Value;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{error, info, warn};
+
+/// Socket file mode unless configured otherwise: owner and group
+pub const DEFAULT_SOCKET_MODE: u32 = 0o660;
+
+/// A framed JSON-RPC server on a Unix socket. Unlike TCP, no read, idle or
+/// write timeouts apply: a local peer that stalls holds its connection slot
+/// until it closes the socket or the server shuts down.
+pub struct UnixServerConfig {
+    pub path: PathBuf,
+    pub server: Arc<RpcServer>,
+    pub auth: Option<Arc<AuthMiddleware>>,
+    pub metrics: Arc<Metrics>,
+    /// Limits checked after auth; may be shared with other transports
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    /// Extra layers applied after metrics, auth and rate limiting
+    pub middleware: Vec<Arc<dyn RpcMiddleware>>,
+    /// Permissions set on the socket file after binding
+    pub mode: u32,
+    /// Requests dispatched concurrently per connection
+    pub max_in_flight: usize,
+    /// Largest frame payload read or written, in bytes
+    pub max_frame_length: usize,
+}
+
+impl UnixServerConfig {
+    pub fn new(path: impl Into<PathBuf>, server: Arc<RpcServer>) -> Self {
+        Self {
+            path: path.into(),
+            server,
+            auth: None,
+            metrics: Arc::new(Metrics::new()),
+            rate_limit: None,
+            middleware: Vec::new(),
+            mode: DEFAULT_SOCKET_MODE,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_frame_length: MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Authenticate requests; `AuthStrategy::PeerCred` uses the uid of
+    /// the connecting process
+    pub fn with_auth(mut self, auth: Arc<AuthMiddleware>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_rate_limit(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    pub fn with_middleware(mut self, layer: impl RpcMiddleware) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
+    /// Set the socket file's permission bits, e.g. `0o600` for the owner only
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Let each connection run up to `limit` requests at once
+    pub fn with_max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = limit;
+        self
+    }
+
+    pub fn with_max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+
+    /// No timeouts: local peers are trusted not to stall
+    fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits {
+            max_in_flight: self.max_in_flight,
+            max_frame_length: self.max_frame_length,
+            read_timeout: None,
+            idle_timeout: None,
+            write_timeout: None,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// The per-request stack: metrics, auth, rate limits, then extras
+    fn connection_layers(&self) -> Vec<Arc<dyn RpcMiddleware>> {
+        let mut layers: Vec<Arc<dyn RpcMiddleware>> =
+            vec![Arc::new(MetricsMiddleware::new(self.metrics.clone()))];
+        if let Some(auth) = &self.auth {
+            layers.push(auth.clone());
+        }
+        if let Some(limiter) = &self.rate_limit {
+            layers.push(limiter.clone());
+        }
+        layers.extend(self.middleware.iter().cloned());
+        layers
+    }
+}
+
+/// Removes the socket file when the server stops
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Bind `path`, replacing a socket file left behind by a server that is
+/// gone. A live socket, or a file that is not a socket, is an error.
+fn bind(path: &Path, mode: u32) -> Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                bail!("{} is in use by a running server", path.display());
+            }
+            warn!("Removing stale socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// The context every request on a connection starts from, carrying the
+/// peer's credentials when the kernel reports them
+fn unix_context(stream: &UnixStream) -> RequestContext {
+    let mut ctx = RequestContext::new(TransportKind::Unix);
+    match stream.peer_cred() {
+        Ok(cred) => ctx.extensions.insert(PeerCredentials {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        }),
+        Err(e) => warn!("Cannot read peer credentials: {}", e),
+    }
+    ctx
+}
+
+/// Run the length-prefixed framing protocol on a Unix domain socket
+pub async fn run_unix(config: UnixServerConfig) -> Result<()> {
+    let listener = bind(&config.path, config.mode)?;
+    let _socket_file = SocketFile(config.path.clone());
+    info!(
+        "DiceRPC Unix server (framed) listening on {} (mode {:o})",
+        config.path.display(),
+        config.mode
+    );
+
+    let shutdown = Arc::new(ShutdownCoordinator::new());
+    let shutdown_clone = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown_clone.wait_for_signal().await;
+    });
+
+    let config = Arc::new(config);
+    let mut shutdown_rx = shutdown.subscribe();
+
+    loop {
+        tokio::select! {
+            accept_result = listener.accept() => {
+                match accept_result {
+                    Ok((stream, _)) => {
+                        let server = config.server.clone();
+                        let layers = config.connection_layers();
+                        let signed_auth = config.auth.clone().filter(|a| a.expects_signatures());
+                        let ctx = unix_context(&stream);
+                        let limits = config.connection_limits();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_framed_connection(server, stream, ctx, layers, signed_auth, limits).await {
+                                error!("Connection error: {:?}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to accept connection: {:?}", e);
+                    }
+                }
+            }
+            _ = shutdown_rx.recv() => {
+                info!("Shutting down Unix server");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send one request (or batch) to the framed server at `path` and return
+/// its response; `None` for notifications
+pub async fn call(path: impl AsRef<Path>, request: &Value) -> Result<Option<Value>> {
+    let mut stream = UnixStream::connect(path.as_ref()).await?;
+    FrameCodec::write_frame(&mut stream, &serde_json::to_vec(request)?).await?;
+    let expects_reply = match request {
+        Value::Array(entries) => entries.iter().any(|e| e.get("id").is_some()),
+        _ => request.get("id").is_some(),
+    };
+    if !expects_reply {
+        return Ok(None);
+    }
+    let frame = FrameCodec::read_frame(&mut stream).await?;
+    Ok(Some(serde_json::from_slice(&frame)?))
+}
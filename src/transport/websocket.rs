@@ -0,0 +1,174 @@
+use crate::middleware::clientcert::PeerCertificate;
+use crate::rpc::{RequestContext, Subscriber, TransportKind, parse_error};
+use crate::transport::http_transport::{HttpState, request_context};
+use crate::util::batch::BatchRequest;
+use axum::{
+    Extension,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Semaphore, mpsc};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Default interval between keepalive pings on `/ws`
+pub const DEFAULT_WS_KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// Requests one WebSocket connection may have running at once; further
+/// messages wait unread until one finishes
+pub const MAX_WS_IN_FLIGHT: usize = 64;
+
+/// Upgrade `GET /ws`. Header credentials are checked once, on the upgrade
+/// request; params credentials are checked per message by the auth layer.
+pub(crate) async fn ws_handler(
+    State(state): State<Arc<HttpState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    cert: Option<Extension<PeerCertificate>>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let mut ctx = request_context(connect_info.map(|ConnectInfo(addr)| addr), &headers);
+    ctx.transport = TransportKind::WebSocket;
+    if let Some(Extension(cert)) = cert {
+        ctx.extensions.insert(cert);
+    }
+
+    // A signed upgrade covers an empty body
+    if let Err(response) = state.authenticate_headers(&mut ctx, &[]).await {
+        return response;
+    }
+
+    upgrade
+        .on_upgrade(move |socket| serve_socket(state, socket, ctx))
+        .into_response()
+}
+
+/// Answer the messages of one connection until either side closes it.
+///
+/// Each text or binary message is a request or batch and runs in its own
+/// task, so responses go out as they finish, matched to requests by id.
+/// Replies use the frame type of the message they answer; subscription
+/// notifications go out as text.
+async fn serve_socket(state: Arc<HttpState>, socket: WebSocket, mut ctx: RequestContext) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<Message>(MAX_WS_IN_FLIGHT);
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let closing = matches!(message, Message::Close(_));
+            if sink.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let (subscriber, mut notifications) = match state.server.subscriber() {
+        Some((subscriber, notifications)) => {
+            ctx.extensions.insert(subscriber.clone());
+            (Some(subscriber), Some(notifications))
+        }
+        None => (None, None),
+    };
+
+    let in_flight = Arc::new(Semaphore::new(MAX_WS_IN_FLIGHT));
+    let keepalive = state.ws_keepalive;
+    let mut ticker = tokio::time::interval_at(Instant::now() + keepalive, keepalive);
+    let mut last_seen = Instant::now();
+
+    loop {
+        let message = tokio::select! {
+            message = stream.next() => message,
+            Some(notification) = next_notification(&mut notifications) => {
+                if tx.send(Message::Text(notification.to_string())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            _ = overflowed(&subscriber) => {
+                debug!("WebSocket peer {:?} overflowed its subscriptions; closing", ctx.peer_addr);
+                let _ = tx.send(Message::Close(None)).await;
+                break;
+            }
+            _ = ticker.tick() => {
+                // Pongs (and any other traffic) count as signs of life
+                if last_seen.elapsed() > keepalive * 2 {
+                    debug!("WebSocket peer {:?} missed its pongs; closing", ctx.peer_addr);
+                    let _ = tx.send(Message::Close(None)).await;
+                    break;
+                }
+                if tx.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(Ok(message)) = message else {
+            break;
+        };
+        last_seen = Instant::now();
+
+        let (payload, binary) = match message {
+            Message::Text(text) => (text.into_bytes(), false),
+            Message::Binary(bytes) => (bytes, true),
+            // Pings are answered by the socket itself
+            Message::Ping(_) | Message::Pong(_) => continue,
+            Message::Close(_) => break,
+        };
+
+        let Ok(permit) = in_flight.clone().acquire_owned().await else {
+            break;
+        };
+        let (state, ctx, tx) = (state.clone(), ctx.clone(), tx.clone());
+        tokio::spawn(async move {
+            let reply = match serde_json::from_slice::<Value>(&payload)
+                .map_err(parse_error)
+                .and_then(BatchRequest::from_value)
+            {
+                Ok(batch) => state
+                    .server
+                    .handle_batch_with_middleware(batch, ctx, &state.layers)
+                    .await
+                    .and_then(|resp| serde_json::to_vec(&resp).ok()),
+                Err(error_response) => serde_json::to_vec(&error_response).ok(),
+            };
+            // Notifications get no reply
+            if let Some(reply) = reply {
+                let message = if binary {
+                    Message::Binary(reply)
+                } else {
+                    Message::Text(String::from_utf8(reply).unwrap_or_default())
+                };
+                let _ = tx.send(message).await;
+            }
+            drop(permit);
+        });
+    }
+
+    // Let replies still being computed finish before the writer stops;
+    // subscriptions end once the in-flight requests drop their contexts
+    drop(subscriber);
+    drop(ctx);
+    drop(tx);
+    let _ = writer.await;
+}
+
+async fn next_notification(notifications: &mut Option<mpsc::Receiver<Value>>) -> Option<Value> {
+    match notifications {
+        Some(notifications) => notifications.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn overflowed(subscriber: &Option<Subscriber>) {
+    match subscriber {
+        Some(subscriber) => subscriber.overflowed().await,
+        None => std::future::pending().await,
+    }
+}
@@ -1,13 +1,21 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::Value;
-use crate::rpc::{RpcRequest, RpcResponse, RpcServer};
+use crate::middleware::pipeline::RpcMiddleware;
+use crate::rpc::{
+    INVALID_REQUEST, RequestContext, RpcRequest, RpcResponse, RpcServer, parse_error,
+};
+use std::sync::Arc;
 
 /// Represents either a single request or a batch of requests
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug)]
 pub enum BatchRequest {
     Single(RpcRequest),
     Batch(Vec<RpcRequest>),
+    /// A batch where some entries failed request validation.
+    ///
+    /// Invalid entries already hold their `INVALID_REQUEST` response and are
+    /// answered in place, so the response keeps the batch order.
+    Mixed(Vec<Result<RpcRequest, RpcResponse>>),
 }
 
 /// Represents either a single response or a batch of responses
@@ -21,14 +29,42 @@ pub enum BatchResponse {
 impl BatchRequest {
     #[allow(dead_code)]
     /// Parse raw JSON string into a BatchRequest
-    pub fn parse(raw: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(raw)
+    ///
+    /// On failure returns the response to send instead: `PARSE_ERROR` for
+    /// invalid JSON, `INVALID_REQUEST` for an empty array or a single value
+    /// that is not a valid request object.
+    pub fn parse(raw: &str) -> Result<Self, RpcResponse> {
+        let value: Value = serde_json::from_str(raw).map_err(parse_error)?;
+        Self::from_value(value)
+    }
+
+    /// Validate an already decoded JSON value as a single or batch request
+    pub fn from_value(value: Value) -> Result<Self, RpcResponse> {
+        match value {
+            Value::Array(items) => {
+                if items.is_empty() {
+                    return Err(RpcResponse::with_error(
+                        Value::Null,
+                        INVALID_REQUEST,
+                        "Invalid Request: empty batch",
+                    ));
+                }
+
+                let entries: Vec<_> = items.into_iter().map(RpcRequest::from_value).collect();
+                if entries.iter().all(Result::is_ok) {
+                    Ok(BatchRequest::Batch(entries.into_iter().flatten().collect()))
+                } else {
+                    Ok(BatchRequest::Mixed(entries))
+                }
+            }
+            other => RpcRequest::from_value(other).map(BatchRequest::Single),
+        }
     }
 
     #[allow(dead_code)]
     /// Check if this is a batch request
     pub fn is_batch(&self) -> bool {
-        matches!(self, BatchRequest::Batch(_))
+        !matches!(self, BatchRequest::Single(_))
     }
 
     /// Get the number of requests in this batch
@@ -36,6 +72,24 @@ impl BatchRequest {
         match self {
             BatchRequest::Single(_) => 1,
             BatchRequest::Batch(v) => v.len(),
+            BatchRequest::Mixed(v) => v.len(),
+        }
+    }
+
+    /// Label used for logging and metrics: the method name or `batch(n)`
+    pub fn method_label(&self) -> String {
+        match self {
+            BatchRequest::Single(req) => req.method.clone(),
+            _ => format!("batch({})", self.len()),
+        }
+    }
+
+    /// Flatten a batch into its entries, invalid ones as their error response
+    pub fn into_entries(self) -> Vec<Result<RpcRequest, RpcResponse>> {
+        match self {
+            BatchRequest::Single(req) => vec![Ok(req)],
+            BatchRequest::Batch(v) => v.into_iter().map(Ok).collect(),
+            BatchRequest::Mixed(v) => v,
         }
     }
 
@@ -45,32 +99,91 @@ impl BatchRequest {
     }
 }
 
+impl BatchResponse {
+    /// Collect the per-request outcomes of a batch, dropping notifications.
+    ///
+    /// Returns `None` when every entry was a notification, in which case
+    /// nothing must be sent back to the client.
+    pub fn from_batch(responses: Vec<Option<RpcResponse>>) -> Option<Self> {
+        let responses: Vec<RpcResponse> = responses.into_iter().flatten().collect();
+        if responses.is_empty() {
+            None
+        } else {
+            Some(BatchResponse::Batch(responses))
+        }
+    }
+
+    /// Check if any response in this batch carries an error
+    pub fn has_error(&self) -> bool {
+        match self {
+            BatchResponse::Single(resp) => resp.error.is_some(),
+            BatchResponse::Batch(resps) => resps.iter().any(|r| r.error.is_some()),
+        }
+    }
+}
+
 impl RpcServer {
     #[allow(dead_code)]
     /// Handle a batch request by processing all requests concurrently
-    pub async fn handle_batch(&self, batch: BatchRequest) -> BatchResponse {
+    ///
+    /// Returns `None` when there is nothing to send back, i.e. for a single
+    /// notification or a batch made up only of notifications.
+    pub async fn handle_batch(&self, batch: BatchRequest) -> Option<BatchResponse> {
+        self.handle_batch_with_context(batch, RequestContext::default())
+            .await
+    }
+
+    /// Handle a batch request, giving every entry its own copy of `ctx`
+    pub async fn handle_batch_with_context(
+        &self,
+        batch: BatchRequest,
+        ctx: RequestContext,
+    ) -> Option<BatchResponse> {
+        self.handle_batch_with_middleware(batch, ctx, &[]).await
+    }
+
+    /// Handle a batch request, passing every entry through `layers`
+    /// before the server's own middleware
+    pub async fn handle_batch_with_middleware(
+        &self,
+        batch: BatchRequest,
+        ctx: RequestContext,
+        layers: &[Arc<dyn RpcMiddleware>],
+    ) -> Option<BatchResponse> {
         match batch {
-            BatchRequest::Single(req) => {
-                BatchResponse::Single(self.handle_request(req).await)
-            }
-            BatchRequest::Batch(requests) => {
-                if requests.is_empty() {
+            BatchRequest::Single(req) => self
+                .handle_request_with_middleware(req, ctx, layers)
+                .await
+                .map(BatchResponse::Single),
+            batch => {
+                if batch.is_empty() {
                     // Empty batch is invalid
-                    return BatchResponse::Single(RpcResponse::with_error(
+                    return Some(BatchResponse::Single(RpcResponse::with_error(
                         Value::Null,
-                        -32600,
+                        INVALID_REQUEST,
                         "Invalid Request: empty batch",
-                    ));
+                    )));
                 }
 
                 // Process all requests concurrently
-                let futures: Vec<_> = requests
+                let futures: Vec<_> = batch
+                    .into_entries()
                     .into_iter()
-                    .map(|req| self.handle_request(req))
+                    .map(|entry| {
+                        let ctx = ctx.clone();
+                        async move {
+                            match entry {
+                                Ok(req) => {
+                                    self.handle_request_with_middleware(req, ctx, layers).await
+                                }
+                                Err(resp) => Some(resp),
+                            }
+                        }
+                    })
                     .collect();
 
                 let responses = futures::future::join_all(futures).await;
-                BatchResponse::Batch(responses)
+                BatchResponse::from_batch(responses)
             }
         }
     }
@@ -117,13 +230,13 @@ mod tests {
                 jsonrpc: "2.0".to_string(),
                 method: "ping".to_string(),
                 params: json!({}),
-                id: json!(1),
+                id: Some(json!(1)),
             },
             RpcRequest {
                 jsonrpc: "2.0".to_string(),
                 method: "ping".to_string(),
                 params: json!({}),
-                id: json!(2),
+                id: Some(json!(2)),
             },
         ];
 
@@ -131,7 +244,7 @@ mod tests {
         let response = server.handle_batch(batch).await;
 
         match response {
-            BatchResponse::Batch(responses) => {
+            Some(BatchResponse::Batch(responses)) => {
                 assert_eq!(responses.len(), 2);
                 assert_eq!(responses[0].result, Some(json!("pong")));
                 assert_eq!(responses[1].result, Some(json!("pong")));
@@ -139,4 +252,31 @@ mod tests {
             _ => panic!("Expected batch response"),
         }
     }
+
+    #[tokio::test]
+    async fn test_batch_omits_notifications() {
+        let server = RpcServer::new();
+        server
+            .register("ping", |_| async move { Ok(json!("pong")) })
+            .await;
+
+        let raw = r#"[
+            {"jsonrpc":"2.0","method":"ping","id":1},
+            {"jsonrpc":"2.0","method":"ping"},
+            {"jsonrpc":"2.0","method":"missing"}
+        ]"#;
+        let response = server.handle_batch(BatchRequest::parse(raw).unwrap()).await;
+
+        match response {
+            Some(BatchResponse::Batch(responses)) => {
+                assert_eq!(responses.len(), 1);
+                assert_eq!(responses[0].id, json!(1));
+            }
+            _ => panic!("Expected batch response"),
+        }
+
+        let raw = r#"[{"jsonrpc":"2.0","method":"ping"},{"jsonrpc":"2.0","method":"ping"}]"#;
+        let response = server.handle_batch(BatchRequest::parse(raw).unwrap()).await;
+        assert!(response.is_none());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::{RequestContext, RpcRequest, RpcServer, TransportKind};
+use dice_rpc::server::admin::register_audit_admin_handlers;
+use dice_rpc::server::handlers::register_stateful_handlers;
+use dice_rpc::state::StateStore;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+fn temp_log(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "dice_rpc_audit_{}_{}.jsonl",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// A ledger server with `api_key` auth and the audit layer installed
+async fn audited_server(audit: Arc<AuditLog>) -> (RpcServer, Arc<KeyStore>) {
+    let server = RpcServer::new();
+    register_stateful_handlers(&server, Arc::new(StateStore::new())).await;
+    let keys = Arc::new(KeyStore::new());
+    server
+        .add_middleware(
+            AuthMiddleware::new(AuthStrategy::ApiKeyInParams).with_key_store(keys.clone()),
+        )
+        .await;
+    server.add_middleware(audit.clone()).await;
+    register_audit_admin_handlers(&server, audit).await;
+    (server, keys)
+}
+
+#[tokio::test]
+async fn test_mutating_calls_are_recorded() {
+    let audit = Arc::new(AuditLog::new());
+    let (server, keys) = audited_server(audit.clone()).await;
+    let teller = keys.issue("teller", ["write"], None).await.unwrap();
+    let ctx =
+        RequestContext::new(TransportKind::Http).with_peer_addr("10.0.0.7:5000".parse().unwrap());
+
+    let call = |method: &str, params: Value| {
+        server.handle_request_with_context(request(method, params), ctx.clone())
+    };
+    call(
+        "set_balance",
+        json!({"api_key": teller.key, "address": "0xA", "balance": 100}),
+    )
+    .await;
+    call(
+        "get_balance",
+        json!({"api_key": teller.key, "address": "0xA"}),
+    )
+    .await;
+    let resp = call(
+        "transfer",
+        json!({"api_key": teller.key, "from": "0xA", "to": "0xB", "amount": 40}),
+    )
+    .await
+    .unwrap();
+    let txid = resp.result.unwrap()["txid"].as_str().unwrap().to_string();
+    call(
+        "transfer",
+        json!({"api_key": teller.key, "from": "0xA", "to": "0xB", "amount": 500}),
+    )
+    .await;
+
+    let records = audit.query(&AuditQuery::default()).await.unwrap();
+    let methods: Vec<&str> = records.iter().map(|r| r.method.as_str()).collect();
+    assert_eq!(methods, ["set_balance", "transfer", "transfer"]);
+
+    let first = &records[0];
+    assert_eq!(first.seq, 1);
+    assert_eq!(first.prev_hash, "0".repeat(64));
+    assert_eq!(first.principal.as_deref(), Some("teller"));
+    assert_eq!(first.key_id.as_deref(), Some(teller.info.id.as_str()));
+    assert_eq!(first.peer.as_deref(), Some("10.0.0.7:5000"));
+    assert_eq!(first.params["api_key"], "[REDACTED]");
+    assert_eq!(first.params["address"], "0xA");
+    assert_eq!(first.outcome, AuditOutcome::Ok);
+
+    assert_eq!(records[1].txid.as_deref(), Some(txid.as_str()));
+    assert_eq!(records[1].prev_hash, records[0].hash);
+    assert!(matches!(records[2].outcome, AuditOutcome::Error { .. }));
+}
+
+#[tokio::test]
+async fn test_file_chain_detects_tampering() {
+    let path = temp_log("chain");
+    let ctx = RequestContext::new(TransportKind::TcpFramed);
+    {
+        let audit = AuditLog::open(&path).unwrap();
+        for amount in [1, 2] {
+            audit
+                .append(
+                    "set_balance",
+                    &json!({"address": "0xA", "balance": amount}),
+                    &ctx,
+                    &Ok(json!({})),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    // Reopening continues the chain
+    let audit = AuditLog::open(&path).unwrap();
+    let third = audit
+        .append(
+            "set_balance",
+            &json!({"address": "0xA", "balance": 3}),
+            &ctx,
+            &Ok(json!({})),
+        )
+        .await
+        .unwrap();
+    assert_eq!(third.seq, 3);
+    assert_eq!(
+        AuditLog::verify_file(&path).unwrap().unwrap().hash,
+        third.hash
+    );
+
+    let original = std::fs::read_to_string(&path).unwrap();
+    let edited = original.replacen("\"balance\":2", "\"balance\":2000", 1);
+    std::fs::write(&path, edited).unwrap();
+    let err = AuditLog::verify_file(&path).unwrap_err().to_string();
+    assert!(err.contains("seq 2"), "{}", err);
+    assert!(AuditLog::open(&path).is_err());
+
+    // Dropping a record breaks the link of the one after it
+    let lines: Vec<&str> = original.lines().collect();
+    std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert!(AuditLog::verify_file(&path).is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_file_queries_run_alongside_appends() {
+    let path = temp_log("concurrent");
+    let audit = Arc::new(AuditLog::open(&path).unwrap().with_sync(false));
+
+    let writers: Vec<_> = (0..8)
+        .map(|n| {
+            let audit = audit.clone();
+            tokio::spawn(async move {
+                let ctx = RequestContext::new(TransportKind::TcpFramed);
+                for balance in 0..25 {
+                    audit
+                        .append(
+                            "set_balance",
+                            &json!({"address": format!("0x{}", n), "balance": balance}),
+                            &ctx,
+                            &Ok(json!({})),
+                        )
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    // Queries see whole records only, in order, while writers are busy
+    for _ in 0..20 {
+        let records = audit.query(&AuditQuery::default()).await.unwrap();
+        assert!(
+            records
+                .iter()
+                .enumerate()
+                .all(|(i, r)| r.seq == i as u64 + 1)
+        );
+    }
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    assert_eq!(AuditLog::verify_file(&path).unwrap().unwrap().seq, 200);
+    let query = AuditQuery {
+        limit: Some(1000),
+        ..AuditQuery::default()
+    };
+    assert_eq!(audit.query(&query).await.unwrap().len(), 200);
+
+    // A record cut off mid-write is not returned
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"{\"seq\":201,").unwrap();
+    assert_eq!(audit.query(&query).await.unwrap().len(), 200);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_admin_audit_query_filters() {
+    let audit = Arc::new(AuditLog::new());
+    let (server, keys) = audited_server(audit).await;
+    let admin = keys.issue("root", ["admin"], None).await.unwrap();
+    let alice = keys.issue("alice", ["write"], None).await.unwrap();
+    let bob = keys.issue("bob", ["write"], None).await.unwrap();
+
+    for key in [&alice.key, &bob.key, &alice.key] {
+        server
+            .handle_request(request(
+                "set_balance",
+                json!({"api_key": key, "address": "0xA", "balance": 1}),
+            ))
+            .await;
+    }
+
+    let resp = server
+        .handle_request(request("admin.audit.query", json!({"api_key": alice.key})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, FORBIDDEN);
+
+    let resp = server
+        .handle_request(request(
+            "admin.audit.query",
+            json!({"api_key": admin.key, "principal": "alice"}),
+        ))
+        .await
+        .unwrap();
+    let result = resp.result.unwrap();
+    assert_eq!(result["count"], 2);
+    assert_eq!(result["records"][1]["seq"], 3);
+
+    let resp = server
+        .handle_request(request(
+            "admin.audit.query",
+            json!({"api_key": admin.key, "since": 0, "until": 1, "principal": "bob"}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["count"], 0);
+
+    let resp = server
+        .handle_request(request(
+            "admin.audit.query",
+            json!({"api_key": admin.key, "after_seq": 1, "limit": 1}),
+        ))
+        .await
+        .unwrap();
+    let result = resp.result.unwrap();
+    assert_eq!(result["count"], 1);
+    assert_eq!(result["records"][0]["principal"], "bob");
+}
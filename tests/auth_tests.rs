@@ -9,7 +9,7 @@ async fn test_no_auth() {
         jsonrpc: "2.0".to_string(),
         method: "ping".to_string(),
         params: json!({}),
-        id: json!(1),
+        id: Some(json!(1)),
     };
 
     assert!(auth.validate_request(&req).await.is_ok());
@@ -26,7 +26,7 @@ async fn test_api_key_auth_success() {
         params: json!({
             "api_key": "test-key-123"
         }),
-        id: json!(1),
+        id: Some(json!(1)),
     };
 
     assert!(auth.validate_request(&req).await.is_ok());
@@ -43,7 +43,7 @@ async fn test_api_key_auth_failure() {
         params: json!({
             "api_key": "invalid-key"
         }),
-        id: json!(1),
+        id: Some(json!(1)),
     };
 
     let result = auth.validate_request(&req).await;
@@ -60,10 +60,66 @@ async fn test_missing_api_key() {
         jsonrpc: "2.0".to_string(),
         method: "ping".to_string(),
         params: json!({}),
-        id: json!(1),
+        id: Some(json!(1)),
     };
 
     let result = auth.validate_request(&req).await;
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().code, AUTH_REQUIRED);
 }
+
+fn headers(pairs: &[(&str, &str)]) -> dice_rpc::RequestContext {
+    let map = pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    dice_rpc::RequestContext::new(dice_rpc::TransportKind::Http).with_headers(map)
+}
+
+#[tokio::test]
+async fn test_header_key_bearer() {
+    let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInHeader);
+    auth.add_key_with_id("test-key-123", "svc").await;
+
+    let principal = auth
+        .authenticate_headers(&headers(&[("authorization", "Bearer test-key-123")]))
+        .await
+        .unwrap();
+    assert_eq!(principal.id, "svc");
+
+    let err = auth
+        .authenticate_headers(&headers(&[("authorization", "Basic test-key-123")]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, AUTH_REQUIRED);
+
+    let err = auth
+        .authenticate_headers(&headers(&[("authorization", "Bearer nope")]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, AUTH_ERROR);
+}
+
+#[tokio::test]
+async fn test_header_key_custom_header() {
+    let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInHeader).with_key_header("X-API-Key");
+    auth.add_key("test-key-123").await;
+
+    assert!(
+        auth.authenticate_headers(&headers(&[("x-api-key", "test-key-123")]))
+            .await
+            .is_ok()
+    );
+
+    // Header strategies never fall back to params
+    let req = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: "ping".to_string(),
+        params: json!({"api_key": "test-key-123"}),
+        id: Some(json!(1)),
+    };
+    assert_eq!(
+        auth.validate_request(&req).await.unwrap_err().code,
+        AUTH_REQUIRED
+    );
+}
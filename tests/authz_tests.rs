@@ -0,0 +1,129 @@
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::{RpcRequest, RpcServer};
+use serde_json::{Value, json};
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+fn policy() -> AccessPolicy {
+    AccessPolicy::new()
+        .require(["admin.*"], ["admin"])
+        .require(["set_*", "transfer"], ["write"])
+        .require(["get_*", "list_accounts"], ["read"])
+}
+
+#[test]
+fn test_glob_rules_and_missing_scopes() {
+    let policy = policy();
+    let reader = Principal::new("reader", "api_key").with_scopes(["read"]);
+    let writer = Principal::new("writer", "api_key").with_scopes(["read", "write"]);
+
+    assert!(policy.check("get_balance", Some(&reader)).is_ok());
+    assert!(policy.check("set_balance", Some(&writer)).is_ok());
+    assert!(policy.check("transfer", Some(&writer)).is_ok());
+
+    let err = policy.check("set_balance", Some(&reader)).unwrap_err();
+    assert_eq!(err.code, FORBIDDEN);
+    let data = err.data.unwrap();
+    assert_eq!(data["method"], "set_balance");
+    assert_eq!(data["missing"], json!(["write"]));
+
+    assert_eq!(
+        policy
+            .check("admin.keys.list", Some(&writer))
+            .unwrap_err()
+            .code,
+        FORBIDDEN
+    );
+    assert_eq!(
+        policy.check("get_balance", None).unwrap_err().code,
+        AUTH_REQUIRED
+    );
+
+    // Methods no rule mentions stay open unless the policy denies by default
+    assert!(policy.check("ping", None).is_ok());
+    assert_eq!(
+        policy
+            .deny_by_default()
+            .check("ping", Some(&writer))
+            .unwrap_err()
+            .code,
+        FORBIDDEN
+    );
+}
+
+#[test]
+fn test_first_matching_rule_wins() {
+    let policy = AccessPolicy::new()
+        .require(["get_transactions"], Vec::<String>::new())
+        .require(["get_*"], ["read"]);
+    let anonymous = Principal::new("anon", "api_key");
+
+    assert!(policy.check("get_transactions", Some(&anonymous)).is_ok());
+    assert!(policy.check("get_balance", Some(&anonymous)).is_err());
+}
+
+#[test]
+fn test_policy_from_json() {
+    let policy = AccessPolicy::from_json(
+        r#"{
+            "deny_by_default": true,
+            "rules": [
+                {"methods": ["ping"]},
+                {"methods": ["admin.*"], "scopes": ["admin"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let admin = Principal::new("root", "api_key").with_scopes(["admin"]);
+
+    assert!(policy.check("ping", Some(&admin)).is_ok());
+    assert!(policy.check("admin.audit.query", Some(&admin)).is_ok());
+    assert!(policy.check("transfer", Some(&admin)).is_err());
+
+    let err = AccessPolicy::from_json(r#"{"rules": [{"methods": "admin.*"}]}"#).unwrap_err();
+    assert!(err.to_string().contains("rules[0].methods"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_key_scopes_enforced_through_pipeline() {
+    let server = RpcServer::new();
+    dice_rpc::rpc::register_default_handlers(&server).await;
+
+    let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams);
+    auth.add_key_with_scopes("reader-key", "reader", ["read"])
+        .await;
+    auth.add_key_with_scopes("sender-key", "sender", ["read", "write"])
+        .await;
+    server.add_middleware(auth).await;
+    server
+        .add_middleware(AccessPolicy::new().require(["send_tx"], ["write"]))
+        .await;
+
+    let resp = server
+        .handle_request(request("send_tx", json!({"api_key": "reader-key"})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, FORBIDDEN);
+
+    let resp = server
+        .handle_request(request(
+            "send_tx",
+            json!({"api_key": "sender-key", "raw_tx": "0xdead"}),
+        ))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none(), "{:?}", resp.error);
+
+    let resp = server
+        .handle_request(request("ping", json!({"api_key": "reader-key"})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!("pong")));
+}
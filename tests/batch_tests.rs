@@ -39,13 +39,13 @@ async fn test_batch_processing() {
             jsonrpc: "2.0".to_string(),
             method: "ping".to_string(),
             params: json!({}),
-            id: json!(1),
+            id: Some(json!(1)),
         },
         RpcRequest {
             jsonrpc: "2.0".to_string(),
             method: "ping".to_string(),
             params: json!({}),
-            id: json!(2),
+            id: Some(json!(2)),
         },
     ];
 
@@ -53,7 +53,7 @@ async fn test_batch_processing() {
     let response = server.handle_batch(batch).await;
 
     match response {
-        BatchResponse::Batch(responses) => {
+        Some(BatchResponse::Batch(responses)) => {
             assert_eq!(responses.len(), 2);
             assert_eq!(responses[0].result, Some(json!("pong")));
             assert_eq!(responses[1].result, Some(json!("pong")));
@@ -0,0 +1,301 @@
+//! JSON-RPC 2.0 specification examples (https://www.jsonrpc.org/specification#examples)
+//! run against every transport.
+//! Run with: cargo test --test conformance_tests --features full
+
+use dice_rpc::*;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Sent after every case on stream transports: if its reply comes back first,
+/// the case itself produced no response.
+const SENTINEL: &str = r#"{"jsonrpc":"2.0","method":"subtract","params":[0,0],"id":"sentinel"}"#;
+
+/// (request payload, expected response or `None` when nothing must be sent)
+fn spec_cases() -> Vec<(&'static str, Option<Value>)> {
+    vec![
+        // rpc call with positional parameters
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":[42,23],"id":1}"#,
+            Some(json!({"jsonrpc":"2.0","result":19,"id":1})),
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":[23,42],"id":2}"#,
+            Some(json!({"jsonrpc":"2.0","result":-19,"id":2})),
+        ),
+        // rpc call with named parameters
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":{"subtrahend":23,"minuend":42},"id":3}"#,
+            Some(json!({"jsonrpc":"2.0","result":19,"id":3})),
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":{"minuend":42,"subtrahend":23},"id":4}"#,
+            Some(json!({"jsonrpc":"2.0","result":19,"id":4})),
+        ),
+        // a Notification
+        (
+            r#"{"jsonrpc":"2.0","method":"update","params":[1,2,3,4,5]}"#,
+            None,
+        ),
+        (r#"{"jsonrpc":"2.0","method":"foobar"}"#, None),
+        // rpc call of non-existent method
+        (
+            r#"{"jsonrpc":"2.0","method":"foobar","id":"1"}"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32601},"id":"1"})),
+        ),
+        // rpc call with invalid JSON
+        (
+            r#"{"jsonrpc":"2.0","method":"foobar,"params":"bar","baz]"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32700},"id":null})),
+        ),
+        // rpc call with invalid Request object
+        (
+            r#"{"jsonrpc":"2.0","method":1,"params":"bar"}"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32600},"id":null})),
+        ),
+        // rpc call Batch, invalid JSON
+        (
+            r#"[{"jsonrpc":"2.0","method":"sum","params":[1,2,4],"id":"1"},{"jsonrpc":"2.0","method"]"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32700},"id":null})),
+        ),
+        // rpc call with an empty Array
+        (
+            r#"[]"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32600},"id":null})),
+        ),
+        // rpc call with an invalid Batch (but not empty)
+        (
+            r#"[1]"#,
+            Some(json!([{"jsonrpc":"2.0","error":{"code":-32600},"id":null}])),
+        ),
+        // rpc call with invalid Batch
+        (
+            r#"[1,2,3]"#,
+            Some(json!([
+                {"jsonrpc":"2.0","error":{"code":-32600},"id":null},
+                {"jsonrpc":"2.0","error":{"code":-32600},"id":null},
+                {"jsonrpc":"2.0","error":{"code":-32600},"id":null}
+            ])),
+        ),
+        // rpc call Batch
+        (
+            r#"[{"jsonrpc":"2.0","method":"sum","params":[1,2,4],"id":"1"},{"jsonrpc":"2.0","method":"notify_hello","params":[7]},{"jsonrpc":"2.0","method":"subtract","params":[42,23],"id":"2"},{"foo":"boo"},{"jsonrpc":"2.0","method":"foo.get","params":{"name":"myself"},"id":"5"},{"jsonrpc":"2.0","method":"get_data","id":"9"}]"#,
+            Some(json!([
+                {"jsonrpc":"2.0","result":7,"id":"1"},
+                {"jsonrpc":"2.0","result":19,"id":"2"},
+                {"jsonrpc":"2.0","error":{"code":-32600},"id":null},
+                {"jsonrpc":"2.0","error":{"code":-32601},"id":"5"},
+                {"jsonrpc":"2.0","result":["hello",5],"id":"9"}
+            ])),
+        ),
+        // rpc call Batch (all notifications)
+        (
+            r#"[{"jsonrpc":"2.0","method":"notify_sum","params":[1,2,4]},{"jsonrpc":"2.0","method":"notify_hello","params":[7]}]"#,
+            None,
+        ),
+        // Beyond the spec examples: version and params shape are enforced
+        (
+            r#"{"jsonrpc":"1.0","method":"subtract","params":[1,1],"id":10}"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32600},"id":10})),
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":"bar","id":11}"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32600},"id":11})),
+        ),
+        (
+            r#"{"jsonrpc":"2.0","method":"subtract","params":[1,1],"id":{"bad":true}}"#,
+            Some(json!({"jsonrpc":"2.0","error":{"code":-32600},"id":null})),
+        ),
+    ]
+}
+
+async fn spec_server() -> Arc<RpcServer> {
+    let server = Arc::new(RpcServer::new());
+
+    server
+        .register("subtract", |params| async move {
+            let (a, b) = match &params {
+                Value::Array(v) => (v[0].as_i64(), v[1].as_i64()),
+                _ => (params["minuend"].as_i64(), params["subtrahend"].as_i64()),
+            };
+            match (a, b) {
+                (Some(a), Some(b)) => Ok(json!(a - b)),
+                _ => Err(RpcError::invalid_params("Invalid params")),
+            }
+        })
+        .await;
+    server
+        .register_typed(
+            "sum",
+            |p: Vec<i64>| async move { Ok(p.iter().sum::<i64>()) },
+        )
+        .await;
+    server
+        .register("get_data", |_| async move { Ok(json!(["hello", 5])) })
+        .await;
+    for method in ["update", "notify_hello", "notify_sum"] {
+        server
+            .register(method, |_| async move { Ok(Value::Null) })
+            .await;
+    }
+
+    server
+}
+
+/// Drop the free-form parts of error objects so only the code is compared
+fn normalize(mut value: Value) -> Value {
+    fn strip(resp: &mut Value) {
+        if let Some(err) = resp.get_mut("error").and_then(Value::as_object_mut) {
+            err.remove("message");
+            err.remove("data");
+        }
+    }
+    match &mut value {
+        Value::Array(items) => items.iter_mut().for_each(strip),
+        other => strip(other),
+    }
+    value
+}
+
+fn assert_case(transport: &str, raw: &str, expected: &Option<Value>, actual: Option<Value>) {
+    assert_eq!(
+        actual.map(normalize),
+        *expected,
+        "{} transport, request: {}",
+        transport,
+        raw
+    );
+}
+
+fn is_sentinel(resp: &Value) -> bool {
+    resp.get("id") == Some(&json!("sentinel"))
+}
+
+#[tokio::test]
+async fn test_conformance_in_process() {
+    let server = spec_server().await;
+
+    for (raw, expected) in spec_cases() {
+        let actual = match BatchRequest::parse(raw) {
+            Ok(batch) => server
+                .handle_batch(batch)
+                .await
+                .map(|resp| serde_json::to_value(resp).unwrap()),
+            Err(resp) => Some(serde_json::to_value(resp).unwrap()),
+        };
+        assert_case("in-process", raw, &expected, actual);
+    }
+}
+
+#[tokio::test]
+async fn test_conformance_success_has_no_error_member() {
+    let server = spec_server().await;
+    let batch = BatchRequest::parse(r#"{"jsonrpc":"2.0","method":"get_data","id":1}"#).unwrap();
+    let resp = serde_json::to_value(server.handle_batch(batch).await.unwrap()).unwrap();
+
+    assert!(resp.get("error").is_none());
+    assert_eq!(resp["result"], json!(["hello", 5]));
+}
+
+#[tokio::test]
+async fn test_conformance_line_delimited_tcp() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let addr = "127.0.0.1:15001";
+    let server = spec_server().await;
+    tokio::spawn(async move {
+        let _ = server::server::serve(addr, server).await;
+    });
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (read_half, mut write_half) = stream.into_split();
+    let mut reader = BufReader::new(read_half);
+
+    for (raw, expected) in spec_cases() {
+        write_half
+            .write_all(format!("{}\n{}\n", raw, SENTINEL).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let first: Value = serde_json::from_str(&line).unwrap();
+        let actual = if is_sentinel(&first) {
+            None
+        } else {
+            line.clear();
+            reader.read_line(&mut line).await.unwrap();
+            assert!(is_sentinel(&serde_json::from_str(&line).unwrap()));
+            Some(first)
+        };
+        assert_case("line-delimited", raw, &expected, actual);
+    }
+}
+
+#[cfg(feature = "tcp")]
+#[tokio::test]
+async fn test_conformance_framed_tcp() {
+    use dice_rpc::transport::FrameCodec;
+
+    let addr = "127.0.0.1:15002";
+    let server = spec_server().await;
+    tokio::spawn(async move {
+        let config = transport::tcp::TcpServerConfig::new(addr, server);
+        let _ = transport::tcp::run_with_framing(config).await;
+    });
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+    for (raw, expected) in spec_cases() {
+        FrameCodec::write_frame(&mut stream, raw.as_bytes())
+            .await
+            .unwrap();
+        FrameCodec::write_frame(&mut stream, SENTINEL.as_bytes())
+            .await
+            .unwrap();
+
+        let first: Value =
+            serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap()).unwrap();
+        let actual = if is_sentinel(&first) {
+            None
+        } else {
+            let next: Value =
+                serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap())
+                    .unwrap();
+            assert!(is_sentinel(&next));
+            Some(first)
+        };
+        assert_case("framed", raw, &expected, actual);
+    }
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn test_conformance_http() {
+    let addr = "127.0.0.1:15003";
+    let server = spec_server().await;
+    tokio::spawn(async move {
+        let _ = transport::HttpTransport::new(server).serve(addr).await;
+    });
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let client = reqwest::Client::new();
+    for (raw, expected) in spec_cases() {
+        let resp = client
+            .post(format!("http://{}/rpc", addr))
+            .header("content-type", "application/json")
+            .body(raw)
+            .send()
+            .await
+            .unwrap();
+
+        let actual = if resp.status() == reqwest::StatusCode::NO_CONTENT {
+            None
+        } else {
+            assert_eq!(resp.status(), reqwest::StatusCode::OK);
+            Some(resp.json::<Value>().await.unwrap())
+        };
+        assert_case("http", raw, &expected, actual);
+    }
+}
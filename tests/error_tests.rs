@@ -0,0 +1,124 @@
+use dice_rpc::rpc::{INTERNAL_ERROR, INVALID_PARAMS, RpcRequest, RpcServer};
+use dice_rpc::server::handlers::{TRANSFER_FAILED, TX_NOT_FOUND, register_stateful_handlers};
+use dice_rpc::{OptionExt, RpcError, StateStore};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+#[derive(Serialize)]
+struct Shortfall {
+    balance: u64,
+    requested: u64,
+}
+
+#[tokio::test]
+async fn test_error_data_reaches_client() {
+    let server = RpcServer::new();
+    server
+        .register("withdraw", |_| async move {
+            Err(
+                RpcError::application(-32010, "Insufficient funds").with_data(Shortfall {
+                    balance: 5,
+                    requested: 10,
+                }),
+            )
+        })
+        .await;
+
+    let resp = server
+        .handle_request(request("withdraw", json!({})))
+        .await
+        .unwrap();
+    let wire = serde_json::to_value(&resp).unwrap();
+
+    assert_eq!(
+        wire["error"],
+        json!({
+            "code": -32010,
+            "message": "Insufficient funds",
+            "data": {"balance": 5, "requested": 10}
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_anyhow_maps_to_internal_error_without_leaking() {
+    let server = RpcServer::new();
+    server
+        .register("boom", |_| async move {
+            let _: Value = Err(anyhow::anyhow!("db password is hunter2"))?;
+            Ok(Value::Null)
+        })
+        .await;
+
+    let resp = server
+        .handle_request(request("boom", json!({})))
+        .await
+        .unwrap();
+    let err = resp.error.unwrap();
+
+    assert_eq!(err.code, INTERNAL_ERROR);
+    assert_eq!(err.message, "Internal error");
+    assert!(err.data.is_none());
+}
+
+#[test]
+fn test_source_chain_is_kept_for_logging() {
+    let io = std::io::Error::other("disk full");
+    let err = RpcError::internal("Could not persist").with_source(io);
+
+    assert!(std::error::Error::source(&err).is_some());
+    assert_eq!(err.chain(), "Could not persist (code -32603): disk full");
+
+    // The source never appears in the wire representation
+    let obj = err.to_error_obj();
+    assert_eq!(obj.message, "Could not persist");
+    assert!(obj.data.is_none());
+}
+
+#[test]
+fn test_option_ext() {
+    let missing: Option<&str> = None;
+    assert_eq!(
+        missing.ok_or_invalid_params().unwrap_err().code,
+        INVALID_PARAMS
+    );
+
+    let err = missing.ok_or_rpc_error(-32050, "Nope").unwrap_err();
+    assert_eq!((err.code, err.message.as_str()), (-32050, "Nope"));
+}
+
+#[tokio::test]
+async fn test_stateful_handler_errors_carry_data() {
+    let server = RpcServer::new();
+    let state = Arc::new(StateStore::new());
+    register_stateful_handlers(&server, state).await;
+
+    let resp = server
+        .handle_request(request(
+            "transfer",
+            json!({"from": "0xNobody", "to": "0xBob", "amount": 10}),
+        ))
+        .await
+        .unwrap();
+    let err = resp.error.unwrap();
+    assert_eq!(err.code, TRANSFER_FAILED);
+    assert_eq!(err.data.unwrap()["amount"], 10);
+
+    let resp = server
+        .handle_request(request("get_transaction", json!({"txid": "missing"})))
+        .await
+        .unwrap();
+    let err = resp.error.unwrap();
+    assert_eq!(err.code, TX_NOT_FOUND);
+    assert_eq!(err.data.unwrap()["txid"], "missing");
+}
@@ -1,5 +1,15 @@
-use dice_rpc::transport::framing::FrameCodec;
+use dice_rpc::middleware::{AuthMiddleware, AuthStrategy};
+use dice_rpc::rpc::{self, RpcServer};
+use dice_rpc::transport::framing::{
+    Frame, FrameCodec, FrameFlags, MessageType, PROTOCOL_V1, V2_HEADER_LEN,
+};
+use dice_rpc::transport::framing::{FrameCodecV2, FrameError};
+use dice_rpc::transport::stdio::{StdioServerConfig, serve};
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use std::sync::Arc;
 use tokio::io::BufReader;
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
 
 #[tokio::test]
 async fn test_frame_codec() {
@@ -30,3 +40,218 @@ async fn test_multiple_frames() {
         assert_eq!(result, *expected);
     }
 }
+
+#[tokio::test]
+async fn test_frame_v2_round_trip() {
+    let frames = vec![
+        Frame::new(MessageType::Request, 1, b"{}".to_vec()),
+        Frame::new(MessageType::Notification, 0, b"pushed".to_vec())
+            .with_flags(FrameFlags::default().with_compressed(true).with_encoding(3)),
+        Frame::new(MessageType::GoAway, u32::MAX, Vec::new()),
+    ];
+    let mut buffer = Vec::new();
+    for frame in &frames {
+        FrameCodec::write_frame_v2(&mut buffer, frame)
+            .await
+            .unwrap();
+    }
+    assert_eq!(&buffer[..3], b"DR\x02");
+    assert_eq!(buffer[V2_HEADER_LEN - 4..V2_HEADER_LEN], [0, 0, 0, 2]);
+
+    let mut reader = BufReader::new(&buffer[..]);
+    for expected in &frames {
+        let frame = FrameCodec::read_frame_v2(&mut reader).await.unwrap();
+        assert_eq!(&frame, expected);
+    }
+    assert!(frames[1].flags.is_compressed());
+    assert_eq!(frames[1].flags.encoding(), 3);
+}
+
+#[tokio::test]
+async fn test_frame_v2_rejects_bad_headers() {
+    // A v1 frame is not a v2 frame
+    let mut v1 = Vec::new();
+    FrameCodec::write_frame(&mut v1, b"a v1 frame, longer than a header")
+        .await
+        .unwrap();
+    let err = FrameCodec::read_frame_v2(&mut &v1[..]).await.unwrap_err();
+    assert!(err.to_string().contains("magic"), "{}", err);
+
+    let mut frame = Vec::new();
+    FrameCodec::write_frame_v2(&mut frame, &Frame::new(MessageType::Ping, 0, Vec::new()))
+        .await
+        .unwrap();
+    let mut bad_type = frame.clone();
+    bad_type[4] = 42;
+    let err = FrameCodec::read_frame_v2(&mut &bad_type[..])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("message type"), "{}", err);
+
+    let mut bad_version = frame.clone();
+    bad_version[2] = 3;
+    assert!(
+        FrameCodec::read_frame_v2(&mut &bad_version[..])
+            .await
+            .is_err()
+    );
+
+    let mut too_large = frame;
+    too_large[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+    let err = FrameCodec::read_frame_v2(&mut &too_large[..])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_negotiate_with_v1_server() {
+    // The stdio server only speaks v1 frames and has no rpc.hello
+    let server = Arc::new(RpcServer::new());
+    rpc::register_default_handlers(&server).await;
+    let (mut client, server_end) = tokio::io::duplex(64 * 1024);
+    let (read, write) = tokio::io::split(server_end);
+    tokio::spawn(serve(StdioServerConfig::new(server), read, write));
+
+    assert_eq!(
+        FrameCodec::negotiate(&mut client).await.unwrap(),
+        PROTOCOL_V1
+    );
+
+    let req = br#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+    FrameCodec::write_frame(&mut client, req).await.unwrap();
+    let resp = FrameCodec::read_frame(&mut client).await.unwrap();
+    let resp: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+    assert_eq!(resp["result"], "pong");
+}
+
+#[tokio::test]
+async fn test_negotiate_falls_back_when_hello_is_refused() {
+    // A v1 server that authenticates every request refuses the hello too
+    let server = Arc::new(RpcServer::new());
+    rpc::register_default_handlers(&server).await;
+    let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams);
+    auth.add_key("stdio-key").await;
+    let config = StdioServerConfig::new(server).with_auth(Arc::new(auth));
+    let (mut client, server_end) = tokio::io::duplex(64 * 1024);
+    let (read, write) = tokio::io::split(server_end);
+    tokio::spawn(serve(config, read, write));
+
+    assert_eq!(
+        FrameCodec::negotiate(&mut client).await.unwrap(),
+        PROTOCOL_V1
+    );
+
+    let req = br#"{"jsonrpc":"2.0","method":"ping","params":{"api_key":"stdio-key"},"id":1}"#;
+    FrameCodec::write_frame(&mut client, req).await.unwrap();
+    let resp = FrameCodec::read_frame(&mut client).await.unwrap();
+    let resp: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+    assert_eq!(resp["result"], "pong");
+}
+
+#[test]
+fn test_decoder_waits_for_whole_frame() {
+    let mut codec = FrameCodec::new();
+    let mut encoded = BytesMut::new();
+    codec.encode(&b"first"[..], &mut encoded).unwrap();
+    codec
+        .encode(Bytes::from_static(b"second"), &mut encoded)
+        .unwrap();
+
+    // Fed a byte at a time, each frame appears once it is complete
+    let mut buf = BytesMut::new();
+    let mut frames = Vec::new();
+    for byte in encoded {
+        buf.extend_from_slice(&[byte]);
+        if let Some(frame) = codec.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+    }
+    assert_eq!(frames, vec![&b"first"[..], &b"second"[..]]);
+    assert!(buf.is_empty());
+    assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+
+    // A stream that stops inside a frame ends with Eof
+    let mut partial = BytesMut::from(&[0u8, 0, 0, 9, b'x'][..]);
+    assert!(codec.decode(&mut partial).unwrap().is_none());
+    assert!(matches!(
+        codec.decode_eof(&mut partial),
+        Err(FrameError::Eof)
+    ));
+}
+
+#[test]
+fn test_max_frame_length() {
+    let mut codec = FrameCodec::new().with_max_frame_length(8);
+    assert_eq!(codec.max_frame_length(), 8);
+
+    let mut buf = BytesMut::new();
+    codec.encode(&b"8 bytes!"[..], &mut buf).unwrap();
+    let err = codec.encode(&b"nine byte"[..], &mut buf).unwrap_err();
+    assert!(matches!(err, FrameError::TooLarge { len: 9, max: 8 }));
+
+    // Rejected from the length prefix alone, before the payload arrives
+    let mut oversized = BytesMut::from(&1000u32.to_be_bytes()[..]);
+    let err = codec.decode(&mut oversized).unwrap_err();
+    assert!(matches!(err, FrameError::TooLarge { len: 1000, max: 8 }));
+
+    let mut v2 = FrameCodecV2::new().with_max_frame_length(4);
+    let frame = Frame::new(MessageType::Request, 1, Bytes::from_static(b"too long"));
+    assert!(matches!(
+        v2.encode(&frame, &mut BytesMut::new()),
+        Err(FrameError::TooLarge { len: 8, max: 4 })
+    ));
+}
+
+#[tokio::test]
+async fn test_read_frame_typed_errors() {
+    let mut empty: &[u8] = &[];
+    assert!(matches!(
+        FrameCodec::read_frame(&mut empty).await,
+        Err(FrameError::Eof)
+    ));
+
+    let mut truncated: &[u8] = &[0, 0, 0, 5, b'a'];
+    assert!(matches!(
+        FrameCodec::read_frame(&mut truncated).await,
+        Err(FrameError::Eof)
+    ));
+
+    let mut huge: &[u8] = &u32::MAX.to_be_bytes();
+    assert!(matches!(
+        FrameCodec::read_frame(&mut huge).await,
+        Err(FrameError::TooLarge { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_framed_read_write() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (server_read, server_write) = tokio::io::split(server);
+    let (client_read, client_write) = tokio::io::split(client);
+
+    // Echo each frame back, in v2, on stream id = its length
+    tokio::spawn(async move {
+        let mut frames = FramedRead::new(server_read, FrameCodec::new());
+        let mut sink = FramedWrite::new(server_write, FrameCodecV2::new());
+        while let Some(frame) = frames.next().await {
+            let payload = frame.unwrap().freeze();
+            let reply = Frame::new(MessageType::Response, payload.len() as u32, payload);
+            sink.send(reply).await.unwrap();
+        }
+    });
+
+    let mut sink = FramedWrite::new(client_write, FrameCodec::new());
+    let mut replies = FramedRead::new(client_read, FrameCodecV2::new());
+    // Larger than the duplex buffer, so frames arrive in pieces
+    let big = Bytes::from(vec![7u8; 4000]);
+    for payload in [Bytes::from_static(b"hi"), big.clone()] {
+        sink.send(payload.clone()).await.unwrap();
+        let reply = replies.next().await.unwrap().unwrap();
+        assert_eq!(reply.stream_id as usize, payload.len());
+        assert_eq!(reply.payload, payload);
+    }
+
+    SinkExt::<Bytes>::close(&mut sink).await.unwrap();
+    assert!(replies.next().await.is_none());
+}
@@ -1,8 +1,22 @@
 #[cfg(feature = "http")] // means - Only compile the following code if the Cargo feature named http is enabled.
 mod http_tests {
     use dice_rpc::*;
-    use std::sync::Arc;
+    use reqwest::StatusCode;
     use serde_json::json;
+    use std::sync::Arc;
+
+    /// Helper to POST a JSON body to a running server and collect the reply
+    async fn post_json(addr: &str, body: serde_json::Value) -> (StatusCode, Vec<u8>) {
+        let resp = reqwest::Client::new()
+            .post(format!("http://{}/rpc", addr))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        let status = resp.status();
+        let bytes = resp.bytes().await.unwrap();
+        (status, bytes.to_vec())
+    }
 
     #[tokio::test]
     async fn test_http_transport_basic() {
@@ -11,7 +25,7 @@ mod http_tests {
 
         // Create HTTP transport
         let http = transport::HttpTransport::new(server);
-        let router = http.router();
+        let _router = http.router();
 
         // Test with axum test helpers
         // (You'd need axum-test crate for this)
@@ -33,4 +47,464 @@ mod http_tests {
         let _router = http.router();
         // Test authenticated requests
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_http_notifications() {
+        let addr = "127.0.0.1:13001";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let _ = transport::HttpTransport::new(server).serve(addr).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        // Single notification -> 204, no body
+        let (status, body) = post_json(
+            addr,
+            json!({"jsonrpc": "2.0", "method": "ping", "params": {}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(body.is_empty());
+
+        // Batch of notifications only -> 204
+        let (status, _) = post_json(
+            addr,
+            json!([
+                {"jsonrpc": "2.0", "method": "ping"},
+                {"jsonrpc": "2.0", "method": "nonexistent_method"}
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Mixed batch -> only the request with an id is answered
+        let (status, body) = post_json(
+            addr,
+            json!([
+                {"jsonrpc": "2.0", "method": "ping"},
+                {"jsonrpc": "2.0", "method": "ping", "id": null}
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let responses: Vec<RpcResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, serde_json::Value::Null);
+        assert_eq!(responses[0].result, Some(json!("pong")));
+    }
+
+    #[tokio::test]
+    async fn test_http_request_context() {
+        let addr = "127.0.0.1:13002";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!({
+                        "http": ctx.transport == TransportKind::Http,
+                        "peer": ctx.peer_addr.map(|a| a.ip().to_string()),
+                        "agent": ctx.header("X-Test-Agent"),
+                        "principal": ctx.principal.as_ref().map(|p| p.id.clone()),
+                    }))
+                })
+                .await;
+
+            let auth = Arc::new(middleware::AuthMiddleware::new(
+                middleware::AuthStrategy::ApiKeyInParams,
+            ));
+            auth.add_key_with_id("ctx-key", "svc-dashboard").await;
+
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(auth)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let resp: RpcResponse = reqwest::Client::new()
+            .post(format!("http://{}/rpc", addr))
+            .header("x-test-agent", "integration")
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "method": "whoami",
+                "params": {"api_key": "ctx-key"},
+                "id": 1
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let result = resp.result.unwrap();
+        assert_eq!(result["http"], true);
+        assert_eq!(result["peer"], "127.0.0.1");
+        assert_eq!(result["agent"], "integration");
+        assert_eq!(result["principal"], "svc-dashboard");
+    }
+
+    #[tokio::test]
+    async fn test_http_header_auth() {
+        let addr = "127.0.0.1:13003";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!({
+                        "principal": ctx.principal.as_ref().map(|p| p.id.clone()),
+                        "sees_key": ctx.header("authorization").is_some(),
+                    }))
+                })
+                .await;
+            let auth = Arc::new(middleware::AuthMiddleware::new(
+                middleware::AuthStrategy::ApiKeyInHeader,
+            ));
+            auth.add_key_with_id("test-key", "svc-a").await;
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(auth)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/rpc", addr);
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "whoami", "id": 1},
+            {"jsonrpc": "2.0", "method": "whoami", "id": 2},
+        ]);
+
+        // The header authenticates every entry; handlers never see the key
+        let resp = client
+            .post(&url)
+            .bearer_auth("test-key")
+            .json(&batch)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body[0]["result"]["principal"], "svc-a");
+        assert_eq!(body[1]["result"]["principal"], "svc-a");
+        assert_eq!(body[0]["result"]["sees_key"], false);
+
+        // Missing or wrong keys get 401 with a JSON-RPC error body
+        let resp = client.post(&url).json(&batch).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["code"], middleware::AUTH_REQUIRED);
+
+        let resp = client
+            .post(&url)
+            .bearer_auth("wrong")
+            .json(&batch)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["code"], middleware::AUTH_ERROR);
+
+        // A key in params is not accepted
+        let (status, _) = post_json(
+            addr,
+            json!({"jsonrpc": "2.0", "method": "whoami", "params": {"api_key": "test-key"}, "id": 3}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    fn hs256_token(secret: &str, claims: serde_json::Value) -> String {
+        use base64::Engine;
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+        let header = URL_SAFE_NO_PAD.encode(json!({"alg": "HS256"}).to_string());
+        let input = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+        let sig = ring::hmac::sign(&key, input.as_bytes());
+        format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    #[tokio::test]
+    async fn test_http_jwt_auth() {
+        let addr = "127.0.0.1:13004";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    let principal = ctx.principal.unwrap();
+                    Ok(json!({"sub": principal.id, "scopes": principal.scopes}))
+                })
+                .await;
+            let auth = Arc::new(middleware::AuthMiddleware::new(
+                middleware::AuthStrategy::Jwt(middleware::JwtConfig::hs256("s3cret")),
+            ));
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(auth)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600;
+        let token = hs256_token(
+            "s3cret",
+            json!({"sub": "alice", "scope": "read", "exp": exp}),
+        );
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/rpc", addr);
+        let req = json!({"jsonrpc": "2.0", "method": "whoami", "id": 1});
+
+        let resp = client
+            .post(&url)
+            .bearer_auth(&token)
+            .json(&req)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["result"], json!({"sub": "alice", "scopes": ["read"]}));
+
+        let forged = hs256_token("guess", json!({"sub": "alice", "exp": exp}));
+        let resp = client
+            .post(&url)
+            .bearer_auth(&forged)
+            .json(&req)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_http_rate_limit_returns_429() {
+        let addr = "127.0.0.1:13005";
+        let metrics = Arc::new(Metrics::new());
+        let limiter = Arc::new(
+            middleware::RateLimiter::new()
+                .per_ip(middleware::RateLimit::per_minute(3))
+                .with_metrics(metrics.clone()),
+        );
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let _ = transport::HttpTransport::new(server)
+                .with_rate_limit(limiter)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let ping = |id: u64| json!({"jsonrpc": "2.0", "method": "ping", "id": id});
+
+        // Each batch entry takes a token: three fit, the fourth is over the limit
+        let (status, body) = post_json(addr, json!([ping(1), ping(2), ping(3), ping(4)])).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body[2]["result"], "pong");
+        assert_eq!(body[3]["error"]["code"], middleware::RATE_LIMITED);
+
+        let resp = reqwest::Client::new()
+            .post(format!("http://{}/rpc", addr))
+            .json(&ping(5))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=20).contains(&retry_after), "{}", retry_after);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert!(body["error"]["data"]["retry_after_ms"].as_u64().unwrap() > 0);
+        assert_eq!(body["error"]["data"]["limit"], "ip");
+
+        let snapshot = metrics.snapshot().await;
+        assert_eq!(snapshot.total_rate_limited, 2);
+        assert_eq!(snapshot.rate_limited_counts.get("ip"), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn test_http_rate_limit_failed_header_auth() {
+        let addr = "127.0.0.1:13013";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let auth = Arc::new(middleware::AuthMiddleware::new(
+                middleware::AuthStrategy::ApiKeyInHeader,
+            ));
+            auth.add_key("test-key").await;
+            let limiter = Arc::new(
+                middleware::RateLimiter::new().per_ip(middleware::RateLimit::per_minute(2)),
+            );
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(auth)
+                .with_rate_limit(limiter)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/rpc", addr);
+        let ping = json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        let send = |key: &'static str| client.post(&url).bearer_auth(key).json(&ping).send();
+
+        // Each rejected key takes a token from the peer's bucket
+        for _ in 0..2 {
+            assert_eq!(
+                send("guess").await.unwrap().status(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        // Once it is empty, even the right key is refused unchecked
+        let resp = send("test-key").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key("retry-after"));
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["code"], middleware::RATE_LIMITED);
+    }
+
+    #[tokio::test]
+    async fn test_http_signed_requests() {
+        use middleware::{HmacConfig, sign_request};
+
+        let addr = "127.0.0.1:13006";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!({
+                        "principal": ctx.principal.as_ref().map(|p| p.id.clone()),
+                        "sees_signature": ctx.header("x-signature").is_some(),
+                    }))
+                })
+                .await;
+            let hmac = HmacConfig::new().with_key("billing", "http-secret", ["write"]);
+            let auth = Arc::new(middleware::AuthMiddleware::new(
+                middleware::AuthStrategy::HmacSigned(hmac),
+            ));
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(auth)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let body = json!({"jsonrpc": "2.0", "method": "whoami", "id": 1}).to_string();
+        let send = |timestamp: u64, nonce: &str, secret: &[u8], body: String| {
+            reqwest::Client::new()
+                .post(format!("http://{}/rpc", addr))
+                .header("content-type", "application/json")
+                .header("x-signature-key", "billing")
+                .header("x-signature-timestamp", timestamp.to_string())
+                .header("x-signature-nonce", nonce)
+                .header(
+                    "x-signature",
+                    sign_request(secret, timestamp, nonce, body.as_bytes()),
+                )
+                .body(body)
+                .send()
+        };
+
+        let resp = send(now, "n-1", b"http-secret", body.clone())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let reply: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(reply["result"]["principal"], "billing");
+        assert_eq!(reply["result"]["sees_signature"], false);
+
+        // Replaying a logged request fails even though it is correctly signed
+        let resp = send(now, "n-1", b"http-secret", body.clone())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let reply: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(reply["error"]["data"]["reason"], "replayed_nonce");
+
+        let resp = send(now - 3600, "n-2", b"http-secret", body.clone())
+            .await
+            .unwrap();
+        let reply: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(reply["error"]["data"]["reason"], "stale_timestamp");
+
+        let resp = send(now, "n-3", b"wrong-secret", body).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, _) =
+            post_json(addr, json!({"jsonrpc": "2.0", "method": "whoami", "id": 1})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_http_ip_filter() {
+        use middleware::{IpFilter, IpRules};
+
+        let addr = "127.0.0.1:13007";
+        let metrics = Arc::new(Metrics::new());
+        let filter = Arc::new(IpFilter::new(
+            IpRules::new().restrict(["set_*"], ["10.0.0.0/8"]).unwrap(),
+        ));
+
+        let (server_metrics, server_filter) = (metrics.clone(), filter.clone());
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            server
+                .register("set_limit", |_params| async move { Ok(json!("set")) })
+                .await;
+            let _ = transport::HttpTransport::new(server)
+                .with_metrics(server_metrics)
+                .with_ip_filter(server_filter)
+                .serve(addr)
+                .await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let (status, body) =
+            post_json(addr, json!({"jsonrpc": "2.0", "method": "ping", "id": 1})).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "pong");
+
+        let (_, body) = post_json(
+            addr,
+            json!({"jsonrpc": "2.0", "method": "set_limit", "id": 2}),
+        )
+        .await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], middleware::IP_DENIED);
+
+        filter.replace(IpRules::new().allow(["10.0.0.0/8"]).unwrap());
+        let refused = reqwest::Client::new()
+            .post(format!("http://{}/rpc", addr))
+            .json(&json!({"jsonrpc": "2.0", "method": "ping", "id": 3}))
+            .send()
+            .await;
+        assert!(refused.is_err());
+        assert_eq!(metrics.snapshot().await.total_rejected_connections, 1);
+    }
+}
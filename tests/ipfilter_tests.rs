@@ -0,0 +1,83 @@
+use dice_rpc::middleware::*;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_cidr_matching() {
+    let net: Cidr = "10.1.0.0/16".parse().unwrap();
+    assert!(net.contains(ip("10.1.200.3")));
+    assert!(!net.contains(ip("10.2.0.1")));
+    // IPv4-mapped IPv6 peers match IPv4 blocks
+    assert!(net.contains(ip("::ffff:10.1.0.9")));
+
+    let host: Cidr = "127.0.0.1".parse().unwrap();
+    assert_eq!(host.to_string(), "127.0.0.1/32");
+    assert!(!host.contains(ip("127.0.0.2")));
+
+    let v6: Cidr = "fd00::/8".parse().unwrap();
+    assert!(v6.contains(ip("fd12::1")));
+    assert!(!v6.contains(ip("10.1.0.1")));
+
+    let any: Cidr = "0.0.0.0/0".parse().unwrap();
+    assert!(any.contains(ip("203.0.113.7")));
+
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("example.com/8".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn test_connection_and_method_lists() {
+    let rules = IpRules::new()
+        .allow(["10.0.0.0/8", "127.0.0.0/8"])
+        .unwrap()
+        .deny(["10.13.0.0/16"])
+        .unwrap()
+        .restrict(["set_balance", "admin.*"], ["127.0.0.0/8"])
+        .unwrap();
+    let filter = IpFilter::new(rules);
+
+    assert!(filter.admits(ip("10.1.2.3")));
+    assert!(!filter.admits(ip("10.13.2.3")));
+    assert!(!filter.admits(ip("192.168.1.1")));
+
+    assert!(filter.check("get_balance", Some(ip("10.1.2.3"))).is_ok());
+    assert!(filter.check("set_balance", Some(ip("127.0.0.1"))).is_ok());
+    let err = filter
+        .check("admin.keys.list", Some(ip("10.1.2.3")))
+        .unwrap_err();
+    assert_eq!(err.code, IP_DENIED);
+    assert_eq!(err.data.unwrap()["reason"], "ip_denied");
+
+    // In-process calls have no address to match an allow list
+    assert!(filter.check("get_balance", None).is_ok());
+    assert!(filter.check("set_balance", None).is_err());
+}
+
+#[test]
+fn test_reload_from_file() {
+    let path = std::env::temp_dir().join(format!("dice_rpc_ip_rules_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"deny": ["192.0.2.0/24"]}"#).unwrap();
+
+    let filter = IpFilter::open(&path).unwrap();
+    assert!(!filter.admits(ip("192.0.2.10")));
+
+    std::fs::write(
+        &path,
+        r#"{"methods": [{"methods": ["set_*"], "allow": ["127.0.0.1"]}]}"#,
+    )
+    .unwrap();
+    filter.reload().unwrap();
+    assert!(filter.admits(ip("192.0.2.10")));
+    assert!(filter.check("set_balance", Some(ip("192.0.2.10"))).is_err());
+
+    // A broken file leaves the rules in force
+    std::fs::write(&path, r#"{"deny": ["not-an-address"]}"#).unwrap();
+    let err = filter.reload().unwrap_err().to_string();
+    assert!(err.contains("not-an-address"), "{}", err);
+    assert!(filter.check("set_balance", Some(ip("192.0.2.10"))).is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
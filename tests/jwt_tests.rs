@@ -0,0 +1,143 @@
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::RpcRequest;
+use ring::{hmac, rand, signature};
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECRET: &str = "jwt-test-secret";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn signing_input(alg: &str, claims: &Value) -> String {
+    let header = URL_SAFE_NO_PAD.encode(json!({"alg": alg, "typ": "JWT"}).to_string());
+    let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+    format!("{}.{}", header, payload)
+}
+
+fn hs256_token(secret: &str, claims: &Value) -> String {
+    let input = signing_input("HS256", claims);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let sig = hmac::sign(&key, input.as_bytes());
+    format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig.as_ref()))
+}
+
+fn pem_der(pem: &str) -> Vec<u8> {
+    let body: String = pem.lines().filter(|l| !l.starts_with("-----")).collect();
+    STANDARD.decode(body).unwrap()
+}
+
+fn rs256_token(claims: &Value) -> String {
+    let key =
+        signature::RsaKeyPair::from_pkcs8(&pem_der(include_str!("fixtures/jwt_rs256_key.pem")))
+            .unwrap();
+    let input = signing_input("RS256", claims);
+    let mut sig = vec![0; key.public().modulus_len()];
+    key.sign(
+        &signature::RSA_PKCS1_SHA256,
+        &rand::SystemRandom::new(),
+        input.as_bytes(),
+        &mut sig,
+    )
+    .unwrap();
+    format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig))
+}
+
+fn claims() -> Value {
+    json!({
+        "sub": "user-42",
+        "scope": "read transfer",
+        "iss": "https://auth.example.com",
+        "aud": ["dice-rpc", "other"],
+        "exp": now() + 600,
+    })
+}
+
+#[test]
+fn test_hs256_valid_token() {
+    let config = JwtConfig::hs256(SECRET)
+        .with_issuer("https://auth.example.com")
+        .with_audience("dice-rpc");
+
+    let principal = config.verify(&hs256_token(SECRET, &claims())).unwrap();
+    assert_eq!(principal.id, "user-42");
+    assert_eq!(principal.scheme, "jwt");
+    assert_eq!(principal.scopes, ["read", "transfer"]);
+    assert!(principal.expires_at.is_some());
+    assert_eq!(principal.claims.unwrap()["iss"], "https://auth.example.com");
+}
+
+#[test]
+fn test_rs256_valid_token() {
+    let config = JwtConfig::rs256_pem(include_str!("fixtures/jwt_rs256_pub.pem")).unwrap();
+    let principal = config.verify(&rs256_token(&claims())).unwrap();
+    assert_eq!(principal.id, "user-42");
+}
+
+#[test]
+fn test_rejects_bad_signature_and_alg_confusion() {
+    let config = JwtConfig::hs256(SECRET);
+    let err = config
+        .verify(&hs256_token("other-secret", &claims()))
+        .unwrap_err();
+    assert_eq!(err.code, AUTH_ERROR);
+
+    // An RS256 verifier must not accept an HS256 token, whatever its key
+    let rsa = JwtConfig::rs256_pem(include_str!("fixtures/jwt_rs256_pub.pem")).unwrap();
+    let err = rsa.verify(&hs256_token(SECRET, &claims())).unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "unexpected alg HS256");
+
+    let unsigned = format!("{}.", signing_input("none", &claims()));
+    assert!(config.verify(&unsigned).is_err());
+    assert!(config.verify("not-a-token").is_err());
+}
+
+#[test]
+fn test_time_claims() {
+    let config = JwtConfig::hs256(SECRET);
+
+    let mut expired = claims();
+    expired["exp"] = json!(now() - 3600);
+    let err = config.verify(&hs256_token(SECRET, &expired)).unwrap_err();
+    assert_eq!(err.message, "Token expired");
+
+    let mut early = claims();
+    early["nbf"] = json!(now() + 3600);
+    let err = config.verify(&hs256_token(SECRET, &early)).unwrap_err();
+    assert_eq!(err.message, "Token not yet valid");
+
+    let mut no_exp = claims();
+    no_exp.as_object_mut().unwrap().remove("exp");
+    assert!(config.verify(&hs256_token(SECRET, &no_exp)).is_err());
+}
+
+#[test]
+fn test_issuer_and_audience() {
+    let token = hs256_token(SECRET, &claims());
+
+    let wrong_iss = JwtConfig::hs256(SECRET).with_issuer("https://evil.example.com");
+    assert!(wrong_iss.verify(&token).is_err());
+
+    let wrong_aud = JwtConfig::hs256(SECRET).with_audience("billing");
+    assert!(wrong_aud.verify(&token).is_err());
+}
+
+#[tokio::test]
+async fn test_jwt_strategy_reads_token_from_params_without_headers() {
+    let auth = AuthMiddleware::new(AuthStrategy::Jwt(JwtConfig::hs256(SECRET)));
+    let req = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: "ping".to_string(),
+        params: json!({"token": hs256_token(SECRET, &claims())}),
+        id: Some(json!(1)),
+    };
+
+    let principal = auth.authenticate(&req).await.unwrap().unwrap();
+    assert_eq!(principal.id, "user-42");
+}
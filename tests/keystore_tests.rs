@@ -0,0 +1,206 @@
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::{RpcRequest, RpcServer};
+use dice_rpc::server::admin::register_key_admin_handlers;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+fn temp_store(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("dice_rpc_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn test_issued_key_is_stored_hashed_and_persisted() {
+    let path = temp_store("persist");
+    let keys = KeyStore::open(&path).unwrap();
+    let issued = keys.issue("billing", ["read"], None).await.unwrap();
+
+    let principal = keys.verify(&issued.key).await.unwrap();
+    assert_eq!(principal.id, "billing");
+    assert_eq!(principal.scopes, ["read"]);
+
+    let file = std::fs::read_to_string(&path).unwrap();
+    assert!(!file.contains(&issued.key));
+
+    let reopened = KeyStore::open(&path).unwrap();
+    assert!(reopened.verify(&issued.key).await.is_ok());
+    assert_eq!(reopened.list().await[0].id, issued.info.id);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_issued_key_embeds_its_id() {
+    let keys = KeyStore::new();
+    keys.insert("chosen-key", "legacy", ["read"]).await.unwrap();
+    let issued = keys.issue("billing", ["read"], None).await.unwrap();
+    let prefix = format!("dk_{}.", issued.info.id);
+    assert!(issued.key.starts_with(&prefix));
+
+    // A known id with the wrong secret is rejected
+    assert_eq!(
+        keys.verify(&format!("{}wrong", prefix))
+            .await
+            .unwrap_err()
+            .code,
+        AUTH_ERROR
+    );
+    assert_eq!(keys.verify("chosen-key").await.unwrap().id, "legacy");
+
+    assert_eq!(keys.list().await[1].last_used, None);
+    keys.verify(&issued.key).await.unwrap();
+    assert!(keys.list().await[1].last_used.is_some());
+}
+
+#[tokio::test]
+async fn test_stores_sharing_a_file_keep_each_others_changes() {
+    let path = temp_store("shared");
+    let server = KeyStore::open(&path).unwrap();
+    let issued = server.issue("billing", ["read"], None).await.unwrap();
+    server.verify(&issued.key).await.unwrap();
+
+    // Another process revokes the key while the server keeps its copy
+    let cli = KeyStore::open(&path).unwrap();
+    assert!(cli.revoke(&issued.info.id).await.unwrap());
+
+    // The server's next change is made on top of the file, not over it
+    let other = server.issue("ops", ["read"], None).await.unwrap();
+    assert_eq!(
+        server.verify(&issued.key).await.unwrap_err().code,
+        AUTH_ERROR
+    );
+    assert!(server.verify(&other.key).await.is_ok());
+
+    let cli_key = cli.issue("cli", ["read"], None).await.unwrap();
+    assert!(server.verify(&cli_key.key).await.is_err());
+    server.reload().await.unwrap();
+    assert!(server.verify(&cli_key.key).await.is_ok());
+
+    // Uses are saved by flush, and survive the reload
+    server.flush().await.unwrap();
+    let saved = KeyStore::open(&path).unwrap().list().await;
+    let used: Vec<_> = saved.iter().map(|k| k.last_used.is_some()).collect();
+    assert_eq!(used, [true, true, true]);
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(path.with_extension("lock"));
+}
+
+#[tokio::test]
+async fn test_expired_and_revoked_keys() {
+    let keys = KeyStore::new();
+    let expired = keys
+        .issue("temp", Vec::<String>::new(), Some(Duration::ZERO))
+        .await
+        .unwrap();
+    let err = keys.verify(&expired.key).await.unwrap_err();
+    assert_eq!(err.code, KEY_EXPIRED);
+    assert_eq!(err.data.unwrap()["key_id"], expired.info.id);
+
+    let revoked = keys
+        .issue("gone", Vec::<String>::new(), None)
+        .await
+        .unwrap();
+    assert!(keys.revoke(&revoked.info.id).await.unwrap());
+    assert_eq!(
+        keys.verify(&revoked.key).await.unwrap_err().code,
+        AUTH_ERROR
+    );
+
+    assert!(!keys.revoke("key_missing").await.unwrap());
+    assert_eq!(
+        keys.verify("dk_unknown").await.unwrap_err().code,
+        AUTH_ERROR
+    );
+}
+
+#[tokio::test]
+async fn test_rotation_grace_period() {
+    let keys = KeyStore::new();
+    let old = keys.issue("svc", ["write"], None).await.unwrap();
+
+    let new = keys
+        .rotate(&old.info.id, Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(new.info.principal, "svc");
+    assert!(keys.verify(&old.key).await.is_ok());
+    assert!(keys.verify(&new.key).await.is_ok());
+
+    // Without a grace period the old key stops at once
+    let newer = keys
+        .rotate(&new.info.id, Duration::ZERO)
+        .await
+        .unwrap()
+        .unwrap();
+    let err = keys.verify(&new.key).await.unwrap_err();
+    assert_eq!(err.code, KEY_EXPIRED);
+    assert_eq!(err.data.unwrap()["rotated_to"], newer.info.id);
+    assert!(keys.verify(&newer.key).await.is_ok());
+
+    assert!(
+        keys.rotate("key_missing", Duration::ZERO)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn test_admin_key_methods_require_admin_scope() {
+    let keys = Arc::new(KeyStore::new());
+    let admin = keys.issue("root", ["admin"], None).await.unwrap();
+    let reader = keys.issue("reader", ["read"], None).await.unwrap();
+
+    let server = RpcServer::new();
+    register_key_admin_handlers(&server, keys.clone()).await;
+    server
+        .add_middleware(AuthMiddleware::new(AuthStrategy::ApiKeyInParams).with_key_store(keys))
+        .await;
+
+    let resp = server
+        .handle_request(request("admin.keys.list", json!({"api_key": reader.key})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, FORBIDDEN);
+
+    let resp = server
+        .handle_request(request(
+            "admin.keys.issue",
+            json!({"api_key": admin.key, "label": "ci", "scopes": ["read"], "ttl_secs": 3600}),
+        ))
+        .await
+        .unwrap();
+    let issued = resp.result.unwrap();
+    assert!(issued["key"].as_str().unwrap().starts_with("dk_"));
+    assert!(issued["expires_at"].is_u64());
+
+    let resp = server
+        .handle_request(request(
+            "admin.keys.revoke",
+            json!({"api_key": admin.key, "id": issued["id"]}),
+        ))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none());
+
+    let resp = server
+        .handle_request(request("admin.keys.list", json!({"api_key": admin.key})))
+        .await
+        .unwrap();
+    let listed = resp.result.unwrap();
+    assert_eq!(listed["count"], 3);
+    assert!(listed["keys"][0].get("hash").is_none());
+    assert_eq!(listed["keys"][2]["revoked"], true);
+}
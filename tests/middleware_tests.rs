@@ -0,0 +1,224 @@
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::{RequestContext, RpcError, RpcRequest, RpcServer};
+use dice_rpc::{BatchRequest, Metrics};
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+/// Records the order in which layers see a request and its outcome
+struct Trace {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl RpcMiddleware for Trace {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} before", self.name));
+            let outcome = next.run(req, ctx).await;
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} after", self.name));
+            outcome
+        })
+    }
+}
+
+struct DenyAdmin;
+
+impl RpcMiddleware for DenyAdmin {
+    fn handle<'a>(
+        &'a self,
+        req: RpcRequest,
+        ctx: RequestContext,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            if req.method.starts_with("admin.") {
+                return Err(RpcError::new(-32010, "Forbidden"));
+            }
+            next.run(req, ctx).await
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_layers_run_in_order() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let server = RpcServer::new();
+    dice_rpc::rpc::register_default_handlers(&server).await;
+    server
+        .add_middleware(Trace {
+            name: "outer",
+            log: log.clone(),
+        })
+        .await;
+    server
+        .add_middleware(Trace {
+            name: "inner",
+            log: log.clone(),
+        })
+        .await;
+
+    let resp = server
+        .handle_request(request("ping", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!("pong")));
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["outer before", "inner before", "inner after", "outer after"]
+    );
+}
+
+#[tokio::test]
+async fn test_short_circuit_skips_handler() {
+    let called = Arc::new(Mutex::new(false));
+    let server = RpcServer::new();
+    let flag = called.clone();
+    server
+        .register("admin.reset", move |_| {
+            let flag = flag.clone();
+            async move {
+                *flag.lock().unwrap() = true;
+                Ok(Value::Null)
+            }
+        })
+        .await;
+    server.add_middleware(DenyAdmin).await;
+
+    let resp = server
+        .handle_request(request("admin.reset", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, -32010);
+    assert!(!*called.lock().unwrap());
+}
+
+#[tokio::test]
+async fn test_transport_layers_wrap_server_layers() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let server = RpcServer::new();
+    dice_rpc::rpc::register_default_handlers(&server).await;
+    server
+        .add_middleware(Trace {
+            name: "server",
+            log: log.clone(),
+        })
+        .await;
+
+    let transport: Vec<Arc<dyn RpcMiddleware>> = vec![Arc::new(Trace {
+        name: "transport",
+        log: log.clone(),
+    })];
+    server
+        .handle_request_with_middleware(
+            request("ping", json!({})),
+            RequestContext::default(),
+            &transport,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "transport before",
+            "server before",
+            "server after",
+            "transport after"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_timeout_middleware() {
+    let server = RpcServer::new();
+    server
+        .register("slow", |_| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(Value::Null)
+        })
+        .await;
+    server
+        .add_middleware(TimeoutMiddleware::new(Duration::from_millis(50)))
+        .await;
+
+    let resp = server
+        .handle_request(request("slow", json!({})))
+        .await
+        .unwrap();
+    let err = resp.error.unwrap();
+    assert_eq!(err.code, REQUEST_TIMEOUT);
+    assert_eq!(err.data.unwrap()["timeout_ms"], 50);
+}
+
+#[tokio::test]
+async fn test_auth_middleware_sets_principal() {
+    let server = RpcServer::new();
+    server
+        .register_with_context("whoami", |_, ctx| async move {
+            Ok(json!(ctx.principal.map(|p| p.id)))
+        })
+        .await;
+
+    let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams);
+    auth.add_key_with_id("secret-key", "alice").await;
+    server.add_middleware(auth).await;
+
+    let resp = server
+        .handle_request(request("whoami", json!({"api_key": "secret-key"})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!("alice")));
+
+    let resp = server
+        .handle_request(request("whoami", json!({"api_key": "nope"})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, AUTH_ERROR);
+}
+
+#[tokio::test]
+async fn test_metrics_count_each_batch_entry() {
+    let metrics = Arc::new(Metrics::new());
+    let server = RpcServer::new();
+    dice_rpc::rpc::register_default_handlers(&server).await;
+    server
+        .add_middleware(MetricsMiddleware::new(metrics.clone()))
+        .await;
+
+    let batch = BatchRequest::parse(
+        r#"[
+            {"jsonrpc":"2.0","method":"ping","id":1},
+            {"jsonrpc":"2.0","method":"ping","id":2},
+            {"jsonrpc":"2.0","method":"missing","id":3}
+        ]"#,
+    )
+    .unwrap();
+    server.handle_batch(batch).await.unwrap();
+
+    let snapshot = metrics.snapshot().await;
+    assert_eq!(snapshot.total_requests, 3);
+    assert_eq!(snapshot.total_success, 2);
+    assert_eq!(snapshot.total_errors, 1);
+    assert_eq!(snapshot.method_counts.get("ping"), Some(&2));
+}
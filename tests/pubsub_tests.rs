@@ -0,0 +1,252 @@
+#[cfg(all(feature = "tcp", feature = "http"))]
+mod pubsub_tests {
+    use dice_rpc::rpc::{Overflow, Subscriber, SubscriptionLimits};
+    use dice_rpc::server::handlers::register_stateful_handlers;
+    use dice_rpc::transport::FrameCodec;
+    use dice_rpc::*;
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message;
+
+    async fn stateful_server() -> Arc<RpcServer> {
+        let server = Arc::new(RpcServer::new());
+        register_stateful_handlers(&server, Arc::new(StateStore::new())).await;
+        server
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "method": method, "params": params, "id": id})
+    }
+
+    /// Frames read while waiting for a response, in order
+    struct Connection {
+        stream: TcpStream,
+        pushed: Vec<Value>,
+    }
+
+    impl Connection {
+        async fn call(&mut self, id: u64, method: &str, params: Value) -> Value {
+            let req = request(id, method, params);
+            FrameCodec::write_frame(&mut self.stream, &serde_json::to_vec(&req).unwrap())
+                .await
+                .unwrap();
+            loop {
+                let frame = FrameCodec::read_frame(&mut self.stream).await.unwrap();
+                let value: Value = serde_json::from_slice(&frame).unwrap();
+                if value["id"] == id {
+                    return value;
+                }
+                self.pushed.push(value);
+            }
+        }
+
+        async fn next_push(&mut self) -> Value {
+            if !self.pushed.is_empty() {
+                return self.pushed.remove(0);
+            }
+            let frame = tokio::time::timeout(
+                Duration::from_secs(2),
+                FrameCodec::read_frame(&mut self.stream),
+            )
+            .await
+            .expect("no notification")
+            .unwrap();
+            serde_json::from_slice(&frame).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_state_subscriptions() {
+        let addr = "127.0.0.1:14018";
+        tokio::spawn(async move {
+            let config = transport::TcpServerConfig::new(addr, stateful_server().await);
+            let _ = transport::run_with_framing(config).await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut conn = Connection {
+            stream: TcpStream::connect(addr).await.unwrap(),
+            pushed: Vec::new(),
+        };
+        let resp = conn
+            .call(
+                1,
+                "subscribe",
+                json!({"topic": "balanceChanged", "params": {"address": "bob"}}),
+            )
+            .await;
+        let balances = resp["result"].as_str().unwrap().to_string();
+        let resp = conn.call(2, "subscribe", json!(["newTransactions"])).await;
+        let transactions = resp["result"].as_str().unwrap().to_string();
+
+        conn.call(
+            3,
+            "set_balance",
+            json!({"address": "alice", "balance": 100}),
+        )
+        .await;
+        let resp = conn
+            .call(
+                4,
+                "transfer",
+                json!({"from": "alice", "to": "bob", "amount": 40}),
+            )
+            .await;
+        let txid = resp["result"]["txid"].as_str().unwrap().to_string();
+
+        // alice's balance changes are filtered out
+        let push = conn.next_push().await;
+        assert_eq!(push["method"], "subscription");
+        assert_eq!(push["params"]["subscription"], balances.as_str());
+        assert_eq!(
+            push["params"]["result"],
+            json!({"address": "bob", "balance": "40"})
+        );
+        let push = conn.next_push().await;
+        assert_eq!(push["params"]["subscription"], transactions.as_str());
+        assert_eq!(push["params"]["result"]["txid"], txid.as_str());
+        assert_eq!(push["params"]["result"]["status"], "pending");
+
+        let resp = conn
+            .call(5, "subscribe", json!(["txStatus", {"txid": txid}]))
+            .await;
+        let status = resp["result"].as_str().unwrap().to_string();
+        assert_eq!(
+            conn.call(6, "unsubscribe", json!([transactions])).await["result"],
+            true
+        );
+        assert_eq!(
+            conn.call(7, "unsubscribe", json!([transactions])).await["result"],
+            false
+        );
+
+        conn.call(8, "confirm_transaction", json!({"txid": txid}))
+            .await;
+        let push = conn.next_push().await;
+        assert_eq!(push["params"]["subscription"], status.as_str());
+        assert_eq!(push["params"]["result"]["status"], "confirmed");
+
+        let resp = conn.call(9, "subscribe", json!({"topic": "blocks"})).await;
+        assert_eq!(resp["error"]["code"], -32602);
+        assert_eq!(
+            resp["error"]["data"]["topics"],
+            json!(["balanceChanged", "newTransactions", "txStatus"])
+        );
+        let resp = conn.call(10, "subscribe", json!(["txStatus"])).await;
+        assert_eq!(resp["error"]["code"], -32602);
+    }
+
+    #[tokio::test]
+    async fn test_ws_subscriptions() {
+        let addr = "127.0.0.1:13012";
+        tokio::spawn(async move {
+            let _ = transport::HttpTransport::new(stateful_server().await)
+                .serve(addr)
+                .await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Plain HTTP has no way to push
+        let resp: Value = reqwest::Client::new()
+            .post(format!("http://{}/rpc", addr))
+            .json(&request(1, "subscribe", json!(["newTransactions"])))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp["error"]["code"], -32600);
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+        let mut send = async |value: Value| {
+            socket.send(Message::text(value.to_string())).await.unwrap();
+            loop {
+                match socket.next().await.unwrap().unwrap() {
+                    Message::Text(text) => return serde_json::from_str::<Value>(&text).unwrap(),
+                    _ => continue,
+                }
+            }
+        };
+
+        let resp = send(request(1, "subscribe", json!(["newTransactions"]))).await;
+        let subscription = resp["result"].clone();
+        assert!(subscription.is_string());
+        send(request(
+            2,
+            "set_balance",
+            json!({"address": "a", "balance": 5}),
+        ))
+        .await;
+
+        // The push and the transfer reply race; both arrive
+        let mut replies = vec![
+            send(request(
+                3,
+                "transfer",
+                json!({"from": "a", "to": "b", "amount": 5}),
+            ))
+            .await,
+        ];
+        while replies.len() < 2 {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                replies.push(serde_json::from_str(&text).unwrap());
+            }
+        }
+        let push = replies
+            .iter()
+            .find(|r| r["method"] == "subscription")
+            .unwrap();
+        assert_eq!(push["params"]["subscription"], subscription);
+        assert_eq!(push["params"]["result"]["to"], "b");
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_policies() {
+        let (items, stream) = futures::channel::mpsc::unbounded::<Value>();
+        let limits = SubscriptionLimits {
+            buffer: 2,
+            overflow: Overflow::DropNewest,
+            max_per_connection: 1,
+        };
+        let (subscriber, mut notifications) = Subscriber::new(limits);
+        subscriber.subscribe(stream.boxed()).unwrap();
+        assert_eq!(
+            subscriber
+                .subscribe(futures::stream::empty().boxed())
+                .unwrap_err()
+                .code,
+            -32600
+        );
+
+        for i in 0..5 {
+            items.unbounded_send(json!(i)).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(notifications.recv().await.unwrap()["params"]["result"], 0);
+        assert_eq!(notifications.recv().await.unwrap()["params"]["result"], 1);
+        items.unbounded_send(json!(5)).unwrap();
+        let next = notifications.recv().await.unwrap();
+        assert_eq!(next["params"]["result"], 5);
+        assert_eq!(next["params"]["dropped"], 3);
+
+        let (items, stream) = futures::channel::mpsc::unbounded::<Value>();
+        let limits = SubscriptionLimits {
+            buffer: 1,
+            overflow: Overflow::Disconnect,
+            ..SubscriptionLimits::default()
+        };
+        let (subscriber, _notifications) = Subscriber::new(limits);
+        subscriber.subscribe(stream.boxed()).unwrap();
+        items.unbounded_send(json!(0)).unwrap();
+        items.unbounded_send(json!(1)).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), subscriber.overflowed())
+            .await
+            .expect("overflow not signalled");
+    }
+}
@@ -0,0 +1,148 @@
+use dice_rpc::middleware::*;
+use dice_rpc::rpc::{RequestContext, RpcRequest, RpcServer, TransportKind};
+use dice_rpc::{BatchRequest, BatchResponse, Metrics};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+fn ctx(transport: TransportKind, principal: &str, peer: &str) -> RequestContext {
+    RequestContext::new(transport)
+        .with_peer_addr(peer.parse().unwrap())
+        .with_principal(Principal::new(principal, "api_key"))
+}
+
+#[test]
+fn test_per_method_limits_are_per_caller() {
+    let limiter = RateLimiter::new().per_method("transfer", RateLimit::per_minute(2));
+    let alice = ctx(TransportKind::Http, "alice", "10.0.0.1:1000");
+    let bob = ctx(TransportKind::Http, "bob", "10.0.0.2:1000");
+
+    assert!(limiter.check("transfer", &alice).is_ok());
+    assert!(limiter.check("transfer", &alice).is_ok());
+    let err = limiter.check("transfer", &alice).unwrap_err();
+    assert_eq!(err.code, RATE_LIMITED);
+    let data = err.data.unwrap();
+    assert_eq!(data["limit"], "method");
+    assert!(data["retry_after_ms"].as_u64().unwrap() > 0);
+
+    // Other callers and other methods are unaffected
+    assert!(limiter.check("transfer", &bob).is_ok());
+    assert!(limiter.check("get_balance", &alice).is_ok());
+}
+
+#[test]
+fn test_key_limit_shared_across_transports() {
+    let limiter = RateLimiter::new().per_key(RateLimit::per_minute(2));
+
+    let tcp = ctx(TransportKind::TcpFramed, "svc", "10.0.0.1:1000");
+    let http = ctx(TransportKind::Http, "svc", "10.0.0.9:2000");
+    assert!(limiter.check("ping", &tcp).is_ok());
+    assert!(limiter.check("ping", &http).is_ok());
+    assert_eq!(limiter.check("ping", &tcp).unwrap_err().code, RATE_LIMITED);
+
+    // Requests without a principal have no key bucket
+    assert!(
+        limiter
+            .check("ping", &RequestContext::new(TransportKind::Http))
+            .is_ok()
+    );
+}
+
+#[test]
+fn test_key_limit_is_per_stored_key() {
+    let limiter = RateLimiter::new().per_key(RateLimit::per_minute(1));
+    let with_key = |key_id: &str| {
+        RequestContext::new(TransportKind::Http)
+            .with_principal(Principal::new("billing", "api_key").with_key_id(key_id))
+    };
+
+    // Two keys issued under one label have their own budgets
+    assert!(limiter.check("ping", &with_key("k1")).is_ok());
+    assert!(limiter.check("ping", &with_key("k2")).is_ok());
+    assert!(limiter.check("ping", &with_key("k1")).is_err());
+
+    // A JWT subject that happens to match a key id is a different caller
+    let jwt = RequestContext::new(TransportKind::Http).with_principal(Principal::new("k1", "jwt"));
+    assert!(limiter.check("ping", &jwt).is_ok());
+}
+
+#[test]
+fn test_rejected_request_takes_no_tokens() {
+    let limiter = RateLimiter::new()
+        .per_ip(RateLimit::per_minute(5))
+        .per_method("transfer", RateLimit::per_minute(1));
+    let caller = ctx(TransportKind::Http, "alice", "10.0.0.1:1000");
+
+    assert!(limiter.check("transfer", &caller).is_ok());
+    for _ in 0..3 {
+        assert!(limiter.check("transfer", &caller).is_err());
+    }
+    // Only the accepted transfer counted against the IP budget
+    for _ in 0..4 {
+        assert!(limiter.check("ping", &caller).is_ok());
+    }
+    assert_eq!(
+        limiter.check("ping", &caller).unwrap_err().data.unwrap()["limit"],
+        "ip"
+    );
+}
+
+#[tokio::test]
+async fn test_tokens_refill() {
+    let limiter = RateLimiter::new().per_ip(RateLimit::per_second(20).with_burst(1));
+    let caller = ctx(TransportKind::Http, "alice", "10.0.0.1:1000");
+
+    assert!(limiter.check("ping", &caller).is_ok());
+    assert!(limiter.check("ping", &caller).is_err());
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(limiter.check("ping", &caller).is_ok());
+}
+
+#[tokio::test]
+async fn test_batch_entries_count_individually() {
+    let metrics = Arc::new(Metrics::new());
+    let server = RpcServer::new();
+    dice_rpc::rpc::register_default_handlers(&server).await;
+    let layers: Vec<Arc<dyn RpcMiddleware>> = vec![Arc::new(
+        RateLimiter::new()
+            .per_key(RateLimit::per_minute(2))
+            .with_metrics(metrics.clone()),
+    )];
+
+    let batch = BatchRequest::parse(
+        r#"[
+            {"jsonrpc":"2.0","method":"ping","id":1},
+            {"jsonrpc":"2.0","method":"ping","id":2},
+            {"jsonrpc":"2.0","method":"ping","id":3}
+        ]"#,
+    )
+    .unwrap();
+    let caller = ctx(TransportKind::TcpFramed, "svc", "10.0.0.1:1000");
+    let Some(BatchResponse::Batch(resps)) = server
+        .handle_batch_with_middleware(batch, caller.clone(), &layers)
+        .await
+    else {
+        panic!("expected a batch response");
+    };
+    let limited = resps.iter().filter(|r| r.error.is_some()).count();
+    assert_eq!(limited, 1);
+
+    let resp = server
+        .handle_request_with_middleware(request("ping", json!({})), caller, &layers)
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, RATE_LIMITED);
+
+    let snapshot = metrics.snapshot().await;
+    assert_eq!(snapshot.total_rate_limited, 2);
+    assert_eq!(snapshot.rate_limited_counts.get("key"), Some(&2));
+}
@@ -0,0 +1,125 @@
+use dice_rpc::middleware::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECRET: &[u8] = b"signing-test-secret";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn signed<'a>(
+    timestamp: u64,
+    nonce: &'a str,
+    signature: &'a str,
+    body: &'a [u8],
+) -> SignedRequest<'a> {
+    SignedRequest {
+        key_id: "svc",
+        timestamp,
+        nonce,
+        signature,
+        body,
+    }
+}
+
+fn config() -> HmacConfig {
+    HmacConfig::new().with_key("svc", SECRET, ["write"])
+}
+
+#[test]
+fn test_valid_signature_and_replay() {
+    let hmac = config();
+    let body = br#"{"jsonrpc":"2.0","method":"transfer","params":{"amount":5},"id":1}"#;
+    let ts = now();
+    let sig = sign_request(SECRET, ts, "nonce-1", body);
+
+    let principal = hmac.verify(&signed(ts, "nonce-1", &sig, body)).unwrap();
+    assert_eq!(principal.id, "svc");
+    assert_eq!(principal.scheme, "hmac");
+    assert!(principal.has_scope("write"));
+
+    let err = hmac.verify(&signed(ts, "nonce-1", &sig, body)).unwrap_err();
+    assert_eq!(err.code, AUTH_ERROR);
+    assert_eq!(err.data.unwrap()["reason"], "replayed_nonce");
+}
+
+#[test]
+fn test_tampering_and_unknown_keys() {
+    let hmac = config();
+    let body = br#"{"jsonrpc":"2.0","method":"transfer","params":{"amount":5},"id":1}"#;
+    let tampered = br#"{"jsonrpc":"2.0","method":"transfer","params":{"amount":500},"id":1}"#;
+    let ts = now();
+    let sig = sign_request(SECRET, ts, "n", body);
+
+    let err = hmac.verify(&signed(ts, "n", &sig, tampered)).unwrap_err();
+    assert_eq!(err.data.unwrap()["reason"], "bad signature");
+    // The timestamp and nonce are covered too
+    assert!(hmac.verify(&signed(ts + 1, "n", &sig, body)).is_err());
+    assert!(hmac.verify(&signed(ts, "m", &sig, body)).is_err());
+    assert!(hmac.verify(&signed(ts, "n", "not-hex", body)).is_err());
+
+    let mut other = signed(ts, "n", &sig, body);
+    other.key_id = "nobody";
+    assert_eq!(
+        hmac.verify(&other).unwrap_err().data.unwrap()["reason"],
+        "unknown key"
+    );
+
+    // A rejected forgery does not burn the nonce
+    assert!(hmac.verify(&signed(ts, "n", &sig, body)).is_ok());
+}
+
+#[test]
+fn test_timestamp_window() {
+    let hmac = config().with_max_skew(Duration::from_secs(60));
+    let body = b"{}";
+
+    for (ts, ok) in [
+        (now() - 30, true),
+        (now() + 30, true),
+        (now() - 120, false),
+        (now() + 120, false),
+    ] {
+        let nonce = format!("n-{}", ts);
+        let sig = sign_request(SECRET, ts, &nonce, body);
+        let outcome = hmac.verify(&signed(ts, &nonce, &sig, body));
+        assert_eq!(
+            outcome.is_ok(),
+            ok,
+            "timestamp offset {}",
+            ts as i64 - now() as i64
+        );
+        if let Err(err) = outcome {
+            assert_eq!(err.data.unwrap()["reason"], "stale_timestamp");
+        }
+    }
+}
+
+#[test]
+fn test_full_nonce_cache_refuses_instead_of_forgetting() {
+    let hmac = config().with_nonce_capacity(2);
+    let body = b"{}";
+    let ts = now();
+    let verify = |nonce: &str| {
+        let sig = sign_request(SECRET, ts, nonce, body);
+        hmac.verify(&signed(ts, nonce, &sig, body))
+    };
+
+    assert!(verify("n-1").is_ok());
+    assert!(verify("n-2").is_ok());
+
+    // Every stored nonce is still inside the window, so none can go
+    let err = verify("n-3").unwrap_err();
+    assert_eq!(err.code, RATE_LIMITED);
+    let data = err.data.unwrap();
+    assert_eq!(data["reason"], "nonce_cache_full");
+    assert!(data["retry_after_ms"].as_u64().unwrap() > 0);
+
+    // The first nonce is still remembered
+    let err = verify("n-1").unwrap_err();
+    assert_eq!(err.code, AUTH_ERROR);
+    assert_eq!(err.data.unwrap()["reason"], "replayed_nonce");
+}
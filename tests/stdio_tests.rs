@@ -0,0 +1,115 @@
+use dice_rpc::transport::stdio::{StdioClient, StdioFraming, StdioServerConfig, serve};
+use dice_rpc::*;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+#[tokio::test]
+async fn test_stdio_content_length_framing() {
+    let server = Arc::new(RpcServer::new());
+    rpc::register_default_handlers(&server).await;
+    let config = StdioServerConfig::new(server).with_framing(StdioFraming::ContentLength);
+
+    let (client, server_end) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server_end);
+    let serving = tokio::spawn(serve(config, server_read, server_write));
+    let (client_read, mut client_write) = tokio::io::split(client);
+    let mut client_read = BufReader::new(client_read);
+
+    // Headers besides Content-Length are ignored, in any case
+    let body = json!({"jsonrpc": "2.0", "method": "ping", "id": 1}).to_string();
+    let message = format!(
+        "content-type: application/vscode-jsonrpc; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    client_write.write_all(message.as_bytes()).await.unwrap();
+    let reply = StdioFraming::ContentLength
+        .read_message(&mut client_read)
+        .await
+        .unwrap()
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(reply["result"], "pong");
+
+    // A notification gets no reply; the next reply answers the batch
+    let framing = StdioFraming::ContentLength;
+    let notification = json!({"jsonrpc": "2.0", "method": "ping"});
+    framing
+        .write_message(&mut client_write, notification.to_string().as_bytes())
+        .await
+        .unwrap();
+    let batch = json!([
+        {"jsonrpc": "2.0", "method": "ping", "id": 2},
+        {"jsonrpc": "2.0", "method": "nope", "id": 3}
+    ]);
+    framing
+        .write_message(&mut client_write, batch.to_string().as_bytes())
+        .await
+        .unwrap();
+    let reply = framing
+        .read_message(&mut client_read)
+        .await
+        .unwrap()
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(reply[0]["result"], "pong");
+    assert_eq!(reply[1]["error"]["code"], -32601);
+
+    framing
+        .write_message(&mut client_write, b"{not json")
+        .await
+        .unwrap();
+    let reply = framing
+        .read_message(&mut client_read)
+        .await
+        .unwrap()
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(reply["error"]["code"], -32700);
+
+    // Closing the input ends the server cleanly
+    client_write.shutdown().await.unwrap();
+    serving.await.unwrap().unwrap();
+}
+
+async fn spawn_server(framing: &str, as_framing: StdioFraming) -> StdioClient {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_dice_rpc"));
+    cmd.args(["stdio-server", "--framing", framing])
+        .env("RUST_LOG", "off");
+    StdioClient::spawn(cmd, as_framing).unwrap()
+}
+
+#[tokio::test]
+async fn test_stdio_subprocess() {
+    for (flag, framing) in [
+        ("length", StdioFraming::LengthPrefixed),
+        ("lsp", StdioFraming::ContentLength),
+    ] {
+        let mut client = spawn_server(flag, framing).await;
+
+        let resp = client
+            .call("get_balance", json!({"address": "0xAlice"}))
+            .await
+            .unwrap();
+        assert_eq!(resp["result"]["balance"], "100000");
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "transfer", "params": {"from": "0xAlice", "to": "0xBob", "amount": 5}, "id": "a"},
+            {"jsonrpc": "2.0", "method": "get_balance", "params": {"address": "0xBob"}, "id": "b"}
+        ]);
+        let resp = client.request(&batch).await.unwrap().unwrap();
+        assert_eq!(resp[0]["result"]["status"], "pending");
+        assert_eq!(resp[1]["result"]["balance"], "50005");
+
+        let notification = json!({"jsonrpc": "2.0", "method": "ping"});
+        assert!(client.request(&notification).await.unwrap().is_none());
+        assert_eq!(
+            client.call("ping", json!({})).await.unwrap()["result"],
+            "pong"
+        );
+
+        assert!(client.shutdown().await.unwrap().success());
+    }
+}
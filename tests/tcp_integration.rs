@@ -95,7 +95,7 @@ mod tcp_tests {
             state.set_balance("0xBob", 500).await;
             
             server::handlers::register_stateful_handlers(&server, state).await;
-            let _ = server::server::run(addr).await;
+            let _ = server::server::serve(addr, server).await;
         });
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -127,7 +127,7 @@ mod tcp_tests {
         tokio::spawn(async move {
             let server = Arc::new(RpcServer::new());
             server::handlers::register_stateful_handlers(&server, state_clone).await;
-            let _ = server::server::run(addr).await;
+            let _ = server::server::serve(addr, server).await;
         });
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -293,4 +293,807 @@ mod tcp_tests {
 
         assert!(response.error.is_some());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_tcp_framed_notifications() {
+        let addr = "127.0.0.1:14008";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        use dice_rpc::transport::FrameCodec;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // A single notification and a notification-only batch produce no frame
+        let notification = json!({"jsonrpc": "2.0", "method": "ping", "params": {}});
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&notification).unwrap())
+            .await
+            .unwrap();
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "ping"},
+            {"jsonrpc": "2.0", "method": "nonexistent_method"},
+        ]);
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&batch).unwrap())
+            .await
+            .unwrap();
+
+        // A mixed batch only answers the entries that carry an id
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "ping"},
+            {"jsonrpc": "2.0", "method": "ping", "id": 7},
+        ]);
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&batch).unwrap())
+            .await
+            .unwrap();
+
+        let resp_bytes = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let responses: Vec<RpcResponse> = serde_json::from_slice(&resp_bytes).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, json!(7));
+        assert_eq!(responses[0].result, Some(json!("pong")));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_framed_request_context() {
+        let addr = "127.0.0.1:14009";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!({
+                        "framed": ctx.transport == TransportKind::TcpFramed,
+                        "peer": ctx.peer_addr.map(|a| a.to_string()),
+                        "headers": ctx.headers.is_some(),
+                    }))
+                })
+                .await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        use dice_rpc::transport::FrameCodec;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let local = stream.local_addr().unwrap().to_string();
+
+        let req = json!({"jsonrpc": "2.0", "method": "whoami", "params": {}, "id": 1});
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&req).unwrap())
+            .await
+            .unwrap();
+        let resp_bytes = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let response: RpcResponse = serde_json::from_slice(&resp_bytes).unwrap();
+
+        let result = response.result.unwrap();
+        assert_eq!(result["framed"], true);
+        assert_eq!(result["peer"], local);
+        assert_eq!(result["headers"], false);
+    }
+
+    /// Send one framed request on an open connection and read its response
+    async fn call_framed(stream: &mut TcpStream, req: serde_json::Value) -> serde_json::Value {
+        use dice_rpc::transport::FrameCodec;
+
+        FrameCodec::write_frame(stream, &serde_json::to_vec(&req).unwrap())
+            .await
+            .unwrap();
+        let resp_bytes = FrameCodec::read_frame(stream).await.unwrap();
+        serde_json::from_slice(&resp_bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tcp_auth_applies_to_batch_entries() {
+        let addr = "127.0.0.1:14010";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
+            auth.add_key("test-key-123").await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server).with_auth(auth);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let resp = call_framed(
+            &mut stream,
+            json!([
+                {"jsonrpc": "2.0", "method": "ping", "params": {"api_key": "test-key-123"}, "id": 1},
+                {"jsonrpc": "2.0", "method": "ping", "params": {}, "id": 2},
+            ]),
+        )
+        .await;
+
+        assert_eq!(resp[0]["result"], "pong");
+        assert_eq!(resp[1]["error"]["code"], middleware::AUTH_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_session_auth() {
+        let addr = "127.0.0.1:14011";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!(ctx.principal.map(|p| p.id)))
+                })
+                .await;
+            let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
+            auth.add_key_with_id("test-key-123", "svc-a").await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_auth(auth)
+                .with_sessions(std::time::Duration::from_millis(300));
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let whoami = json!({"jsonrpc": "2.0", "method": "whoami", "params": {}, "id": 2});
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Without a session, credentials are required on every request
+        let resp = call_framed(&mut stream, whoami.clone()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED);
+
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "rpc.auth", "params": {"api_key": "wrong"}, "id": 1}),
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "rpc.auth", "params": {"api_key": "test-key-123"}, "id": 1}),
+        )
+        .await;
+        assert_eq!(resp["result"]["principal"], "svc-a");
+
+        // Later requests on the same connection inherit the identity
+        let resp = call_framed(&mut stream, whoami.clone()).await;
+        assert_eq!(resp["result"], "svc-a");
+
+        // Sessions are per connection
+        let mut other = TcpStream::connect(addr).await.unwrap();
+        let resp = call_framed(&mut other, whoami.clone()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED);
+
+        // Once expired, the client must re-authenticate
+        tokio::time::sleep(tokio::time::Duration::from_millis(400)).await;
+        let resp = call_framed(&mut stream, whoami.clone()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED);
+        assert_eq!(resp["error"]["message"], "Session expired");
+
+        call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "rpc.auth", "params": {"api_key": "test-key-123"}, "id": 3}),
+        )
+        .await;
+        let resp = call_framed(&mut stream, whoami).await;
+        assert_eq!(resp["result"], "svc-a");
+    }
+
+    #[tokio::test]
+    async fn test_tcp_session_ends_when_key_is_revoked() {
+        let addr = "127.0.0.1:14027";
+        let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
+        let keys = auth.key_store().clone();
+        let issued = keys.issue("svc-r", ["read"], None).await.unwrap();
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!(ctx.principal.map(|p| p.id)))
+                })
+                .await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_auth(auth)
+                .with_sessions(std::time::Duration::from_secs(60));
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let whoami = json!({"jsonrpc": "2.0", "method": "whoami", "params": {}, "id": 2});
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "rpc.auth", "params": {"api_key": issued.key}, "id": 1}),
+        )
+        .await;
+        assert_eq!(resp["result"]["principal"], "svc-r");
+        let resp = call_framed(&mut stream, whoami.clone()).await;
+        assert_eq!(resp["result"], "svc-r");
+
+        keys.revoke(&issued.info.id).await.unwrap();
+        let resp = call_framed(&mut stream, whoami.clone()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+        assert_eq!(resp["error"]["message"], "API key revoked");
+
+        // The session is gone, not just refused once
+        let resp = call_framed(&mut stream, whoami).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_jwt_auth() {
+        use base64::Engine;
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+        let addr = "127.0.0.1:14012";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register_with_context("whoami", |params, ctx| async move {
+                    Ok(json!({
+                        "sub": ctx.principal.map(|p| p.id),
+                        "sees_token": params.get("token").is_some(),
+                    }))
+                })
+                .await;
+            let auth = Arc::new(AuthMiddleware::new(AuthStrategy::Jwt(
+                middleware::JwtConfig::hs256("s3cret"),
+            )));
+            let config = transport::tcp::TcpServerConfig::new(addr, server).with_auth(auth);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600;
+        let header = URL_SAFE_NO_PAD.encode(json!({"alg": "HS256"}).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(json!({"sub": "svc-b", "exp": exp}).to_string());
+        let input = format!("{}.{}", header, payload);
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"s3cret");
+        let sig = ring::hmac::sign(&key, input.as_bytes());
+        let token = format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig.as_ref()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "whoami", "params": {"token": token}, "id": 1}),
+        )
+        .await;
+        assert_eq!(resp["result"], json!({"sub": "svc-b", "sees_token": false}));
+
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "whoami", "params": {"token": "x.y.z"}, "id": 2}),
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_signed_envelope() {
+        use dice_rpc::middleware::{HmacConfig, SignedEnvelope};
+
+        let addr = "127.0.0.1:14013";
+
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let hmac = HmacConfig::new().with_key("ledger", "tcp-secret", ["write"]);
+            let auth = Arc::new(AuthMiddleware::new(AuthStrategy::HmacSigned(hmac)));
+            let config = transport::tcp::TcpServerConfig::new(addr, server).with_auth(auth);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let payload = json!({"jsonrpc": "2.0", "method": "ping", "id": 1}).to_string();
+        let envelope = serde_json::to_value(SignedEnvelope::sign(
+            "ledger",
+            b"tcp-secret",
+            payload.clone(),
+        ))
+        .unwrap();
+
+        let resp = call_framed(&mut stream, envelope.clone()).await;
+        assert_eq!(resp["result"], "pong");
+
+        // The same frame again is a replay
+        let resp = call_framed(&mut stream, envelope).await;
+        assert_eq!(resp["error"]["data"]["reason"], "replayed_nonce");
+
+        // Unsigned requests never reach the handler
+        let resp = call_framed(&mut stream, serde_json::from_str(&payload).unwrap()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED);
+
+        let forged =
+            serde_json::to_value(SignedEnvelope::sign("ledger", b"wrong", payload)).unwrap();
+        let resp = call_framed(&mut stream, forged).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_ip_filter() {
+        use dice_rpc::middleware::{IpFilter, IpRules};
+
+        let addr = "127.0.0.1:14014";
+        let metrics = Arc::new(Metrics::new());
+        let rules = IpRules::new().restrict(["set_*"], ["10.0.0.0/8"]).unwrap();
+        let filter = Arc::new(IpFilter::new(rules));
+
+        let (server_metrics, server_filter) = (metrics.clone(), filter.clone());
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            server
+                .register("set_limit", |_params| async move { Ok(json!("set")) })
+                .await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_metrics(server_metrics)
+                .with_ip_filter(server_filter);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "ping", "id": 1}),
+        )
+        .await;
+        assert_eq!(resp["result"], "pong");
+        let resp = call_framed(
+            &mut stream,
+            json!({"jsonrpc": "2.0", "method": "set_limit", "id": 2}),
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], middleware::IP_DENIED);
+
+        // Once loopback is denied, new connections are dropped unanswered
+        filter.replace(IpRules::new().deny(["127.0.0.0/8"]).unwrap());
+        let mut refused = TcpStream::connect(addr).await.unwrap();
+        let req =
+            serde_json::to_vec(&json!({"jsonrpc": "2.0", "method": "ping", "id": 3})).unwrap();
+        let _ = transport::FrameCodec::write_frame(&mut refused, &req).await;
+        assert!(
+            transport::FrameCodec::read_frame(&mut refused)
+                .await
+                .is_err()
+        );
+        assert_eq!(metrics.snapshot().await.total_rejected_connections, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_framed_pipelining() {
+        use dice_rpc::transport::FrameCodec;
+        use std::time::Duration;
+
+        async fn sleepy_server() -> Arc<RpcServer> {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            server
+                .register("sleep", |params| async move {
+                    let ms = params["ms"].as_u64().unwrap_or(0);
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(json!(ms))
+                })
+                .await;
+            server
+        }
+
+        async fn reply_ids(addr: &str) -> Vec<serde_json::Value> {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let slow = json!({"jsonrpc": "2.0", "method": "sleep", "params": {"ms": 300}, "id": 1});
+            let fast = json!({"jsonrpc": "2.0", "method": "ping", "id": 2});
+            for req in [slow, fast] {
+                FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&req).unwrap())
+                    .await
+                    .unwrap();
+            }
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+                let resp: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+                ids.push(resp["id"].clone());
+            }
+            ids
+        }
+
+        let (concurrent, serial) = ("127.0.0.1:14019", "127.0.0.1:14020");
+        tokio::spawn(async move {
+            let config = transport::tcp::TcpServerConfig::new(concurrent, sleepy_server().await);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::spawn(async move {
+            let config = transport::tcp::TcpServerConfig::new(serial, sleepy_server().await)
+                .with_max_in_flight(1);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        // The slow request no longer holds up the one behind it
+        assert_eq!(reply_ids(concurrent).await, vec![json!(2), json!(1)]);
+        assert_eq!(reply_ids(serial).await, vec![json!(1), json!(2)]);
+
+        // A client that closes its write side still gets its answers
+        let mut stream = TcpStream::connect(concurrent).await.unwrap();
+        let slow = json!({"jsonrpc": "2.0", "method": "sleep", "params": {"ms": 100}, "id": 3});
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&slow).unwrap())
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(resp["result"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_framing_v2() {
+        use dice_rpc::transport::framing::{PROTOCOL_V1, PROTOCOL_V2};
+        use dice_rpc::transport::{Frame, FrameCodec, FrameFlags, MessageType};
+
+        let addr = "127.0.0.1:14021";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(
+            FrameCodec::negotiate(&mut stream).await.unwrap(),
+            PROTOCOL_V2
+        );
+
+        // Responses come back on the stream id of their request
+        for stream_id in [7, 8] {
+            let req = json!({"jsonrpc": "2.0", "method": "ping", "id": stream_id});
+            let frame = Frame::new(MessageType::Request, stream_id, req.to_string());
+            FrameCodec::write_frame_v2(&mut stream, &frame)
+                .await
+                .unwrap();
+        }
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            let frame = FrameCodec::read_frame_v2(&mut stream).await.unwrap();
+            assert_eq!(frame.kind, MessageType::Response);
+            let resp: serde_json::Value = serde_json::from_slice(&frame.payload).unwrap();
+            assert_eq!(resp["id"], frame.stream_id);
+            assert_eq!(resp["result"], "pong");
+            seen.push(frame.stream_id);
+        }
+        seen.sort();
+        assert_eq!(seen, vec![7, 8]);
+
+        let ping = Frame::new(MessageType::Ping, 0, b"tick".to_vec());
+        FrameCodec::write_frame_v2(&mut stream, &ping)
+            .await
+            .unwrap();
+        let pong = FrameCodec::read_frame_v2(&mut stream).await.unwrap();
+        assert_eq!(pong, Frame::new(MessageType::Pong, 0, b"tick".to_vec()));
+
+        // Nothing decompresses payloads yet
+        let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 9});
+        let frame = Frame::new(MessageType::Request, 9, req.to_string())
+            .with_flags(FrameFlags::default().with_compressed(true));
+        FrameCodec::write_frame_v2(&mut stream, &frame)
+            .await
+            .unwrap();
+        let reply = FrameCodec::read_frame_v2(&mut stream).await.unwrap();
+        assert_eq!(reply.stream_id, 9);
+        let resp: serde_json::Value = serde_json::from_slice(&reply.payload).unwrap();
+        assert_eq!(resp["error"]["code"], -32600);
+
+        // A v2 notification frame gets no reply even when the entry has an id
+        let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 10});
+        let frame = Frame::new(MessageType::Notification, 10, req.to_string());
+        FrameCodec::write_frame_v2(&mut stream, &frame)
+            .await
+            .unwrap();
+        let goaway = Frame::new(MessageType::GoAway, 0, Vec::new());
+        FrameCodec::write_frame_v2(&mut stream, &goaway)
+            .await
+            .unwrap();
+        let mut rest = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut rest)
+            .await
+            .unwrap();
+        assert!(rest.is_empty());
+
+        // Plain v1 clients, and v1-only offers, are served as before
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let hello =
+            json!({"jsonrpc": "2.0", "method": "rpc.hello", "params": {"versions": [1]}, "id": 0});
+        FrameCodec::write_frame(&mut stream, hello.to_string().as_bytes())
+            .await
+            .unwrap();
+        let resp: serde_json::Value =
+            serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap()).unwrap();
+        assert_eq!(resp["result"]["version"], PROTOCOL_V1);
+        let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        FrameCodec::write_frame(&mut stream, req.to_string().as_bytes())
+            .await
+            .unwrap();
+        let resp: serde_json::Value =
+            serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap()).unwrap();
+        assert_eq!(resp["result"], "pong");
+
+        // Only the first frame can negotiate
+        FrameCodec::write_frame(&mut stream, hello.to_string().as_bytes())
+            .await
+            .unwrap();
+        let resp: serde_json::Value =
+            serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap()).unwrap();
+        assert_eq!(resp["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connection_limits() {
+        use dice_rpc::transport::FrameCodec;
+        use dice_rpc::transport::tcp::CONNECTION_CLOSED;
+
+        async fn ping(stream: &mut TcpStream) -> serde_json::Value {
+            let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+            FrameCodec::write_frame(stream, req.to_string().as_bytes())
+                .await
+                .unwrap();
+            serde_json::from_slice(&FrameCodec::read_frame(stream).await.unwrap()).unwrap()
+        }
+
+        /// The refusal frame, then the connection closes
+        async fn refused(addr: &str) -> serde_json::Value {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let resp: serde_json::Value =
+                serde_json::from_slice(&FrameCodec::read_frame(&mut stream).await.unwrap())
+                    .unwrap();
+            assert_eq!(resp["error"]["code"], CONNECTION_CLOSED);
+            assert!(FrameCodec::read_frame(&mut stream).await.is_err());
+            resp["error"]["data"]["reason"].clone()
+        }
+
+        let metrics = Arc::new(server::metrics::Metrics::new());
+        let (per_ip, total) = ("127.0.0.1:14022", "127.0.0.1:14023");
+        for (addr, per_ip_limit) in [(per_ip, true), (total, false)] {
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let server = Arc::new(RpcServer::new());
+                rpc::register_default_handlers(&server).await;
+                let config =
+                    transport::tcp::TcpServerConfig::new(addr, server).with_metrics(metrics);
+                let config = if per_ip_limit {
+                    config.with_max_connections_per_ip(1)
+                } else {
+                    config.with_max_connections(1)
+                };
+                let _ = transport::tcp::run_with_framing(config).await;
+            });
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        for (addr, reason) in [
+            (per_ip, "max_connections_per_ip"),
+            (total, "max_connections"),
+        ] {
+            let mut first = TcpStream::connect(addr).await.unwrap();
+            assert_eq!(ping(&mut first).await["result"], "pong");
+            assert_eq!(refused(addr).await, reason);
+
+            // Closing the first connection frees its slot
+            drop(first);
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            let mut next = TcpStream::connect(addr).await.unwrap();
+            assert_eq!(ping(&mut next).await["result"], "pong");
+        }
+        assert_eq!(metrics.snapshot().await.total_rejected_connections, 2);
+
+        // A v2 client learns of the refusal from its handshake
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        let mut held = TcpStream::connect(total).await.unwrap();
+        assert_eq!(ping(&mut held).await["result"], "pong");
+        let mut stream = TcpStream::connect(total).await.unwrap();
+        let err = FrameCodec::negotiate(&mut stream).await.unwrap_err();
+        assert!(err.to_string().contains("max_connections"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_timeouts() {
+        use dice_rpc::transport::tcp::CONNECTION_CLOSED;
+        use dice_rpc::transport::{FrameCodec, MessageType};
+        use std::time::Duration;
+
+        let metrics = Arc::new(server::metrics::Metrics::new());
+        let addr = "127.0.0.1:14024";
+        let server_metrics = metrics.clone();
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_metrics(server_metrics)
+                .with_read_timeout(Duration::from_millis(200))
+                .with_idle_timeout(Duration::from_millis(400));
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let closing_reason = |frame: &[u8]| {
+            let resp: serde_json::Value = serde_json::from_slice(frame).unwrap();
+            assert_eq!(resp["error"]["code"], CONNECTION_CLOSED);
+            resp["error"]["data"]["reason"].clone()
+        };
+
+        // Half a length prefix no longer holds the connection open
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&[0, 0]).await.unwrap();
+        let started = std::time::Instant::now();
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        assert_eq!(closing_reason(&frame), "read_timeout");
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(FrameCodec::read_frame(&mut stream).await.is_err());
+
+        // Quiet connections are reaped after the idle timeout
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        FrameCodec::write_frame(&mut stream, req.to_string().as_bytes())
+            .await
+            .unwrap();
+        FrameCodec::read_frame(&mut stream).await.unwrap();
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        assert_eq!(closing_reason(&frame), "idle_timeout");
+
+        // On v2 the last frame is a goaway
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        FrameCodec::negotiate(&mut stream).await.unwrap();
+        let frame = FrameCodec::read_frame_v2(&mut stream).await.unwrap();
+        assert_eq!(frame.kind, MessageType::GoAway);
+        assert_eq!(closing_reason(&frame.payload), "idle_timeout");
+
+        assert_eq!(metrics.snapshot().await.total_timed_out_connections, 3);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_oversized_frame_drains_running_requests() {
+        use dice_rpc::transport::FrameCodec;
+        use dice_rpc::transport::tcp::CONNECTION_CLOSED;
+        use std::time::Duration;
+
+        let addr = "127.0.0.1:14028";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register("slow", |_params| async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok(json!("done"))
+                })
+                .await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_max_frame_length(1024);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // A frame over the limit stops reading, but the running request
+        // still gets its answer before the connection closes
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let req = json!({"jsonrpc": "2.0", "method": "slow", "id": 1});
+        FrameCodec::write_frame(&mut stream, req.to_string().as_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&4096u32.to_be_bytes()).await.unwrap();
+
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(resp["result"], "done");
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(resp["error"]["code"], CONNECTION_CLOSED);
+        assert_eq!(resp["error"]["data"]["reason"], "frame_too_large");
+        assert!(FrameCodec::read_frame(&mut stream).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_write_timeout() {
+        use dice_rpc::transport::FrameCodec;
+        use std::time::Duration;
+
+        let metrics = Arc::new(server::metrics::Metrics::new());
+        let addr = "127.0.0.1:14025";
+        let server_metrics = metrics.clone();
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            server
+                .register(
+                    "blob",
+                    |_params| async move { Ok(json!("x".repeat(2_000_000))) },
+                )
+                .await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_metrics(server_metrics)
+                .with_write_timeout(Duration::from_millis(200));
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Ask for far more than the socket buffers hold, and never read it
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        for id in 0..20 {
+            let req = json!({"jsonrpc": "2.0", "method": "blob", "id": id});
+            FrameCodec::write_frame(&mut stream, req.to_string().as_bytes())
+                .await
+                .unwrap();
+        }
+        let mut timed_out = 0;
+        for _ in 0..50 {
+            timed_out = metrics.snapshot().await.total_timed_out_connections;
+            if timed_out > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(timed_out, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_rate_limit_before_auth() {
+        let addr = "127.0.0.1:14026";
+        let metrics = Arc::new(Metrics::new());
+        let limiter = Arc::new(
+            middleware::RateLimiter::new()
+                .per_ip(middleware::RateLimit::per_minute(3))
+                .with_metrics(metrics.clone()),
+        );
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let auth = Arc::new(AuthMiddleware::new(AuthStrategy::ApiKeyInParams));
+            auth.add_key("test-key-123").await;
+            let config = transport::tcp::TcpServerConfig::new(addr, server)
+                .with_auth(auth)
+                .with_sessions(std::time::Duration::from_secs(60))
+                .with_rate_limit(limiter);
+            let _ = transport::tcp::run_with_framing(config).await;
+        });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let bad_key = |id: u64| json!({"jsonrpc": "2.0", "method": "ping", "params": {"api_key": "guess"}, "id": id});
+        let bad_login = json!({"jsonrpc": "2.0", "method": "rpc.auth", "params": {"api_key": "guess"}, "id": 3});
+
+        // Bad keys and failed logins spend the peer's budget...
+        for id in 1..=2 {
+            let resp = call_framed(&mut stream, bad_key(id)).await;
+            assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+        }
+        let resp = call_framed(&mut stream, bad_login.clone()).await;
+        assert_eq!(resp["error"]["code"], middleware::AUTH_ERROR);
+
+        // ...until further guesses are refused without being checked, on
+        // this connection or a new one
+        let resp = call_framed(&mut stream, bad_login).await;
+        assert_eq!(resp["error"]["code"], middleware::RATE_LIMITED);
+        assert_eq!(resp["error"]["data"]["limit"], "ip");
+        let mut other = TcpStream::connect(addr).await.unwrap();
+        let good = json!({"jsonrpc": "2.0", "method": "ping", "params": {"api_key": "test-key-123"}, "id": 5});
+        let resp = call_framed(&mut other, good).await;
+        assert_eq!(resp["error"]["code"], middleware::RATE_LIMITED);
+
+        assert_eq!(
+            metrics.snapshot().await.rate_limited_counts.get("ip"),
+            Some(&2)
+        );
+    }
+}
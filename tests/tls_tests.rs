@@ -0,0 +1,331 @@
+#[cfg(all(feature = "tcp", feature = "http"))]
+mod tls_tests {
+    use dice_rpc::middleware::{
+        AuthMiddleware, AuthStrategy, CertIdentity, CertNames, ClientCertConfig, PeerCertificate,
+    };
+    use dice_rpc::transport::{FrameCodec, TlsClientConfig, TlsConfig};
+    use dice_rpc::*;
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+        KeyPair, SanType,
+    };
+    use serde_json::{Value, json};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    /// A throwaway CA that signs server certificates
+    struct TestCa {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    impl TestCa {
+        fn new() -> Self {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(DnType::CommonName, "dice_rpc test CA");
+            let cert = params.self_signed(&key).unwrap();
+            Self { cert, key }
+        }
+
+        fn client(&self) -> TlsClientConfig {
+            TlsClientConfig::new()
+                .with_ca_pem(self.cert.pem().as_bytes())
+                .unwrap()
+        }
+
+        /// Sign a certificate for `name` and write it and its key under
+        /// `file`; returns the two paths
+        fn issue(&self, name: &str, file: &str) -> (PathBuf, PathBuf) {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, name);
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+
+            let dir = std::env::temp_dir();
+            let prefix = format!("dice_rpc_tls_{}_{}", file, std::process::id());
+            let cert_path = dir.join(format!("{}.crt", prefix));
+            let key_path = dir.join(format!("{}.key", prefix));
+            std::fs::write(&cert_path, cert.pem()).unwrap();
+            std::fs::write(&key_path, key.serialize_pem()).unwrap();
+            (cert_path, key_path)
+        }
+    }
+
+    impl TestCa {
+        /// A client certificate for `common_name` with a SPIFFE id, as PEM
+        /// (certificate, PKCS#8 key)
+        fn client_identity(&self, common_name: &str, spiffe_id: &str) -> (String, String) {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::default();
+            params
+                .distinguished_name
+                .push(DnType::CommonName, common_name);
+            params.subject_alt_names = vec![SanType::URI(spiffe_id.try_into().unwrap())];
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        }
+
+        fn client_with_identity(&self, common_name: &str, spiffe_id: &str) -> TlsClientConfig {
+            let (cert, key) = self.client_identity(common_name, spiffe_id);
+            self.client()
+                .with_client_cert_pem(cert.as_bytes(), key.as_bytes())
+                .unwrap()
+        }
+
+        fn client_cert_auth(&self) -> Arc<AuthMiddleware> {
+            let certs = ClientCertConfig::from_ca_pem(self.cert.pem().as_bytes())
+                .unwrap()
+                .with_identity(CertIdentity::Uri)
+                .with_roles("spiffe://test/billing", ["write"])
+                .with_roles("spiffe://test/*", ["read"]);
+            Arc::new(AuthMiddleware::new(AuthStrategy::ClientCert(certs)))
+        }
+    }
+
+    async fn whoami_server() -> Arc<RpcServer> {
+        let server = Arc::new(RpcServer::new());
+        rpc::register_default_handlers(&server).await;
+        server
+            .register_with_context("whoami", |_params, ctx| async move {
+                let principal = ctx.principal.unwrap();
+                Ok(json!({"id": principal.id, "scopes": principal.scopes}))
+            })
+            .await;
+        server
+    }
+
+    async fn spawn_tcp(addr: &'static str, tls: TlsConfig) {
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let config = transport::TcpServerConfig::new(addr, server).with_tls(tls);
+            let _ = transport::run_with_framing(config).await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
+
+    /// Ping over a fresh TLS connection that verifies `server_name`
+    async fn ping(
+        client: &TlsClientConfig,
+        addr: &str,
+        server_name: &str,
+    ) -> anyhow::Result<Value> {
+        let mut stream = client.connect(addr, server_name).await?;
+        let req = json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&req)?).await?;
+        let frame = FrameCodec::read_frame(&mut stream).await?;
+        Ok(serde_json::from_slice(&frame)?)
+    }
+
+    #[tokio::test]
+    async fn test_tcp_tls_with_sni() {
+        let addr = "127.0.0.1:14015";
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue("localhost", "sni_default");
+        let (admin_cert, admin_key) = ca.issue("admin.local", "sni_admin");
+        let original = TlsConfig::from_pem_files(cert, key).unwrap();
+        let tls = original
+            .clone()
+            .with_sni_cert("admin.local", admin_cert, admin_key)
+            .unwrap();
+        spawn_tcp(addr, tls).await;
+        // Clones share the SNI names, so reloading any of them keeps them
+        original.reload().unwrap();
+
+        let client = ca.client();
+        let resp = ping(&client, addr, "localhost").await.unwrap();
+        assert_eq!(resp["result"], "pong");
+
+        // Only the SNI certificate is valid for this name
+        let resp = ping(&client, addr, "admin.local").await.unwrap();
+        assert_eq!(resp["result"], "pong");
+
+        // Names with no certificate get the default one, which fails to verify
+        assert!(ping(&client, addr, "other.local").await.is_err());
+
+        // A client that does not trust the CA refuses the server
+        let stranger = TestCa::new().client();
+        assert!(ping(&stranger, addr, "localhost").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_reload() {
+        let addr = "127.0.0.1:14016";
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue("localhost", "reload");
+        let tls = TlsConfig::from_pem_files(&cert, &key).unwrap();
+        spawn_tcp(addr, tls.clone()).await;
+
+        let client = ca.client();
+        assert!(ping(&client, addr, "localhost").await.is_ok());
+        assert!(ping(&client, addr, "renewed.local").await.is_err());
+
+        // A broken file is rejected and the current certificate stays
+        std::fs::write(&cert, "not a certificate").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(ping(&client, addr, "localhost").await.is_ok());
+
+        // The clone held by the server picks up the renewed files
+        let (renewed_cert, renewed_key) = ca.issue("renewed.local", "reload_renewed");
+        std::fs::copy(&renewed_cert, &cert).unwrap();
+        std::fs::copy(&renewed_key, &key).unwrap();
+        tls.reload().unwrap();
+        assert!(ping(&client, addr, "renewed.local").await.is_ok());
+        assert!(ping(&client, addr, "localhost").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_https_transport() {
+        let addr = "127.0.0.1:13008";
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue("localhost", "https");
+        let tls = TlsConfig::from_pem_files(cert, key).unwrap();
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            let _ = transport::HttpTransport::new(server)
+                .with_tls(tls)
+                .serve(addr)
+                .await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let client = ca.client();
+        let resp: Value = client
+            .http_client()
+            .unwrap()
+            .post("https://localhost:13008/rpc")
+            .json(&json!({"jsonrpc": "2.0", "method": "ping", "id": 1}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp["result"], "pong");
+
+        // Plain HTTP is not spoken on a TLS port
+        let plain = reqwest::Client::new()
+            .post("http://localhost:13008/rpc")
+            .json(&json!({"jsonrpc": "2.0", "method": "ping", "id": 1}))
+            .send()
+            .await;
+        assert!(plain.is_err());
+
+        // HTTP/2 is offered by default
+        let stream = client
+            .with_alpn(["h2", "http/1.1"])
+            .connect(addr, "localhost")
+            .await
+            .unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_cert_names() {
+        use rustls::pki_types::CertificateDer;
+        use rustls::pki_types::pem::PemObject;
+
+        let ca = TestCa::new();
+        let (cert, _) = ca.client_identity("billing", "spiffe://test/billing");
+        let der = CertificateDer::from_pem_slice(cert.as_bytes()).unwrap();
+        let names = CertNames::from_der(der.as_ref()).unwrap();
+        assert_eq!(names.common_name.as_deref(), Some("billing"));
+        assert_eq!(names.uris, ["spiffe://test/billing"]);
+        assert!(names.dns.is_empty());
+        assert!(CertNames::from_der(b"not a certificate").is_none());
+
+        let certs = ClientCertConfig::from_ca_pem(ca.cert.pem().as_bytes()).unwrap();
+        let principal = certs.principal(&PeerCertificate(der.clone())).unwrap();
+        assert_eq!(principal.id, "billing");
+        assert_eq!(principal.scheme, "client_cert");
+        let err = certs
+            .with_identity(CertIdentity::Dns)
+            .principal(&PeerCertificate(der))
+            .unwrap_err();
+        assert_eq!(err.code, middleware::AUTH_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_client_cert_auth() {
+        let addr = "127.0.0.1:14017";
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue("localhost", "mtls_tcp");
+        let tls = TlsConfig::from_pem_files(cert, key).unwrap();
+        let auth = ca.client_cert_auth();
+        tokio::spawn(async move {
+            let config = transport::TcpServerConfig::new(addr, whoami_server().await)
+                .with_tls(tls)
+                .with_auth(auth);
+            let _ = transport::run_with_framing(config).await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let client = ca.client_with_identity("billing", "spiffe://test/billing");
+        let mut stream = client.connect(addr, "localhost").await.unwrap();
+        let req = json!({"jsonrpc": "2.0", "method": "whoami", "id": 1});
+        FrameCodec::write_frame(&mut stream, &serde_json::to_vec(&req).unwrap())
+            .await
+            .unwrap();
+        let frame = FrameCodec::read_frame(&mut stream).await.unwrap();
+        let resp: Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(resp["result"]["id"], "spiffe://test/billing");
+        assert_eq!(resp["result"]["scopes"], json!(["write", "read"]));
+
+        // No certificate, or one from another CA: the server refuses
+        assert!(ping(&ca.client(), addr, "localhost").await.is_err());
+        let (cert, key) = TestCa::new().client_identity("billing", "spiffe://test/billing");
+        let forged = ca
+            .client()
+            .with_client_cert_pem(cert.as_bytes(), key.as_bytes())
+            .unwrap();
+        assert!(ping(&forged, addr, "localhost").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_https_client_cert_auth() {
+        let addr = "127.0.0.1:13009";
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue("localhost", "mtls_https");
+        let tls = TlsConfig::from_pem_files(cert, key).unwrap();
+        let auth = ca.client_cert_auth();
+        tokio::spawn(async move {
+            let _ = transport::HttpTransport::new(whoami_server().await)
+                .with_tls(tls)
+                .with_auth(auth)
+                .serve(addr)
+                .await;
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let http = ca
+            .client_with_identity("reports", "spiffe://test/reports")
+            .http_client()
+            .unwrap();
+        let resp: Value = http
+            .post("https://localhost:13009/rpc")
+            .json(&json!({"jsonrpc": "2.0", "method": "whoami", "id": 1}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(resp["result"]["id"], "spiffe://test/reports");
+        assert_eq!(resp["result"]["scopes"], json!(["read"]));
+
+        let anonymous = ca.client().http_client().unwrap();
+        let resp = anonymous
+            .post("https://localhost:13009/rpc")
+            .json(&json!({"jsonrpc": "2.0", "method": "ping", "id": 1}))
+            .send()
+            .await;
+        assert!(resp.is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use dice_rpc::rpc::{INVALID_PARAMS, RpcRequest, RpcServer};
+use dice_rpc::state::StateStore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct AddParams {
+    a: i64,
+    b: i64,
+    #[serde(default)]
+    scale: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct AddResult {
+    sum: i64,
+}
+
+async fn add(p: AddParams) -> Result<AddResult, dice_rpc::RpcError> {
+    Ok(AddResult {
+        sum: (p.a + p.b) * p.scale.unwrap_or(1),
+    })
+}
+
+fn request(method: &str, params: Value) -> RpcRequest {
+    RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+        id: Some(json!(1)),
+    }
+}
+
+#[tokio::test]
+async fn test_typed_by_name_params() {
+    let server = RpcServer::new();
+    server.register_typed("add", add).await;
+
+    let resp = server
+        .handle_request(request("add", json!({"a": 2, "b": 3})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!({"sum": 5})));
+}
+
+#[tokio::test]
+async fn test_typed_by_position_params() {
+    let server = RpcServer::new();
+    server.register_typed("add", add).await;
+
+    let resp = server
+        .handle_request(request("add", json!([2, 3, 10])))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!({"sum": 50})));
+}
+
+#[tokio::test]
+async fn test_typed_invalid_params_reports_path() {
+    let server = RpcServer::new();
+    server.register_typed("add", add).await;
+
+    let resp = server
+        .handle_request(request("add", json!({"a": 2, "b": "three"})))
+        .await
+        .unwrap();
+    let err = resp.error.unwrap();
+    assert_eq!(err.code, INVALID_PARAMS);
+    assert_eq!(err.data.unwrap()["path"], "b");
+
+    let resp = server
+        .handle_request(request("add", json!({"a": 2})))
+        .await
+        .unwrap();
+    assert_eq!(resp.error.unwrap().code, INVALID_PARAMS);
+}
+
+#[tokio::test]
+async fn test_typed_omitted_params() {
+    let server = RpcServer::new();
+    server
+        .register_typed("unit", |_: ()| async move { Ok("done") })
+        .await;
+
+    let resp = server
+        .handle_request(request("unit", Value::Null))
+        .await
+        .unwrap();
+    assert_eq!(resp.result, Some(json!("done")));
+}
+
+#[tokio::test]
+async fn test_stateful_handlers_accept_positional_params() {
+    let server = RpcServer::new();
+    let state = Arc::new(StateStore::new());
+    state.set_balance("0xAlice", 1000).await;
+    dice_rpc::server::handlers::register_stateful_handlers(&server, state.clone()).await;
+
+    let resp = server
+        .handle_request(request("transfer", json!(["0xAlice", "0xBob", 250])))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none());
+    assert_eq!(state.get_balance("0xBob").await, Some(250));
+
+    let resp = server
+        .handle_request(request("get_balance", json!({"address": "0xAlice"})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["balance"], "750");
+}
@@ -0,0 +1,167 @@
+#[cfg(all(feature = "tcp", unix))]
+mod unix_tests {
+    use dice_rpc::middleware::{PeerCredConfig, PeerCredentials};
+    use dice_rpc::transport::{UnixServerConfig, run_unix, unix};
+    use dice_rpc::*;
+    use serde_json::json;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn temp_socket(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("dice_rpc_{}_{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    async fn whoami_server() -> Arc<RpcServer> {
+        let server = Arc::new(RpcServer::new());
+        rpc::register_default_handlers(&server).await;
+        server
+            .register_with_context("whoami", |_params, ctx| async move {
+                let principal = ctx.principal.unwrap();
+                Ok(json!({
+                    "id": principal.id,
+                    "scopes": principal.scopes,
+                    "transport": format!("{:?}", ctx.transport),
+                }))
+            })
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_with_peer_credentials() {
+        let path = temp_socket("peercred");
+        // A socket file left behind by a server that is gone
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        // Files we create belong to our uid
+        let uid = std::fs::symlink_metadata(&path).unwrap().uid();
+
+        let creds = PeerCredConfig::new().with_roles(uid, ["admin"]);
+        let auth = AuthMiddleware::new(AuthStrategy::PeerCred(creds));
+        let config = UnixServerConfig::new(&path, whoami_server().await)
+            .with_auth(Arc::new(auth))
+            .with_mode(0o600);
+        tokio::spawn(run_unix(config));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let resp = unix::call(
+            &path,
+            &json!({"jsonrpc": "2.0", "method": "whoami", "id": 1}),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(resp["result"]["id"], format!("uid:{}", uid));
+        assert_eq!(resp["result"]["scopes"], json!(["admin"]));
+        assert_eq!(resp["result"]["transport"], "Unix");
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "ping", "id": 1},
+            {"jsonrpc": "2.0", "method": "ping", "id": 2}
+        ]);
+        let resp = unix::call(&path, &batch).await.unwrap().unwrap();
+        assert_eq!(resp.as_array().unwrap().len(), 2);
+
+        // A batch of notifications gets no reply to wait for
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "ping"},
+            {"jsonrpc": "2.0", "method": "ping"}
+        ]);
+        let resp = tokio::time::timeout(Duration::from_secs(1), unix::call(&path, &batch))
+            .await
+            .expect("call waited for a reply")
+            .unwrap();
+        assert!(resp.is_none());
+
+        // A live socket is not replaced
+        let err = run_unix(UnixServerConfig::new(&path, whoami_server().await))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("in use"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_unix_negotiates_v2_with_auth() {
+        use dice_rpc::transport::framing::PROTOCOL_V2;
+        use dice_rpc::transport::{Frame, FrameCodec, MessageType};
+
+        let path = temp_socket("hello_auth");
+        let auth = AuthMiddleware::new(AuthStrategy::ApiKeyInParams);
+        auth.add_key("unix-key").await;
+        let config = UnixServerConfig::new(&path, whoami_server().await).with_auth(Arc::new(auth));
+        tokio::spawn(run_unix(config));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The hello is answered before auth, and requests still need a key
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        assert_eq!(
+            FrameCodec::negotiate(&mut stream).await.unwrap(),
+            PROTOCOL_V2
+        );
+        for (stream_id, params) in [(1, json!({})), (2, json!({"api_key": "unix-key"}))] {
+            let req =
+                json!({"jsonrpc": "2.0", "method": "ping", "params": params, "id": stream_id});
+            let frame = Frame::new(MessageType::Request, stream_id, req.to_string());
+            FrameCodec::write_frame_v2(&mut stream, &frame)
+                .await
+                .unwrap();
+            let reply = FrameCodec::read_frame_v2(&mut stream).await.unwrap();
+            assert_eq!(reply.stream_id, stream_id);
+            let resp: serde_json::Value = serde_json::from_slice(&reply.payload).unwrap();
+            match stream_id {
+                1 => assert_eq!(resp["error"]["code"], middleware::AUTH_REQUIRED),
+                _ => assert_eq!(resp["result"], "pong"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unix_refuses_non_socket_path() {
+        let path = temp_socket("regular");
+        std::fs::write(&path, "not a socket").unwrap();
+        let err = run_unix(UnixServerConfig::new(&path, whoami_server().await))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not a socket"), "{}", err);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_peer_cred_principal() {
+        let peer = PeerCredentials {
+            uid: 1001,
+            gid: 1001,
+            pid: Some(42),
+        };
+        let creds = PeerCredConfig::new()
+            .with_roles(1001, ["read"])
+            .with_roles(1001, ["read", "write"]);
+        let principal = creds.principal(&peer).unwrap();
+        assert_eq!(principal.id, "uid:1001");
+        assert_eq!(principal.scheme, "peer_cred");
+        assert_eq!(principal.scopes, vec!["read", "write"]);
+
+        assert!(
+            PeerCredConfig::new()
+                .principal(&peer)
+                .unwrap()
+                .scopes
+                .is_empty()
+        );
+        let err = PeerCredConfig::new()
+            .with_roles(0, ["admin"])
+            .known_uids_only()
+            .principal(&peer)
+            .unwrap_err();
+        assert_eq!(err.code, middleware::AUTH_ERROR);
+        assert_eq!(err.data.unwrap()["reason"], "unknown_uid");
+    }
+}
@@ -0,0 +1,149 @@
+#[cfg(feature = "http")]
+mod websocket_tests {
+    use dice_rpc::server::metrics::Metrics;
+    use dice_rpc::*;
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    /// The next text or binary message, skipping control frames
+    async fn next_reply(socket: &mut Socket) -> Message {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Ping(_) | Message::Pong(_) => continue,
+                message => return message,
+            }
+        }
+    }
+
+    fn json_of(message: &Message) -> Value {
+        serde_json::from_slice(&message.clone().into_data()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ws_concurrent_requests() {
+        let addr = "127.0.0.1:13010";
+        let metrics = Arc::new(Metrics::new());
+        let server_metrics = metrics.clone();
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            server
+                .register("sleep", |params| async move {
+                    let ms = params["ms"].as_u64().unwrap_or(0);
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(json!(ms))
+                })
+                .await;
+            let _ = transport::HttpTransport::new(server)
+                .with_metrics(server_metrics)
+                .serve(addr)
+                .await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+
+        // The slow request does not hold up the one behind it
+        let slow = json!({"jsonrpc": "2.0", "method": "sleep", "params": {"ms": 300}, "id": 1});
+        let fast = json!({"jsonrpc": "2.0", "method": "ping", "id": 2});
+        socket.send(Message::text(slow.to_string())).await.unwrap();
+        socket.send(Message::text(fast.to_string())).await.unwrap();
+        let first = next_reply(&mut socket).await;
+        assert!(first.is_text());
+        assert_eq!(json_of(&first)["id"], 2);
+        assert_eq!(json_of(&next_reply(&mut socket).await)["id"], 1);
+
+        // Notifications get no reply; batches and binary frames work too
+        let notify = json!({"jsonrpc": "2.0", "method": "ping"});
+        socket
+            .send(Message::text(notify.to_string()))
+            .await
+            .unwrap();
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "ping", "id": 3},
+            {"jsonrpc": "2.0", "method": "ping", "id": 4}
+        ]);
+        socket
+            .send(Message::binary(serde_json::to_vec(&batch).unwrap()))
+            .await
+            .unwrap();
+        let reply = next_reply(&mut socket).await;
+        assert!(reply.is_binary());
+        assert_eq!(json_of(&reply).as_array().unwrap().len(), 2);
+
+        socket.send(Message::text("{not json")).await.unwrap();
+        let reply = json_of(&next_reply(&mut socket).await);
+        assert_eq!(reply["error"]["code"], -32700);
+
+        assert_eq!(metrics.snapshot().await.total_requests, 5);
+    }
+
+    #[tokio::test]
+    async fn test_ws_header_auth_and_keepalive() {
+        let addr = "127.0.0.1:13011";
+        tokio::spawn(async move {
+            let server = Arc::new(RpcServer::new());
+            rpc::register_default_handlers(&server).await;
+            server
+                .register_with_context("whoami", |_params, ctx| async move {
+                    Ok(json!({
+                        "id": ctx.principal.map(|p| p.id),
+                        "transport": format!("{:?}", ctx.transport),
+                    }))
+                })
+                .await;
+            let auth = middleware::AuthMiddleware::new(middleware::AuthStrategy::ApiKeyInHeader);
+            auth.add_key_with_id("ws-key", "dashboard").await;
+            let _ = transport::HttpTransport::new(server)
+                .with_auth(Arc::new(auth))
+                .with_ws_keepalive(Duration::from_millis(100))
+                .serve(addr)
+                .await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://{}/ws", addr);
+        let err = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("401"), "{}", err);
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer ws-key".parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        let whoami = json!({"jsonrpc": "2.0", "method": "whoami", "id": 1});
+        socket
+            .send(Message::text(whoami.to_string()))
+            .await
+            .unwrap();
+        let reply = json_of(&next_reply(&mut socket).await);
+        assert_eq!(reply["result"]["id"], "dashboard");
+        assert_eq!(reply["result"]["transport"], "WebSocket");
+
+        // The server pings; reading answers each ping, so the connection lives on
+        let mut pings = 0;
+        while pings < 3 {
+            if let Message::Ping(_) = socket.next().await.unwrap().unwrap() {
+                pings += 1;
+            }
+        }
+        socket
+            .send(Message::text(whoami.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(json_of(&next_reply(&mut socket).await)["id"], 1);
+    }
+}